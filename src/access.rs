@@ -0,0 +1,447 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use md5::{Digest, Md5};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::players::is_valid_username;
+use crate::properties::ServerProperties;
+
+/// mojang's username lookup, `{username}` is replaced with the name being looked up
+pub const DEFAULT_PROFILE_LOOKUP: &str =
+    "https://api.mojang.com/users/profiles/minecraft/{username}";
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WhitelistEntry {
+    pub uuid: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpEntry {
+    pub uuid: String,
+    pub name: String,
+    pub level: u8,
+    #[serde(default)]
+    pub bypasses_player_limit: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanEntry {
+    pub uuid: String,
+    pub name: String,
+    pub created: String,
+    pub source: String,
+    pub expires: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpBanEntry {
+    pub ip: String,
+    pub created: String,
+    pub source: String,
+    pub expires: String,
+    pub reason: String,
+}
+
+/// a player as the server identifies them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub uuid: String,
+    pub name: String,
+}
+
+/// the uuid a server in offline mode gives a username
+pub fn offline_uuid(username: &str) -> String {
+    let mut hash: [u8; 16] = Md5::digest(format!("OfflinePlayer:{}", username)).into();
+    // name based uuid, version 3
+    hash[6] = (hash[6] & 0x0f) | 0x30;
+    hash[8] = (hash[8] & 0x3f) | 0x80;
+    hyphenate(&hex::encode(hash))
+}
+
+/// turns the bare 32 character form mojang returns into the usual uuid form
+fn hyphenate(uuid: &str) -> String {
+    format!(
+        "{}-{}-{}-{}-{}",
+        &uuid[0..8],
+        &uuid[8..12],
+        &uuid[12..16],
+        &uuid[16..20],
+        &uuid[20..32]
+    )
+}
+
+#[derive(Deserialize)]
+struct LookupResponse {
+    id: String,
+    name: String,
+}
+
+/// resolves a username the way the server would, through the lookup endpoint in online mode
+pub async fn resolve_profile(
+    username: &str,
+    online_mode: bool,
+    lookup_url: &str,
+) -> Result<Profile, String> {
+    if !is_valid_username(username) {
+        return Err(format!("{} is not a valid username", username));
+    }
+    if !online_mode {
+        return Ok(Profile {
+            uuid: offline_uuid(username),
+            name: username.to_string(),
+        });
+    }
+    let client = reqwest::Client::builder()
+        .timeout(LOOKUP_TIMEOUT)
+        .build()
+        .expect("failed to build http client");
+    let response = client
+        .get(lookup_url.replace("{username}", username))
+        .send()
+        .await
+        .map_err(|err| format!("profile lookup failed: {}", err))?;
+    if !response.status().is_success() {
+        return Err(format!("no player named {}", username));
+    }
+    let found: LookupResponse = response
+        .json()
+        .await
+        .map_err(|err| format!("profile lookup returned garbage: {}", err))?;
+    let uuid = found.id.replace('-', "");
+    if uuid.len() != 32 || !uuid.chars().all(|x| x.is_ascii_hexdigit()) {
+        return Err(format!("profile lookup returned bad uuid {}", found.id));
+    }
+    Ok(Profile {
+        uuid: hyphenate(&uuid.to_lowercase()),
+        name: found.name,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessList {
+    Whitelist,
+    Ops,
+    Bans,
+    IpBans,
+}
+
+impl AccessList {
+    fn file_name(&self) -> &'static str {
+        match self {
+            AccessList::Whitelist => "whitelist.json",
+            AccessList::Ops => "ops.json",
+            AccessList::Bans => "banned-players.json",
+            AccessList::IpBans => "banned-ips.json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessAction {
+    Add,
+    Remove,
+}
+
+/// one edit to one of a server's access lists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessChange {
+    pub list: AccessList,
+    pub action: AccessAction,
+    /// a username, or an address for ip bans
+    pub target: String,
+    /// permission level for new ops, the server's op-permission-level when unset
+    #[serde(default)]
+    pub level: Option<u8>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl AccessChange {
+    fn reason(&self) -> String {
+        self.reason
+            .clone()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .unwrap_or(DEFAULT_BAN_REASON.to_string())
+    }
+    fn validate(&self) -> Result<(), String> {
+        let target = self.target.trim();
+        if self.list == AccessList::IpBans {
+            target
+                .parse::<IpAddr>()
+                .map_err(|_| format!("{} is not an ip address", target))?;
+        } else if !is_valid_username(target) {
+            return Err(format!("{} is not a valid username", target));
+        }
+        if let Some(level) = self.level
+            && !(1..=4).contains(&level)
+        {
+            return Err("op level must be from 1 to 4".to_string());
+        }
+        if self
+            .reason
+            .as_ref()
+            .is_some_and(|x| x.contains(['\n', '\r']))
+        {
+            return Err("reason must be a single line".to_string());
+        }
+        Ok(())
+    }
+    /// the console command making this change on a running server
+    pub fn command(&self, default_op_level: u8) -> Result<String, String> {
+        self.validate()?;
+        let target = self.target.trim();
+        Ok(match (self.list, self.action) {
+            (AccessList::Whitelist, AccessAction::Add) => format!("whitelist add {}", target),
+            (AccessList::Whitelist, AccessAction::Remove) => {
+                format!("whitelist remove {}", target)
+            }
+            (AccessList::Ops, AccessAction::Add) => {
+                if self.level.is_some_and(|x| x != default_op_level) {
+                    return Err(
+                        "ops added while the server runs get op-permission-level, stop it to pick another level"
+                            .to_string(),
+                    );
+                }
+                format!("op {}", target)
+            }
+            (AccessList::Ops, AccessAction::Remove) => format!("deop {}", target),
+            (AccessList::Bans, AccessAction::Add) => format!("ban {} {}", target, self.reason()),
+            (AccessList::Bans, AccessAction::Remove) => format!("pardon {}", target),
+            (AccessList::IpBans, AccessAction::Add) => {
+                format!("ban-ip {} {}", target, self.reason())
+            }
+            (AccessList::IpBans, AccessAction::Remove) => format!("pardon-ip {}", target),
+        })
+    }
+}
+
+/// the json lists a server keeps next to its world
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AccessLists {
+    pub whitelist: Vec<WhitelistEntry>,
+    pub ops: Vec<OpEntry>,
+    pub bans: Vec<BanEntry>,
+    pub ip_bans: Vec<IpBanEntry>,
+}
+
+/// a list that doesn't parse is an error, writing over it would throw away every entry
+async fn load_list<T: DeserializeOwned>(
+    server_id: usize,
+    list: AccessList,
+) -> Result<Vec<T>, String> {
+    match fs::read_to_string(format!("./servers/{}/game/{}", server_id, list.file_name())).await {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|err| format!("invalid {}: {}", list.file_name(), err)),
+        Err(_) => Ok(vec![]),
+    }
+}
+
+async fn write_list<T: Serialize>(
+    server_id: usize,
+    list: AccessList,
+    entries: &[T],
+) -> std::io::Result<()> {
+    fs::create_dir_all(format!("./servers/{}/game", server_id)).await?;
+    fs::write(
+        format!("./servers/{}/game/{}", server_id, list.file_name()),
+        serde_json::to_string_pretty(entries).unwrap(),
+    )
+    .await
+}
+
+/// when an entry was made, in the format the server writes
+fn created() -> String {
+    chrono::Utc::now()
+        .format("%Y-%m-%d %H:%M:%S %z")
+        .to_string()
+}
+
+impl AccessLists {
+    pub async fn load(server_id: usize) -> Result<AccessLists, String> {
+        Ok(AccessLists {
+            whitelist: load_list(server_id, AccessList::Whitelist).await?,
+            ops: load_list(server_id, AccessList::Ops).await?,
+            bans: load_list(server_id, AccessList::Bans).await?,
+            ip_bans: load_list(server_id, AccessList::IpBans).await?,
+        })
+    }
+    /// edits the files of a stopped server, looking up the player's uuid when adding them
+    pub async fn apply_offline(
+        server_id: usize,
+        change: &AccessChange,
+        lookup_url: &str,
+    ) -> Result<(), String> {
+        change.validate()?;
+        let properties = ServerProperties::load(server_id).await;
+        let online_mode = properties.get("online-mode").as_deref() != Some("false");
+        let default_op_level = properties
+            .get("op-permission-level")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(4);
+        let target = change.target.trim();
+        let same = |name: &str| name.eq_ignore_ascii_case(target);
+        let mut lists = AccessLists::load(server_id).await?;
+        let result = match (change.list, change.action) {
+            (AccessList::IpBans, AccessAction::Add) => {
+                lists.ip_bans.retain(|x| x.ip != target);
+                lists.ip_bans.push(IpBanEntry {
+                    ip: target.to_string(),
+                    created: created(),
+                    source: "mcmanage".to_string(),
+                    expires: "forever".to_string(),
+                    reason: change.reason(),
+                });
+                write_list(server_id, change.list, &lists.ip_bans).await
+            }
+            (AccessList::IpBans, AccessAction::Remove) => {
+                lists.ip_bans.retain(|x| x.ip != target);
+                write_list(server_id, change.list, &lists.ip_bans).await
+            }
+            (AccessList::Whitelist, AccessAction::Remove) => {
+                lists.whitelist.retain(|x| !same(&x.name));
+                write_list(server_id, change.list, &lists.whitelist).await
+            }
+            (AccessList::Ops, AccessAction::Remove) => {
+                lists.ops.retain(|x| !same(&x.name));
+                write_list(server_id, change.list, &lists.ops).await
+            }
+            (AccessList::Bans, AccessAction::Remove) => {
+                lists.bans.retain(|x| !same(&x.name));
+                write_list(server_id, change.list, &lists.bans).await
+            }
+            (list, AccessAction::Add) => {
+                let profile = resolve_profile(target, online_mode, lookup_url).await?;
+                match list {
+                    AccessList::Whitelist => {
+                        lists.whitelist.retain(|x| x.uuid != profile.uuid);
+                        lists.whitelist.push(WhitelistEntry {
+                            uuid: profile.uuid,
+                            name: profile.name,
+                        });
+                        write_list(server_id, list, &lists.whitelist).await
+                    }
+                    AccessList::Ops => {
+                        lists.ops.retain(|x| x.uuid != profile.uuid);
+                        lists.ops.push(OpEntry {
+                            uuid: profile.uuid,
+                            name: profile.name,
+                            level: change.level.unwrap_or(default_op_level),
+                            bypasses_player_limit: false,
+                        });
+                        write_list(server_id, list, &lists.ops).await
+                    }
+                    _ => {
+                        lists.bans.retain(|x| x.uuid != profile.uuid);
+                        lists.bans.push(BanEntry {
+                            uuid: profile.uuid,
+                            name: profile.name,
+                            created: created(),
+                            source: "mcmanage".to_string(),
+                            expires: "forever".to_string(),
+                            reason: change.reason(),
+                        });
+                        write_list(server_id, list, &lists.bans).await
+                    }
+                }
+            }
+        };
+        result.map_err(|err| format!("failed to write {}: {}", change.list.file_name(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(list: AccessList, action: AccessAction, target: &str) -> AccessChange {
+        AccessChange {
+            list,
+            action,
+            target: target.to_string(),
+            level: None,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn derives_offline_uuids() {
+        assert_eq!(
+            offline_uuid("Notch"),
+            "b50ad385-829d-3141-a216-7e7d7539ba7f"
+        );
+        assert_eq!(
+            hyphenate("069a79f444e94726a5befca90e38aaf5"),
+            "069a79f4-44e9-4726-a5be-fca90e38aaf5"
+        );
+    }
+
+    #[test]
+    fn builds_console_commands() {
+        use AccessAction::{Add, Remove};
+        use AccessList::{Bans, IpBans, Ops, Whitelist};
+        let cases = [
+            (Whitelist, Add, " Steve ", "whitelist add Steve"),
+            (Whitelist, Remove, "Steve", "whitelist remove Steve"),
+            (Ops, Add, "Steve", "op Steve"),
+            (Ops, Remove, "Steve", "deop Steve"),
+            (Bans, Add, "Steve", "ban Steve Banned by an operator."),
+            (Bans, Remove, "Steve", "pardon Steve"),
+            (
+                IpBans,
+                Add,
+                "10.0.0.1",
+                "ban-ip 10.0.0.1 Banned by an operator.",
+            ),
+            (IpBans, Remove, "10.0.0.1", "pardon-ip 10.0.0.1"),
+        ];
+        for (list, action, target, command) in cases {
+            assert_eq!(
+                change(list, action, target).command(4).as_deref(),
+                Ok(command)
+            );
+        }
+        let mut ban = change(Bans, Add, "Steve");
+        ban.reason = Some(" griefing ".to_string());
+        assert_eq!(ban.command(4).as_deref(), Ok("ban Steve griefing"));
+        let mut op = change(Ops, Add, "Steve");
+        op.level = Some(2);
+        assert!(op.command(4).is_err());
+        assert_eq!(op.command(2).as_deref(), Ok("op Steve"));
+    }
+
+    #[test]
+    fn rejects_bad_changes() {
+        assert!(
+            change(AccessList::Whitelist, AccessAction::Add, "no spaces")
+                .validate()
+                .is_err()
+        );
+        assert!(
+            change(AccessList::IpBans, AccessAction::Add, "Steve")
+                .validate()
+                .is_err()
+        );
+        let mut op = change(AccessList::Ops, AccessAction::Add, "Steve");
+        for level in [0, 5] {
+            op.level = Some(level);
+            assert!(op.validate().is_err());
+        }
+        op.level = Some(4);
+        assert!(op.validate().is_ok());
+        let mut ban = change(AccessList::Bans, AccessAction::Add, "Steve");
+        ban.reason = Some("spam\nop Steve".to_string());
+        assert!(ban.validate().is_err());
+        assert!(ban.command(4).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use zip::result::ZipResult;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// locked by a running server and useless anywhere else
+pub const SESSION_LOCK: &str = "session.lock";
+
+/// zips everything under `dir` into `archive` with entries placed under `prefix`,
+/// skipping session locks. blocking, so call it from `spawn_blocking`
+pub fn zip_directory(
+    dir: &Path,
+    archive: &Path,
+    prefix: &Path,
+    method: CompressionMethod,
+) -> io::Result<()> {
+    let mut writer = ZipWriter::new(File::create(archive)?);
+    let options = SimpleFileOptions::default()
+        .compression_method(method)
+        .large_file(true);
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        for entry in fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name() == SESSION_LOCK {
+                continue;
+            }
+            let relative = prefix.join(path.strip_prefix(dir).unwrap_or(&path));
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if entry.file_type()?.is_dir() {
+                writer.add_directory(relative, options)?;
+                stack.push(path);
+            } else {
+                writer.start_file(relative, options)?;
+                io::copy(&mut File::open(&path)?, &mut writer)?;
+            }
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ArchiveEntry {
+    /// path inside the archive, directories end in a slash
+    pub path: String,
+    pub size_bytes: u64,
+    pub is_dir: bool,
+}
+
+/// every entry of a zip file. blocking
+pub fn zip_entries(archive: &Path) -> ZipResult<Vec<ArchiveEntry>> {
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        entries.push(ArchiveEntry {
+            path: file.name().to_string(),
+            size_bytes: file.size(),
+            is_dir: file.is_dir(),
+        });
+    }
+    Ok(entries)
+}
+
+/// the contents of one file in a zip. blocking
+pub fn read_zip_file(archive: &Path, path: &str) -> ZipResult<Vec<u8>> {
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    let mut file = archive.by_name(path)?;
+    let mut contents = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// decompresses every file so damaged data fails its checksum. blocking
+pub fn verify_zip(archive: &Path) -> ZipResult<()> {
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        io::copy(&mut file, &mut io::sink())?;
+    }
+    Ok(())
+}
+
+/// unpacks a zip into `dir`, replacing whatever was there. entries that would land
+/// outside of `dir` make it fail. blocking
+pub fn unzip_into(archive: &Path, dir: &Path) -> ZipResult<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    fs::create_dir_all(dir)?;
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    archive.extract(dir)
+}
+
+/// copies one zip entry, counting the bytes it really unpacks to since the size in its
+/// header can lie. false once `total` goes past `limit`, having written at most one byte
+/// too many. blocking
+pub fn copy_capped(
+    entry: impl Read,
+    out: &mut impl Write,
+    total: &mut u64,
+    limit: u64,
+) -> io::Result<bool> {
+    let remaining = limit.saturating_sub(*total);
+    *total += io::copy(&mut entry.take(remaining + 1), out)?;
+    Ok(*total <= limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_what_is_copied() {
+        let mut total = 0;
+        let mut out = vec![];
+        assert!(copy_capped(&[1u8; 6][..], &mut out, &mut total, 10).unwrap());
+        assert!(copy_capped(&[2u8; 4][..], &mut out, &mut total, 10).unwrap());
+        assert_eq!(total, 10);
+        assert!(!copy_capped(&[3u8; 1000][..], &mut out, &mut total, 10).unwrap());
+        assert_eq!(out.len(), 11);
+    }
+}
@@ -0,0 +1,959 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::rt::spawn;
+use chrono::{DateTime, Datelike, Local};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::task::spawn_blocking;
+use tokio::time::sleep;
+use zip::CompressionMethod;
+
+use crate::archive::{
+    ArchiveEntry, read_zip_file, unzip_into, verify_zip, zip_directory, zip_entries,
+};
+use crate::chunks::{ChunkStore, Snapshot};
+use crate::configuration::{ConfigurationManager, Server};
+use crate::events::{GameEvent, ServerEvent};
+use crate::metrics::DurationStats;
+use crate::players::now;
+use crate::server_runner::ServerRunnerHandle;
+use crate::worlds::{pause_saving, resume_saving};
+
+/// how often servers are checked for a due backup
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// how long a failed scheduled backup waits before it is tried again
+const RETRY_AFTER: Duration = Duration::from_secs(15 * 60);
+/// how long a checked archive is trusted before it's read through again
+const VERIFY_EVERY_SECS: u64 = 7 * 24 * 3600;
+
+lazy_static! {
+    pub static ref BACKUP_DURATIONS: Mutex<DurationStats> = Mutex::new(DurationStats::default());
+    /// servers with a backup being written right now
+    static ref IN_PROGRESS: Mutex<HashSet<usize>> = Mutex::new(HashSet::new());
+    /// held for every change to a backups.toml so the scheduler, the verifier and the
+    /// web pages don't write over each other
+    static ref LIST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupCompression {
+    /// no compression, fastest and biggest
+    Store,
+    #[default]
+    Deflate,
+    /// smaller and faster than deflate, but the archives need a newer unzip to open
+    Zstd,
+}
+
+impl BackupCompression {
+    pub const ALL: [BackupCompression; 3] = [
+        BackupCompression::Store,
+        BackupCompression::Deflate,
+        BackupCompression::Zstd,
+    ];
+    fn method(self) -> CompressionMethod {
+        match self {
+            BackupCompression::Store => CompressionMethod::Stored,
+            BackupCompression::Deflate => CompressionMethod::Deflated,
+            BackupCompression::Zstd => CompressionMethod::Zstd,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFormat {
+    /// a full zip archive every time
+    #[default]
+    Zip,
+    /// a snapshot in the server's chunk store, only new data takes up space
+    Chunks,
+}
+
+impl BackupFormat {
+    pub const ALL: [BackupFormat; 2] = [BackupFormat::Zip, BackupFormat::Chunks];
+}
+
+/// automatic backups of one server
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupSettings {
+    pub every_hours: u64,
+    /// newest scheduled backup of each of this many days is kept
+    pub keep_daily: usize,
+    /// newest scheduled backup of each of this many weeks is kept
+    pub keep_weekly: usize,
+    #[serde(default)]
+    pub compression: BackupCompression,
+    #[serde(default)]
+    pub format: BackupFormat,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            every_hours: 24,
+            keep_daily: 7,
+            keep_weekly: 4,
+            compression: BackupCompression::default(),
+            format: BackupFormat::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    /// made on the server's schedule or by a task, removed by retention
+    Scheduled,
+    /// asked for from the ui, kept until deleted
+    Manual,
+    /// what the server looked like right before a restore or an update, kept until deleted
+    Safety,
+}
+
+impl BackupKind {
+    fn suffix(self) -> &'static str {
+        match self {
+            BackupKind::Scheduled => "scheduled",
+            BackupKind::Manual => "manual",
+            BackupKind::Safety => "safety",
+        }
+    }
+}
+
+/// outcome of reading a whole archive back
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Verification {
+    /// unix timestamp in seconds
+    pub checked: u64,
+    /// what was wrong with the archive, none when it read back fine
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Backup {
+    /// file name of the archive or snapshot inside the server's backup directory
+    pub name: String,
+    /// unix timestamp in seconds
+    pub created: u64,
+    pub kind: BackupKind,
+    #[serde(default)]
+    pub format: BackupFormat,
+    /// only applies to zips, chunks are always zstd
+    pub compression: BackupCompression,
+    /// for snapshots just the new data they added to the chunk store
+    pub size_bytes: u64,
+    /// total size of a snapshot's files
+    #[serde(default)]
+    pub logical_bytes: Option<u64>,
+    pub duration_secs: f64,
+    /// last integrity check, none until the first one
+    #[serde(default)]
+    pub verified: Option<Verification>,
+    /// ids of the backup targets that have a copy
+    #[serde(default)]
+    pub uploaded_to: Vec<usize>,
+}
+
+/// every backup of a server, oldest first
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackupList {
+    pub backups: Vec<Backup>,
+}
+
+pub fn backup_dir(server_id: usize) -> PathBuf {
+    PathBuf::from(format!("./backups/{}", server_id))
+}
+
+fn chunk_store(server_id: usize) -> ChunkStore {
+    ChunkStore::new(backup_dir(server_id).join("chunks"))
+}
+
+/// how much the chunk store saves over keeping full copies
+#[derive(Debug, Serialize)]
+pub struct StoreStats {
+    pub snapshots: usize,
+    pub chunks: usize,
+    /// what the snapshots would take as plain files
+    pub logical_bytes: u64,
+    /// what the chunk store actually takes on disk
+    pub physical_bytes: u64,
+}
+
+/// the text of the innermost error, checksum failures come out wrapped in a few layers
+fn root_cause(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(inner) = source {
+        message = inner.to_string();
+        source = inner.source();
+    }
+    message
+}
+
+/// a backup opened for reading, however it was stored
+enum StoredBackup {
+    Zip(PathBuf),
+    Chunks(Snapshot, ChunkStore),
+}
+
+impl StoredBackup {
+    /// blocking
+    fn open(server_id: usize, backup: &Backup) -> io::Result<StoredBackup> {
+        let path = backup_dir(server_id).join(&backup.name);
+        Ok(match backup.format {
+            BackupFormat::Zip => StoredBackup::Zip(path),
+            BackupFormat::Chunks => {
+                StoredBackup::Chunks(Snapshot::load(&path)?, chunk_store(server_id))
+            }
+        })
+    }
+    fn entries(&self) -> io::Result<Vec<ArchiveEntry>> {
+        match self {
+            StoredBackup::Zip(path) => Ok(zip_entries(path)?),
+            StoredBackup::Chunks(snapshot, _) => Ok(snapshot.entries()),
+        }
+    }
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self {
+            StoredBackup::Zip(archive) => Ok(read_zip_file(archive, path)?),
+            StoredBackup::Chunks(snapshot, store) => snapshot.read_file(store, path),
+        }
+    }
+    fn verify(&self) -> io::Result<()> {
+        match self {
+            StoredBackup::Zip(path) => Ok(verify_zip(path)?),
+            StoredBackup::Chunks(snapshot, store) => snapshot.verify(store),
+        }
+    }
+    fn unpack_into(&self, dir: &Path) -> io::Result<()> {
+        match self {
+            StoredBackup::Zip(path) => Ok(unzip_into(path, dir)?),
+            StoredBackup::Chunks(snapshot, store) => snapshot.restore_into(store, dir),
+        }
+    }
+}
+
+/// opens one of the server's backups and hands it to `f` on the blocking pool
+async fn with_backup<T: Send + 'static>(
+    server_id: usize,
+    name: &str,
+    f: impl FnOnce(StoredBackup) -> io::Result<T> + Send + 'static,
+) -> Result<T, String> {
+    let backup = find_backup(server_id, name).await?;
+    spawn_blocking(move || StoredBackup::open(server_id, &backup).and_then(f))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| root_cause(&err))
+}
+
+impl BackupList {
+    fn path(server_id: usize) -> PathBuf {
+        backup_dir(server_id).join("backups.toml")
+    }
+    pub async fn load(server_id: usize) -> Result<BackupList, String> {
+        match fs::read_to_string(Self::path(server_id)).await {
+            Ok(contents) => toml::from_str(&contents).map_err(|err| {
+                format!(
+                    "invalid backup list in {}: {}",
+                    Self::path(server_id).display(),
+                    err
+                )
+            }),
+            Err(_) => Ok(BackupList::default()),
+        }
+    }
+    /// written beside the list and moved over it so a crash can't leave half a list
+    async fn write(&self, server_id: usize) -> std::io::Result<()> {
+        fs::create_dir_all(backup_dir(server_id)).await?;
+        let partial = Self::path(server_id).with_extension("toml.partial");
+        let mut file = File::create(&partial).await?;
+        file.write_all(toml::to_string_pretty(&self).unwrap().as_bytes())
+            .await?;
+        file.flush().await?;
+        fs::rename(&partial, Self::path(server_id)).await
+    }
+    /// loads the list, changes it and writes it back while holding the list lock. a list
+    /// that doesn't parse is left alone
+    pub async fn update(server_id: usize, f: impl FnOnce(&mut BackupList)) -> Result<(), String> {
+        let _lock = LIST_LOCK.lock().await;
+        let mut list = Self::load(server_id).await?;
+        f(&mut list);
+        list.write(server_id)
+            .await
+            .map_err(|err| format!("failed to write backup list: {}", err))
+    }
+    pub fn last_scheduled(&self) -> Option<&Backup> {
+        self.backups
+            .iter()
+            .filter(|x| x.kind == BackupKind::Scheduled)
+            .max_by_key(|x| x.created)
+    }
+    /// the backup that has gone the longest without an integrity check, if one is due
+    fn needs_verifying(&self) -> Option<&Backup> {
+        self.backups
+            .iter()
+            .filter(|x| {
+                x.verified
+                    .as_ref()
+                    .is_none_or(|v| v.checked + VERIFY_EVERY_SECS <= now())
+            })
+            .min_by_key(|x| x.verified.as_ref().map(|v| v.checked).unwrap_or(0))
+    }
+    /// the scheduled backups retention lets go of
+    pub fn expired(&self, settings: &BackupSettings) -> Vec<String> {
+        let mut scheduled: Vec<&Backup> = self
+            .backups
+            .iter()
+            .filter(|x| x.kind == BackupKind::Scheduled)
+            .collect();
+        scheduled.sort_by_key(|x| std::cmp::Reverse(x.created));
+        let local = |backup: &Backup| {
+            DateTime::from_timestamp(backup.created as i64, 0)
+                .unwrap_or_default()
+                .with_timezone(&Local)
+        };
+        let mut keep = HashSet::new();
+        let mut days = HashSet::new();
+        let mut weeks = HashSet::new();
+        for backup in &scheduled {
+            let created = local(backup);
+            if days.len() < settings.keep_daily && days.insert(created.date_naive()) {
+                keep.insert(backup.name.clone());
+            }
+            if weeks.len() < settings.keep_weekly && weeks.insert(created.iso_week()) {
+                keep.insert(backup.name.clone());
+            }
+        }
+        scheduled
+            .into_iter()
+            .filter(|x| !keep.contains(&x.name))
+            .map(|x| x.name.clone())
+            .collect()
+    }
+}
+
+/// a backup by name, as long as it is one of the server's
+pub async fn find_backup(server_id: usize, name: &str) -> Result<Backup, String> {
+    BackupList::load(server_id)
+        .await?
+        .backups
+        .into_iter()
+        .find(|x| x.name == name)
+        .ok_or("backup not found".to_string())
+}
+
+/// a zip of the backup to download and whether it was made just for that and should
+/// be removed once it's open
+pub async fn export_backup(server_id: usize, name: &str) -> Result<(PathBuf, bool), String> {
+    let backup = find_backup(server_id, name).await?;
+    let path = backup_dir(server_id).join(&backup.name);
+    if backup.format == BackupFormat::Zip {
+        return Ok((path, false));
+    }
+    let stem = backup.name.trim_end_matches(".snapshot");
+    let archive = backup_dir(server_id)
+        .join("downloads")
+        .join(format!("{}.zip", stem));
+    let result = {
+        let archive = archive.clone();
+        spawn_blocking(move || {
+            std::fs::create_dir_all(archive.parent().unwrap())?;
+            Snapshot::load(&path)?.export_zip(&chunk_store(server_id), &archive)
+        })
+        .await
+        .map_err(|err| err.to_string())?
+    };
+    if let Err(err) = result {
+        let _ = fs::remove_file(&archive).await;
+        return Err(format!("failed to pack backup: {}", root_cause(&err)));
+    }
+    Ok((archive, true))
+}
+
+/// what the server's chunk store holds against what its snapshots add up to
+pub async fn store_stats(server_id: usize) -> Option<StoreStats> {
+    let list = BackupList::load(server_id).await.ok()?;
+    let snapshots: Vec<&Backup> = list
+        .backups
+        .iter()
+        .filter(|x| x.format == BackupFormat::Chunks)
+        .collect();
+    if snapshots.is_empty() {
+        return None;
+    }
+    let logical_bytes = snapshots.iter().filter_map(|x| x.logical_bytes).sum();
+    let (chunks, physical_bytes) = spawn_blocking(move || chunk_store(server_id).usage())
+        .await
+        .ok()?
+        .ok()?;
+    Some(StoreStats {
+        snapshots: snapshots.len(),
+        chunks,
+        logical_bytes,
+        physical_bytes,
+    })
+}
+
+/// removes chunks that no snapshot uses anymore. only call it while holding the
+/// server's backup claim, a snapshot being written has chunks that aren't listed yet
+async fn collect_garbage(server_id: usize) {
+    // without the list every chunk would look unused
+    let list = match BackupList::load(server_id).await {
+        Ok(list) => list,
+        Err(err) => {
+            println!("not collecting chunks of server {}: {}", server_id, err);
+            return;
+        }
+    };
+    let snapshots: Vec<PathBuf> = list
+        .backups
+        .iter()
+        .filter(|x| x.format == BackupFormat::Chunks)
+        .map(|x| backup_dir(server_id).join(&x.name))
+        .collect();
+    let result = spawn_blocking(move || {
+        let mut referenced = HashSet::new();
+        for path in snapshots {
+            // a snapshot that can't be read would lose all of its chunks
+            referenced.extend(Snapshot::load(&path)?.chunks().cloned());
+        }
+        chunk_store(server_id).collect_garbage(&referenced)
+    })
+    .await;
+    match result {
+        Ok(Ok(freed)) if freed > 0 => {
+            println!("freed {} bytes of chunks of server {}", freed, server_id)
+        }
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => println!("failed to collect chunks of server {}: {}", server_id, err),
+        Err(err) => println!("failed to collect chunks of server {}: {}", server_id, err),
+    }
+}
+
+/// the files and directories directly inside `dir` of a backup, directories first
+pub async fn browse_backup(
+    server_id: usize,
+    name: &str,
+    dir: &str,
+) -> Result<Vec<ArchiveEntry>, String> {
+    let entries = with_backup(server_id, name, |backup| backup.entries())
+        .await
+        .map_err(|err| format!("failed to read backup: {}", err))?;
+    let dir = dir.trim_matches('/');
+    let prefix = if dir.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", dir)
+    };
+    let mut children: Vec<ArchiveEntry> = vec![];
+    for entry in entries {
+        let Some(rest) = entry.path.strip_prefix(&prefix) else {
+            continue;
+        };
+        let rest = rest.trim_end_matches('/');
+        if rest.is_empty() {
+            continue;
+        }
+        // archives don't always have entries for their directories, so they come from paths
+        let child = match rest.split_once('/') {
+            Some((child_dir, _)) => ArchiveEntry {
+                path: format!("{}{}/", prefix, child_dir),
+                size_bytes: 0,
+                is_dir: true,
+            },
+            None if entry.is_dir => ArchiveEntry {
+                path: format!("{}{}/", prefix, rest),
+                size_bytes: 0,
+                is_dir: true,
+            },
+            None => entry,
+        };
+        if !children.iter().any(|x| x.path == child.path) {
+            children.push(child);
+        }
+    }
+    children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.path.cmp(&b.path)));
+    Ok(children)
+}
+
+/// one file out of a backup
+pub async fn read_backup_file(server_id: usize, name: &str, path: &str) -> Result<Vec<u8>, String> {
+    let path = path.to_string();
+    with_backup(server_id, name, move |backup| backup.read_file(&path))
+        .await
+        .map_err(|err| format!("failed to read file from backup: {}", err))
+}
+
+/// notes that a target has a copy of the backup
+pub async fn record_upload(server_id: usize, name: &str, target_id: usize) {
+    let result = BackupList::update(server_id, |list| {
+        if let Some(backup) = list.backups.iter_mut().find(|x| x.name == name)
+            && !backup.uploaded_to.contains(&target_id)
+        {
+            backup.uploaded_to.push(target_id);
+        }
+    })
+    .await;
+    if let Err(err) = result {
+        println!("failed to record upload: {}", err);
+    }
+}
+
+/// removes a backup's archive or snapshot and its entry in the list. chunks only
+/// the snapshot used are collected right away unless a backup is running, then the
+/// next prune gets them
+pub async fn delete_backup(server_id: usize, name: &str) -> Result<(), String> {
+    let backup = find_backup(server_id, name).await?;
+    let _ = fs::remove_file(backup_dir(server_id).join(name)).await;
+    BackupList::update(server_id, |list| list.backups.retain(|x| x.name != name)).await?;
+    if backup.format == BackupFormat::Chunks
+        && let Some(_in_progress) = InProgress::claim(server_id)
+    {
+        collect_garbage(server_id).await;
+    }
+    Ok(())
+}
+
+/// deletes the scheduled backups retention no longer keeps. called while holding the
+/// server's backup claim
+async fn prune(server_id: usize, settings: &BackupSettings) {
+    let list = match BackupList::load(server_id).await {
+        Ok(list) => list,
+        Err(err) => {
+            println!("not pruning backups of server {}: {}", server_id, err);
+            return;
+        }
+    };
+    let expired = list.expired(settings);
+    for name in &expired {
+        println!("removing expired backup {} of server {}", name, server_id);
+        if let Err(err) = delete_backup(server_id, name).await {
+            println!("failed to remove backup {}: {}", name, err);
+        }
+    }
+    let snapshots_expired = list
+        .backups
+        .iter()
+        .any(|x| x.format == BackupFormat::Chunks && expired.contains(&x.name));
+    if snapshots_expired {
+        collect_garbage(server_id).await;
+    }
+}
+
+/// lets other backups of the server start once this one is done
+struct InProgress(usize);
+
+impl InProgress {
+    fn claim(server_id: usize) -> Option<InProgress> {
+        IN_PROGRESS
+            .lock()
+            .unwrap()
+            .insert(server_id)
+            .then_some(InProgress(server_id))
+    }
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        IN_PROGRESS.lock().unwrap().remove(&self.0);
+    }
+}
+
+pub fn is_backing_up(server_id: usize) -> bool {
+    IN_PROGRESS.lock().unwrap().contains(&server_id)
+}
+
+/// writes the game directory as a zip, returning its size on disk. blocking
+fn write_zip(game: &Path, archive: &Path, compression: BackupCompression) -> io::Result<u64> {
+    let partial = archive.with_extension("zip.partial");
+    if let Err(err) = zip_directory(game, &partial, Path::new(""), compression.method()) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(err);
+    }
+    std::fs::rename(&partial, archive)?;
+    Ok(std::fs::metadata(archive)?.len())
+}
+
+/// adds the game directory to the chunk store, only reading files that changed since
+/// `previous`. returns the bytes it added and the total size of the files. blocking
+fn write_snapshot(
+    server_id: usize,
+    game: &Path,
+    manifest: &Path,
+    previous: Option<PathBuf>,
+) -> io::Result<(u64, u64)> {
+    // without the previous snapshot every file is read again, which is slower but fine
+    let previous = previous.and_then(|x| Snapshot::load(&x).ok());
+    let (snapshot, written) = Snapshot::take(game, &chunk_store(server_id), previous.as_ref())?;
+    let partial = manifest.with_extension("snapshot.partial");
+    snapshot.write(&partial)?;
+    std::fs::rename(&partial, manifest)?;
+    Ok((written, snapshot.logical_size()))
+}
+
+async fn write_archive(
+    runner_handle: &ServerRunnerHandle,
+    server: &Server,
+    kind: BackupKind,
+    settings: &BackupSettings,
+) -> Result<Backup, String> {
+    let game = PathBuf::from(format!("./servers/{}/game", server.id));
+    if !fs::try_exists(&game).await.unwrap_or(false) {
+        return Err("the server has no files yet".to_string());
+    }
+    fs::create_dir_all(backup_dir(server.id))
+        .await
+        .map_err(|err| format!("failed to create backup directory: {}", err))?;
+    let created = now();
+    let name = format!(
+        "{}-{}.{}",
+        Local::now().format("%Y-%m-%d_%H-%M-%S"),
+        kind.suffix(),
+        match settings.format {
+            BackupFormat::Zip => "zip",
+            BackupFormat::Chunks => "snapshot",
+        }
+    );
+    let path = backup_dir(server.id).join(&name);
+    let previous = BackupList::load(server.id)
+        .await?
+        .backups
+        .iter()
+        .rfind(|x| x.format == BackupFormat::Chunks)
+        .map(|x| backup_dir(server.id).join(&x.name));
+
+    // a running server has to flush the world and leave it alone while it's copied
+    let running = runner_handle.is_running(server.id).await;
+    if running && !pause_saving(runner_handle, server.id).await {
+        resume_saving(runner_handle, server.id);
+        return Err("the server didn't save the world in time".to_string());
+    }
+    let started = Instant::now();
+    let result = {
+        let (format, compression, server_id) = (settings.format, settings.compression, server.id);
+        spawn_blocking(move || match format {
+            BackupFormat::Zip => write_zip(&game, &path, compression).map(|x| (x, None)),
+            BackupFormat::Chunks => write_snapshot(server_id, &game, &path, previous)
+                .map(|(written, logical)| (written, Some(logical))),
+        })
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|x| x.map_err(|err| err.to_string()))
+    };
+    if running {
+        resume_saving(runner_handle, server.id);
+    }
+    let (size_bytes, logical_bytes) =
+        result.map_err(|err| format!("failed to write backup: {}", err))?;
+    let duration = started.elapsed();
+    BACKUP_DURATIONS.lock().unwrap().record(duration);
+
+    let backup = Backup {
+        name,
+        created,
+        kind,
+        format: settings.format,
+        compression: settings.compression,
+        size_bytes,
+        logical_bytes,
+        duration_secs: duration.as_secs_f64(),
+        verified: None,
+        uploaded_to: vec![],
+    };
+    BackupList::update(server.id, |list| list.backups.push(backup.clone())).await?;
+    Ok(backup)
+}
+
+/// archives the server's whole directory and announces how it went
+pub async fn backup_server(
+    runner_handle: &ServerRunnerHandle,
+    server: &Server,
+    kind: BackupKind,
+) -> Result<Backup, String> {
+    let Some(_in_progress) = InProgress::claim(server.id) else {
+        return Err("a backup of this server is already running".to_string());
+    };
+    let settings = server.backups.clone().unwrap_or_default();
+    let result = write_archive(runner_handle, server, kind, &settings).await;
+    let event = match &result {
+        Ok(backup) => GameEvent::BackupFinished {
+            backup: backup.name.clone(),
+            size_bytes: backup.size_bytes,
+        },
+        Err(error) => GameEvent::BackupFailed {
+            error: error.clone(),
+        },
+    };
+    runner_handle.publish(ServerEvent::new(server.id, event));
+    if let Some(settings) = &server.backups
+        && result.is_ok()
+    {
+        prune(server.id, settings).await;
+    }
+    result
+}
+
+/// reads a backup back in full and records whether it's intact, a damaged one is
+/// announced like a failed backup
+pub async fn verify_backup(
+    runner_handle: &ServerRunnerHandle,
+    server_id: usize,
+    name: &str,
+) -> Result<Verification, String> {
+    let Some(_in_progress) = InProgress::claim(server_id) else {
+        return Err("a backup of this server is already running".to_string());
+    };
+    let error = with_backup(server_id, name, |backup| backup.verify())
+        .await
+        .err();
+    let verification = Verification {
+        checked: now(),
+        error,
+    };
+    if let Some(error) = &verification.error {
+        runner_handle.publish(ServerEvent::new(
+            server_id,
+            GameEvent::BackupFailed {
+                error: format!("backup {} is damaged: {}", name, error),
+            },
+        ));
+    }
+    BackupList::update(server_id, |list| {
+        if let Some(backup) = list.backups.iter_mut().find(|x| x.name == name) {
+            backup.verified = Some(verification.clone());
+        }
+    })
+    .await?;
+    Ok(verification)
+}
+
+/// unpacks a backup of `server_id` next to the files of `target_id` and swaps it in
+/// once it's complete
+async fn unpack_game(server_id: usize, name: &str, target_id: usize) -> Result<(), String> {
+    let game = PathBuf::from(format!("./servers/{}/game", target_id));
+    let staging = PathBuf::from(format!("./servers/{}/game.restoring", target_id));
+    let result = async {
+        {
+            let staging = staging.clone();
+            with_backup(server_id, name, move |backup| backup.unpack_into(&staging))
+                .await
+                .map_err(|err| format!("failed to unpack backup: {}", err))?;
+        }
+        if fs::try_exists(&game).await.unwrap_or(false) {
+            fs::remove_dir_all(&game)
+                .await
+                .map_err(|err| format!("failed to remove current files: {}", err))?;
+        }
+        fs::rename(&staging, &game)
+            .await
+            .map_err(|err| format!("failed to move restored files into place: {}", err))
+    }
+    .await;
+    if result.is_err() {
+        // half unpacked files would be in the way of the next restore
+        let _ = fs::remove_dir_all(&staging).await;
+    }
+    result
+}
+
+/// puts a backup back in place of the server's files. the server is stopped for it and
+/// started again after, and the files being replaced are kept as a safety backup first
+pub async fn restore_backup(
+    runner_handle: &ServerRunnerHandle,
+    server: &Server,
+    name: &str,
+) -> Result<Option<Backup>, String> {
+    let Some(_in_progress) = InProgress::claim(server.id) else {
+        return Err("a backup of this server is already running".to_string());
+    };
+    find_backup(server.id, name).await?;
+    let running = runner_handle.is_running(server.id).await;
+    if running {
+        // saved first, so the safety backup has everything the players did
+        runner_handle.stop_gracefully(server.id).await;
+    }
+    let has_files = fs::try_exists(format!("./servers/{}/game", server.id))
+        .await
+        .unwrap_or(false);
+    let safety = if has_files {
+        let settings = server.backups.clone().unwrap_or_default();
+        match write_archive(runner_handle, server, BackupKind::Safety, &settings).await {
+            Ok(backup) => Some(backup),
+            Err(err) => {
+                if running {
+                    runner_handle.start_server(server.clone());
+                }
+                return Err(format!("failed to take a safety backup: {}", err));
+            }
+        }
+    } else {
+        None
+    };
+    let result = unpack_game(server.id, name, server.id).await;
+    if running {
+        runner_handle.start_server(server.clone());
+    }
+    result.map(|_| safety)
+}
+
+/// makes a new server out of a backup, with the same settings apart from its name,
+/// ports and domain. it starts out disabled
+pub async fn clone_backup(
+    config: &ConfigurationManager,
+    server: &Server,
+    name: &str,
+    new_name: String,
+) -> Result<Server, String> {
+    find_backup(server.id, name).await?;
+    let mut clone = server.clone();
+    clone.name = new_name;
+    clone.domain = None;
+    clone.enabled = false;
+    let Some(clone) = config.add_server(clone).await else {
+        return Err("no free port left in the port pool".to_string());
+    };
+    // a clone that didn't unpack would be an empty server nobody asked for
+    if let Err(err) = unpack_game(server.id, name, clone.id).await {
+        config.remove_server(clone.id).await;
+        let _ = fs::remove_dir_all(format!("./servers/{}", clone.id)).await;
+        return Err(err);
+    }
+    Ok(clone)
+}
+
+/// takes scheduled backups of every server that has them turned on
+pub struct Backups;
+
+impl Backups {
+    pub fn begin(config: ConfigurationManager, runner_handle: ServerRunnerHandle) {
+        spawn(async move {
+            let mut failed_at: HashMap<usize, Instant> = HashMap::new();
+            loop {
+                sleep(CHECK_INTERVAL).await;
+                for server in config.get_servers().await {
+                    let list = match BackupList::load(server.id).await {
+                        Ok(list) => list,
+                        Err(err) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                    };
+                    if let Some(backup) = list.needs_verifying() {
+                        match verify_backup(&runner_handle, server.id, &backup.name).await {
+                            Ok(Verification {
+                                error: Some(err), ..
+                            }) => println!(
+                                "backup {} of server {} is damaged: {}",
+                                backup.name, server.id, err
+                            ),
+                            Ok(_) => {}
+                            Err(err) => println!("failed to verify {}: {}", backup.name, err),
+                        }
+                    }
+                    let Some(settings) = &server.backups else {
+                        continue;
+                    };
+                    let due = list
+                        .last_scheduled()
+                        .is_none_or(|x| x.created + settings.every_hours * 3600 <= now());
+                    let retrying = failed_at
+                        .get(&server.id)
+                        .is_some_and(|x| x.elapsed() < RETRY_AFTER);
+                    let has_files = fs::try_exists(format!("./servers/{}/game", server.id))
+                        .await
+                        .unwrap_or(false);
+                    if !due || retrying || !has_files {
+                        continue;
+                    }
+                    println!("backing up server {}", server.id);
+                    match backup_server(&runner_handle, &server, BackupKind::Scheduled).await {
+                        Ok(_) => {
+                            failed_at.remove(&server.id);
+                        }
+                        Err(err) => {
+                            println!("backup of server {} failed: {}", server.id, err);
+                            failed_at.insert(server.id, Instant::now());
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn backup(day: u32, hour: u32, kind: BackupKind) -> Backup {
+        Backup {
+            name: format!("{}-{}-{}", day, hour, kind.suffix()),
+            created: Local
+                .with_ymd_and_hms(2026, 1, day, hour, 0, 0)
+                .unwrap()
+                .timestamp() as u64,
+            kind,
+            format: BackupFormat::Zip,
+            compression: BackupCompression::Deflate,
+            size_bytes: 0,
+            logical_bytes: None,
+            duration_secs: 0.0,
+            verified: None,
+            uploaded_to: vec![],
+        }
+    }
+
+    fn ten_days() -> BackupList {
+        BackupList {
+            backups: (1..=10)
+                .flat_map(|day| [6, 12, 18].map(|hour| backup(day, hour, BackupKind::Scheduled)))
+                .collect(),
+        }
+    }
+
+    fn settings(keep_daily: usize, keep_weekly: usize) -> BackupSettings {
+        BackupSettings {
+            keep_daily,
+            keep_weekly,
+            ..BackupSettings::default()
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_of_each_day() {
+        let expired = ten_days().expired(&settings(3, 0));
+        assert_eq!(expired.len(), 27);
+        for kept in ["8-18-scheduled", "9-18-scheduled", "10-18-scheduled"] {
+            assert!(!expired.contains(&kept.to_string()));
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_of_each_week() {
+        // the 1st of january 2026 is a thursday, so the 4th ends its iso week
+        let expired = ten_days().expired(&settings(1, 2));
+        assert_eq!(expired.len(), 28);
+        assert!(!expired.contains(&"10-18-scheduled".to_string()));
+        assert!(!expired.contains(&"4-18-scheduled".to_string()));
+    }
+
+    #[test]
+    fn never_expires_other_kinds() {
+        let mut list = ten_days();
+        list.backups.push(backup(1, 0, BackupKind::Manual));
+        list.backups.push(backup(1, 1, BackupKind::Safety));
+        let expired = list.expired(&settings(0, 0));
+        assert_eq!(expired.len(), 30);
+        assert!(expired.iter().all(|x| x.ends_with("scheduled")));
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::archive::{ArchiveEntry, SESSION_LOCK};
+
+/// no chunk is cut shorter than this, except at the end of a file
+const MIN_CHUNK: usize = 16 * 1024;
+/// chunks are cut here even if the content gives no boundary
+const MAX_CHUNK: usize = 256 * 1024;
+/// 16 bits puts a boundary every 64KiB past the minimum on average. the high bits are
+/// used since they depend on the last 64 bytes instead of just the last few
+const BOUNDARY_MASK: u64 = 0xffff_0000_0000_0000;
+const ZSTD_LEVEL: i32 = 3;
+
+/// random values for the gear rolling hash, made with splitmix64 so they never change
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6d63_6d61_6e61_6765;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// where the next chunk of `data` ends
+fn boundary(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK);
+    let mut hash: u64 = 0;
+    for (i, byte) in data[..end].iter().enumerate().skip(MIN_CHUNK) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        if hash & BOUNDARY_MASK == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// cuts a stream into content defined chunks without holding more than one max sized
+/// chunk in memory
+struct Chunker<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::with_capacity(MAX_CHUNK),
+            done: false,
+        }
+    }
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        while !self.done && self.buffer.len() < MAX_CHUNK {
+            let filled = self.buffer.len();
+            self.buffer.resize(MAX_CHUNK, 0);
+            let read = self.reader.read(&mut self.buffer[filled..])?;
+            self.buffer.truncate(filled + read);
+            if read == 0 {
+                self.done = true;
+            }
+        }
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let end = if self.done && self.buffer.len() <= MIN_CHUNK {
+            self.buffer.len()
+        } else {
+            boundary(&self.buffer)
+        };
+        Ok(Some(self.buffer.drain(..end).collect()))
+    }
+}
+
+/// compressed chunks of one server's backups, named by the sha256 of their contents.
+/// files are cut where their content says so rather than at fixed offsets, so an edit
+/// only changes the chunks around it and unchanged region files cost nothing twice
+pub struct ChunkStore {
+    dir: PathBuf,
+}
+
+impl ChunkStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+    /// stores a chunk unless it's already there, returning its hash and the bytes it
+    /// took on disk
+    pub fn put(&self, data: &[u8]) -> io::Result<(String, u64)> {
+        let hash = hex::encode(Sha256::digest(data));
+        let path = self.path(&hash);
+        if path.exists() {
+            return Ok((hash, 0));
+        }
+        fs::create_dir_all(path.parent().unwrap())?;
+        let compressed = zstd::encode_all(data, ZSTD_LEVEL)?;
+        let partial = path.with_extension("partial");
+        let mut file = File::create(&partial)?;
+        file.write_all(&compressed)?;
+        file.sync_all()?;
+        fs::rename(&partial, &path)?;
+        Ok((hash, compressed.len() as u64))
+    }
+    /// reads a chunk back, failing if it no longer matches its hash
+    pub fn get(&self, hash: &str) -> io::Result<Vec<u8>> {
+        if hash.len() < 2 || !hash.bytes().all(|x| x.is_ascii_hexdigit()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad chunk name"));
+        }
+        let data = zstd::decode_all(File::open(self.path(hash))?)?;
+        if hex::encode(Sha256::digest(&data)) != hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk {} is damaged", hash),
+            ));
+        }
+        Ok(data)
+    }
+    fn for_each_chunk(
+        &self,
+        mut f: impl FnMut(&str, &Path, u64) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let Ok(prefixes) = fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+        for prefix in prefixes {
+            let prefix = prefix?;
+            if !prefix.file_type()?.is_dir() {
+                continue;
+            }
+            for chunk in fs::read_dir(prefix.path())? {
+                let chunk = chunk?;
+                let name = chunk.file_name().to_string_lossy().to_string();
+                f(&name, &chunk.path(), chunk.metadata()?.len())?;
+            }
+        }
+        Ok(())
+    }
+    /// chunk count and bytes on disk
+    pub fn usage(&self) -> io::Result<(usize, u64)> {
+        let mut count = 0;
+        let mut bytes = 0;
+        self.for_each_chunk(|_, _, size| {
+            count += 1;
+            bytes += size;
+            Ok(())
+        })?;
+        Ok((count, bytes))
+    }
+    /// deletes every chunk no snapshot refers to anymore, returning the bytes freed.
+    /// nothing may be writing to the store while this runs
+    pub fn collect_garbage(&self, referenced: &HashSet<String>) -> io::Result<u64> {
+        let mut freed = 0;
+        self.for_each_chunk(|name, path, size| {
+            // leftovers of interrupted writes go too
+            if !referenced.contains(name) {
+                fs::remove_file(path)?;
+                freed += size;
+            }
+            Ok(())
+        })?;
+        Ok(freed)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SnapshotFile {
+    /// relative to the server's game directory, with forward slashes
+    pub path: String,
+    pub size_bytes: u64,
+    /// nanoseconds since the unix epoch, lets the next snapshot skip reading it again
+    pub modified: u64,
+    pub chunks: Vec<String>,
+}
+
+/// the manifest of one deduplicated backup, the files it had and the chunks that make
+/// them up. directories are kept so empty ones come back too
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Snapshot {
+    pub directories: Vec<String>,
+    pub files: Vec<SnapshotFile>,
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// a path from a manifest under `dir`, refusing anything that would leave it
+fn enclosed(dir: &Path, path: &str) -> io::Result<PathBuf> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|x| matches!(x, Component::Normal(_)))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad path {} in snapshot", path),
+        ));
+    }
+    Ok(dir.join(relative))
+}
+
+impl Snapshot {
+    /// stores everything under `dir`, only reading files that changed since `previous`.
+    /// returns the snapshot and the bytes newly added to the store. blocking
+    pub fn take(
+        dir: &Path,
+        store: &ChunkStore,
+        previous: Option<&Snapshot>,
+    ) -> io::Result<(Snapshot, u64)> {
+        let mut snapshot = Snapshot::default();
+        let mut written = 0;
+        let previous: HashMap<&str, &SnapshotFile> = previous
+            .map(|x| x.files.iter().map(|x| (x.path.as_str(), x)).collect())
+            .unwrap_or_default();
+        let mut stack = vec![dir.to_path_buf()];
+        while let Some(current) = stack.pop() {
+            for entry in fs::read_dir(&current)? {
+                let entry = entry?;
+                let path = entry.path();
+                if entry.file_name() == SESSION_LOCK {
+                    continue;
+                }
+                let relative = path
+                    .strip_prefix(dir)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    snapshot.directories.push(relative);
+                    stack.push(path);
+                    continue;
+                }
+                let modified = modified_nanos(&metadata);
+                let unchanged = previous.get(relative.as_str()).copied().filter(|x| {
+                    x.size_bytes == metadata.len() && x.modified == modified && modified != 0
+                });
+                if let Some(unchanged) = unchanged {
+                    snapshot.files.push(SnapshotFile {
+                        path: relative,
+                        ..unchanged.clone()
+                    });
+                    continue;
+                }
+                let mut chunker = Chunker::new(File::open(&path)?);
+                let mut chunks = vec![];
+                let mut size_bytes = 0;
+                while let Some(chunk) = chunker.next_chunk()? {
+                    size_bytes += chunk.len() as u64;
+                    let (hash, stored) = store.put(&chunk)?;
+                    written += stored;
+                    chunks.push(hash);
+                }
+                snapshot.files.push(SnapshotFile {
+                    path: relative,
+                    size_bytes,
+                    modified,
+                    chunks,
+                });
+            }
+        }
+        snapshot.directories.sort();
+        snapshot.files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((snapshot, written))
+    }
+    /// json rather than toml since these hold a line per chunk of every file
+    pub fn load(path: &Path) -> io::Result<Snapshot> {
+        serde_json::from_reader(io::BufReader::new(File::open(path)?))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut file, self)?;
+        file.flush()
+    }
+    /// total size of the files, what the backup would take as plain copies
+    pub fn logical_size(&self) -> u64 {
+        self.files.iter().map(|x| x.size_bytes).sum()
+    }
+    pub fn chunks(&self) -> impl Iterator<Item = &String> {
+        self.files.iter().flat_map(|x| x.chunks.iter())
+    }
+    /// the snapshot's contents laid out like a zip's entries
+    pub fn entries(&self) -> Vec<ArchiveEntry> {
+        let directories = self.directories.iter().map(|x| ArchiveEntry {
+            path: format!("{}/", x),
+            size_bytes: 0,
+            is_dir: true,
+        });
+        let files = self.files.iter().map(|x| ArchiveEntry {
+            path: x.path.clone(),
+            size_bytes: x.size_bytes,
+            is_dir: false,
+        });
+        directories.chain(files).collect()
+    }
+    fn copy_file(
+        &self,
+        store: &ChunkStore,
+        file: &SnapshotFile,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let mut size = 0;
+        for hash in &file.chunks {
+            let chunk = store.get(hash)?;
+            size += chunk.len() as u64;
+            out.write_all(&chunk)?;
+        }
+        if size != file.size_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} came back the wrong size", file.path),
+            ));
+        }
+        Ok(())
+    }
+    pub fn read_file(&self, store: &ChunkStore, path: &str) -> io::Result<Vec<u8>> {
+        // files are kept sorted by path
+        let Ok(index) = self.files.binary_search_by(|x| x.path.as_str().cmp(path)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "file not found in backup",
+            ));
+        };
+        let file = &self.files[index];
+        let mut contents = Vec::with_capacity(file.size_bytes as usize);
+        self.copy_file(store, file, &mut contents)?;
+        Ok(contents)
+    }
+    /// reads every chunk back and checks it against its hash. blocking
+    pub fn verify(&self, store: &ChunkStore) -> io::Result<()> {
+        let mut sizes: HashMap<&str, u64> = HashMap::new();
+        for file in &self.files {
+            let mut size = 0;
+            for hash in &file.chunks {
+                size += match sizes.get(hash.as_str()) {
+                    Some(len) => *len,
+                    None => {
+                        let len = store.get(hash)?.len() as u64;
+                        sizes.insert(hash, len);
+                        len
+                    }
+                };
+            }
+            if size != file.size_bytes {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} came back the wrong size", file.path),
+                ));
+            }
+        }
+        Ok(())
+    }
+    /// writes the snapshot's files into `dir`, replacing whatever was there. blocking
+    pub fn restore_into(&self, store: &ChunkStore, dir: &Path) -> io::Result<()> {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::create_dir_all(dir)?;
+        for directory in &self.directories {
+            fs::create_dir_all(enclosed(dir, directory)?)?;
+        }
+        for file in &self.files {
+            let path = enclosed(dir, &file.path)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut out = io::BufWriter::new(File::create(&path)?);
+            self.copy_file(store, file, &mut out)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+    /// packs the snapshot into a zip for downloading. blocking
+    pub fn export_zip(&self, store: &ChunkStore, archive: &Path) -> io::Result<()> {
+        let mut writer = ZipWriter::new(File::create(archive)?);
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(true);
+        for directory in &self.directories {
+            writer.add_directory(directory.as_str(), options)?;
+        }
+        for file in &self.files {
+            writer.start_file(file.path.as_str(), options)?;
+            self.copy_file(store, file, &mut writer)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_unchanged_files() {
+        let root = std::env::temp_dir().join(format!("mcmanage-chunks-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let game = root.join("game");
+        fs::create_dir_all(game.join("world")).unwrap();
+        for i in 0..50 {
+            fs::write(
+                game.join("world").join(format!("{}.dat", i)),
+                vec![i as u8; 1000],
+            )
+            .unwrap();
+        }
+        let store = ChunkStore::new(root.join("chunks"));
+        let (first, written) = Snapshot::take(&game, &store, None).unwrap();
+        assert!(written > 0);
+        fs::write(game.join("world/7.dat"), b"changed").unwrap();
+        // same size and time, so it's taken from the previous snapshot without reading it
+        let sneaky = game.join("world/42.dat");
+        let modified = fs::metadata(&sneaky).unwrap().modified().unwrap();
+        fs::write(&sneaky, vec![0; 1000]).unwrap();
+        File::options()
+            .write(true)
+            .open(&sneaky)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let (second, _) = Snapshot::take(&game, &store, Some(&first)).unwrap();
+        assert_eq!(second.read_file(&store, "world/7.dat").unwrap(), b"changed");
+        assert_eq!(
+            second.read_file(&store, "world/42.dat").unwrap(),
+            vec![42; 1000]
+        );
+        assert_eq!(
+            second.read_file(&store, "world/43.dat").unwrap(),
+            vec![43; 1000]
+        );
+        assert!(second.read_file(&store, "world/missing.dat").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
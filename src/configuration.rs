@@ -8,9 +8,18 @@ use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 
+use crate::access::DEFAULT_PROFILE_LOOKUP;
+use crate::backups::BackupSettings;
 use crate::downloader::Downloader;
+use crate::loaders::forge::{ForgeKind, args_file};
+use crate::ports::{PortPool, taken_ports};
+use crate::proxy::ProxySettings;
+use crate::scheduler::ScheduledTask;
 use crate::server_runner::ServerRunnerHandle;
+use crate::targets::BackupTarget;
+use crate::upgrades::VersionChange;
 use crate::versions::VersionInfo;
+use crate::webhooks::Webhook;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -19,10 +28,77 @@ pub struct User {
     pub is_admin: bool,
 }
 
-// nice support for fabric planned
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum ServerType {
     Vanilla,
+    /// the fabric server launcher, which loads mods from the mods folder
+    Fabric {
+        loader_version: String,
+        installer_version: String,
+    },
+    /// paper, checked against the sha256 papermc publishes for each build
+    Paper {
+        build: u32,
+    },
+    /// purpur, a fork of paper with its own builds
+    Purpur {
+        build: u32,
+    },
+    /// installed into the server's directory by the forge installer
+    Forge {
+        version: String,
+    },
+    /// installed into the server's directory by the neoforge installer
+    NeoForge {
+        version: String,
+    },
+}
+
+impl ServerType {
+    /// the jar downloaded for it, relative to `./server_versions`. the server itself, or
+    /// the installer for forge and neoforge
+    pub fn jar(&self, mc_version: &str) -> String {
+        match self {
+            ServerType::Vanilla => format!("{}/{}.jar", mc_version, mc_version),
+            ServerType::Fabric {
+                loader_version,
+                installer_version,
+            } => format!(
+                "fabric/fabric-server-mc.{}-loader.{}-launcher.{}.jar",
+                mc_version, loader_version, installer_version
+            ),
+            ServerType::Paper { build } => format!("paper/paper-{}-{}.jar", mc_version, build),
+            ServerType::Purpur { build } => format!("purpur/purpur-{}-{}.jar", mc_version, build),
+            ServerType::Forge { version } => {
+                format!("forge/forge-{}-{}-installer.jar", mc_version, version)
+            }
+            ServerType::NeoForge { version } => {
+                format!("neoforge/neoforge-{}-installer.jar", version)
+            }
+        }
+    }
+    /// which installer sets it up and the loader version, for forge and neoforge
+    pub fn forge(&self) -> Option<(ForgeKind, &str)> {
+        match self {
+            ServerType::Forge { version } => Some((ForgeKind::Forge, version)),
+            ServerType::NeoForge { version } => Some((ForgeKind::NeoForge, version)),
+            _ => None,
+        }
+    }
+    /// what java is given to start the server from inside its directory
+    pub fn launch_args(&self, mc_version: &str) -> Vec<String> {
+        match self.forge() {
+            // the installer writes the classpath and main class into an arguments file
+            Some((kind, version)) => vec![
+                "@user_jvm_args.txt".to_string(),
+                format!("@{}", args_file(kind, mc_version, version)),
+            ],
+            None => vec![
+                "-jar".to_string(),
+                format!("../../../server_versions/{}", self.jar(mc_version)),
+            ],
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,9 +107,22 @@ pub struct Server {
     pub name: String,
     pub domain: Option<String>,
     pub port: u16,
+    /// second port from the pool reserved for rcon
+    #[serde(default)]
+    pub rcon_port: Option<u16>,
     pub server_type: ServerType,
     pub mc_version_id: String,
     pub enabled: bool,
+    /// stop the server after this many minutes without players and start it again when
+    /// someone tries to join
+    #[serde(default)]
+    pub sleep_after_minutes: Option<u64>,
+    /// automatic backups, off when unset
+    #[serde(default)]
+    pub backups: Option<BackupSettings>,
+    /// every minecraft version the server was moved between, oldest first
+    #[serde(default)]
+    pub version_history: Vec<VersionChange>,
     /// #By changing the setting below to TRUE you are indicating your agreement to our EULA (https://aka.ms/MinecraftEULA).
     pub eula: bool,
 }
@@ -45,9 +134,13 @@ impl Server {
             name,
             domain: None,
             port: 25565,
+            rcon_port: None,
             server_type: ServerType::Vanilla,
             mc_version_id: version_id,
             enabled: true,
+            sleep_after_minutes: None,
+            backups: None,
+            version_history: vec![],
             eula,
         }
     }
@@ -58,8 +151,33 @@ pub struct Configuration {
     pub next_server_id: usize,
     pub max_concurrent_servers: usize,
     pub max_total_ram_mb: usize,
+    /// ports new servers are given
+    #[serde(default)]
+    pub port_pool: PortPool,
+    /// bearer token required by the /metrics endpoint, left open when unset
+    #[serde(default)]
+    pub metrics_token: Option<String>,
+    /// username to uuid lookup used when editing the lists of a stopped server,
+    /// `{username}` is replaced with the name, mojang's api when unset
+    #[serde(default)]
+    pub profile_lookup_url: Option<String>,
+    /// built in proxy routing players by hostname to each server's domain, off when unset
+    #[serde(default)]
+    pub proxy: Option<ProxySettings>,
     pub users: Vec<User>,
     pub servers: Vec<Server>,
+    #[serde(default)]
+    pub next_task_id: usize,
+    #[serde(default)]
+    pub tasks: Vec<ScheduledTask>,
+    #[serde(default)]
+    pub next_webhook_id: usize,
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    #[serde(default)]
+    pub next_backup_target_id: usize,
+    #[serde(default)]
+    pub backup_targets: Vec<BackupTarget>,
 }
 
 const FILE_PATH: &str = "mcmanager.toml";
@@ -86,8 +204,18 @@ impl Configuration {
             next_server_id: 0,
             max_concurrent_servers: 1,
             max_total_ram_mb: 3000,
+            port_pool: PortPool::default(),
+            metrics_token: None,
+            profile_lookup_url: None,
+            proxy: None,
             users: vec![],
             servers: vec![],
+            next_task_id: 0,
+            tasks: vec![],
+            next_webhook_id: 0,
+            webhooks: vec![],
+            next_backup_target_id: 0,
+            backup_targets: vec![],
         }
     }
     async fn write(&self) -> std::io::Result<()> {
@@ -106,8 +234,45 @@ pub struct ConfigurationManager {
 
 impl ConfigurationManager {
     pub async fn new() -> Self {
-        Self {
+        let new = Self {
             manager: Arc::new(Mutex::new(Configuration::load_config().await)),
+        };
+        new.assign_ports().await;
+        new
+    }
+    /// gives servers from older configs an rcon port and moves any that share a port
+    async fn assign_ports(&self) {
+        let mut lock = self.manager.lock().await;
+        let mut changed = false;
+        for index in 0..lock.servers.len() {
+            let server = &lock.servers[index];
+            let proxy_bind = lock.proxy.as_ref().map(|x| x.bind_address.as_str());
+            let mut taken = taken_ports(&lock.servers, proxy_bind, Some(server.id));
+            let clashes = taken.contains(&server.port);
+            let missing_rcon = server.rcon_port.is_none_or(|x| taken.contains(&x));
+            if !clashes && !missing_rcon {
+                continue;
+            }
+            let pool = lock.port_pool;
+            if clashes {
+                let Some(port) = pool.allocate(&taken) else {
+                    println!("no free port left in the pool for server {}", server.id);
+                    continue;
+                };
+                println!(
+                    "server {} shared port {}, moved to {}",
+                    server.id, server.port, port
+                );
+                taken.insert(port);
+                lock.servers[index].port = port;
+            } else {
+                taken.insert(server.port);
+            }
+            lock.servers[index].rcon_port = pool.allocate(&taken);
+            changed = true;
+        }
+        if changed {
+            lock.write().await.expect("failed to write updated config");
         }
     }
     pub async fn reload(&self) {
@@ -137,6 +302,20 @@ impl ConfigurationManager {
         let lock = self.manager.lock().await;
         lock.users.iter().find(|x| x.username == username).cloned()
     }
+    pub async fn get_metrics_token(&self) -> Option<String> {
+        let lock = self.manager.lock().await;
+        lock.metrics_token.clone()
+    }
+    pub async fn get_profile_lookup_url(&self) -> String {
+        let lock = self.manager.lock().await;
+        lock.profile_lookup_url
+            .clone()
+            .unwrap_or(DEFAULT_PROFILE_LOOKUP.to_string())
+    }
+    pub async fn get_proxy_settings(&self) -> Option<ProxySettings> {
+        let lock = self.manager.lock().await;
+        lock.proxy.clone()
+    }
     pub async fn has_users(&self) -> bool {
         let lock = self.manager.lock().await;
         !lock.users.is_empty()
@@ -151,16 +330,25 @@ impl ConfigurationManager {
         }
         Err(())
     }
-    /// add a new server and return it, ignores whatever id or port is provided
-    pub async fn add_server(&self, mut new_server: Server) -> Server {
+    /// add a new server and return it, ignores whatever id or ports are provided,
+    /// none if the port pool has run out
+    pub async fn add_server(&self, mut new_server: Server) -> Option<Server> {
         let mut lock = self.manager.lock().await;
-        let new_id = lock.next_server_id;
-        new_server.id = new_id;
-        new_server.port = 25565 + new_id as u16;
+        let proxy_bind = lock.proxy.as_ref().map(|x| x.bind_address.as_str());
+        let mut taken = taken_ports(&lock.servers, proxy_bind, None);
+        new_server.port = lock.port_pool.allocate(&taken)?;
+        taken.insert(new_server.port);
+        new_server.rcon_port = Some(lock.port_pool.allocate(&taken)?);
+        new_server.id = lock.next_server_id;
         lock.next_server_id += 1;
         lock.servers.push(new_server.clone());
         lock.write().await.expect("failed to write updated config");
-        new_server
+        Some(new_server)
+    }
+    pub async fn remove_server(&self, server_id: usize) {
+        let mut lock = self.manager.lock().await;
+        lock.servers.retain(|x| x.id != server_id);
+        lock.write().await.expect("failed to write updated config");
     }
     pub async fn modify_server(&self, updated_server: Server) {
         let mut lock = self.manager.lock().await;
@@ -188,16 +376,164 @@ impl ConfigurationManager {
         }
         lock.write().await.expect("failed to write updated config");
     }
+    pub async fn set_server_domain(&self, server: usize, domain: Option<String>) {
+        let mut lock = self.manager.lock().await;
+        let server = lock.servers.iter_mut().find(|x| x.id == server);
+        if let Some(server) = server {
+            server.domain = domain;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn set_server_sleep(&self, server: usize, sleep_after_minutes: Option<u64>) {
+        let mut lock = self.manager.lock().await;
+        let server = lock.servers.iter_mut().find(|x| x.id == server);
+        if let Some(server) = server {
+            server.sleep_after_minutes = sleep_after_minutes;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn set_server_type(&self, server: usize, server_type: ServerType) {
+        let mut lock = self.manager.lock().await;
+        let server = lock.servers.iter_mut().find(|x| x.id == server);
+        if let Some(server) = server {
+            server.server_type = server_type;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
+    /// moves the server to another game version along with the server type for it,
+    /// returning the updated server
+    pub async fn set_server_version(
+        &self,
+        server: usize,
+        mc_version: String,
+        server_type: ServerType,
+        change: VersionChange,
+    ) -> Option<Server> {
+        let mut lock = self.manager.lock().await;
+        let server = lock.servers.iter_mut().find(|x| x.id == server)?;
+        server.mc_version_id = mc_version;
+        server.server_type = server_type;
+        server.version_history.push(change);
+        let server = server.clone();
+        lock.write().await.expect("failed to write updated config");
+        Some(server)
+    }
+    pub async fn set_server_backups(&self, server: usize, backups: Option<BackupSettings>) {
+        let mut lock = self.manager.lock().await;
+        let server = lock.servers.iter_mut().find(|x| x.id == server);
+        if let Some(server) = server {
+            server.backups = backups;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn get_tasks(&self) -> Vec<ScheduledTask> {
+        let lock = self.manager.lock().await;
+        lock.tasks.clone()
+    }
+    /// add a new scheduled task and return it, ignores whatever id is provided
+    pub async fn add_task(&self, mut new_task: ScheduledTask) -> ScheduledTask {
+        let mut lock = self.manager.lock().await;
+        new_task.id = lock.next_task_id;
+        lock.next_task_id += 1;
+        lock.tasks.push(new_task.clone());
+        lock.write().await.expect("failed to write updated config");
+        new_task
+    }
+    /// replaces a task's name, schedule and action, keeping whether it's enabled
+    pub async fn update_task(&self, updated: ScheduledTask) {
+        let mut lock = self.manager.lock().await;
+        if let Some(task) = lock.tasks.iter_mut().find(|x| x.id == updated.id) {
+            task.name = updated.name;
+            task.schedule = updated.schedule;
+            task.action = updated.action;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn remove_task(&self, task_id: usize) {
+        let mut lock = self.manager.lock().await;
+        lock.tasks.retain(|x| x.id != task_id);
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn set_task_enabled(&self, task_id: usize, enabled: bool) {
+        let mut lock = self.manager.lock().await;
+        if let Some(task) = lock.tasks.iter_mut().find(|x| x.id == task_id) {
+            task.enabled = enabled;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn get_webhooks(&self) -> Vec<Webhook> {
+        let lock = self.manager.lock().await;
+        lock.webhooks.clone()
+    }
+    pub async fn get_webhook(&self, webhook_id: usize) -> Option<Webhook> {
+        let lock = self.manager.lock().await;
+        lock.webhooks.iter().find(|x| x.id == webhook_id).cloned()
+    }
+    /// add a new webhook and return it, ignores whatever id is provided
+    pub async fn add_webhook(&self, mut new_webhook: Webhook) -> Webhook {
+        let mut lock = self.manager.lock().await;
+        new_webhook.id = lock.next_webhook_id;
+        lock.next_webhook_id += 1;
+        lock.webhooks.push(new_webhook.clone());
+        lock.write().await.expect("failed to write updated config");
+        new_webhook
+    }
+    pub async fn remove_webhook(&self, webhook_id: usize) {
+        let mut lock = self.manager.lock().await;
+        lock.webhooks.retain(|x| x.id != webhook_id);
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn set_webhook_enabled(&self, webhook_id: usize, enabled: bool) {
+        let mut lock = self.manager.lock().await;
+        if let Some(webhook) = lock.webhooks.iter_mut().find(|x| x.id == webhook_id) {
+            webhook.enabled = enabled;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn get_backup_targets(&self) -> Vec<BackupTarget> {
+        let lock = self.manager.lock().await;
+        lock.backup_targets.clone()
+    }
+    pub async fn get_backup_target(&self, target_id: usize) -> Option<BackupTarget> {
+        let lock = self.manager.lock().await;
+        lock.backup_targets
+            .iter()
+            .find(|x| x.id == target_id)
+            .cloned()
+    }
+    /// add a new backup target and return it, ignores whatever id is provided
+    pub async fn add_backup_target(&self, mut new_target: BackupTarget) -> BackupTarget {
+        let mut lock = self.manager.lock().await;
+        new_target.id = lock.next_backup_target_id;
+        lock.next_backup_target_id += 1;
+        lock.backup_targets.push(new_target.clone());
+        lock.write().await.expect("failed to write updated config");
+        new_target
+    }
+    pub async fn remove_backup_target(&self, target_id: usize) {
+        let mut lock = self.manager.lock().await;
+        lock.backup_targets.retain(|x| x.id != target_id);
+        lock.write().await.expect("failed to write updated config");
+    }
+    pub async fn set_backup_target_enabled(&self, target_id: usize, enabled: bool) {
+        let mut lock = self.manager.lock().await;
+        if let Some(target) = lock.backup_targets.iter_mut().find(|x| x.id == target_id) {
+            target.enabled = enabled;
+        }
+        lock.write().await.expect("failed to write updated config");
+    }
     pub async fn create_new_server(
         &self,
         name: String,
         version: VersionInfo,
+        server_type: ServerType,
         eula: bool,
-    ) -> Server {
-        let server = Server::new(name, version.id.clone(), eula);
-        let server = self.add_server(server).await;
-        Downloader::ensure_available(version).await;
-        return server;
+    ) -> Result<Option<Server>, String> {
+        let mut server = Server::new(name, version.id.clone(), eula);
+        // fetched first so a failed download doesn't leave a server that can't start
+        Downloader::ensure_available(version).await?;
+        server.server_type = server_type;
+        Ok(self.add_server(server).await)
     }
     pub async fn start_all(&self, handle: ServerRunnerHandle) {
         let lock = self.manager.lock().await;
@@ -1,21 +1,26 @@
-use std::{fs::create_dir_all, path::Path};
+use std::path::PathBuf;
 
-use crate::versions::VersionInfo;
+use crate::{
+    loaders::{Checksum, download},
+    versions::VersionInfo,
+};
 
 pub struct Downloader;
 
 impl Downloader {
-    pub async fn ensure_available(version: VersionInfo) {
-        let path = format!("./server_versions/{}", &version.id);
-        if Path::new(&path).is_dir() {
-            return;
+    /// fetches the vanilla server jar of a version unless it's already there, checking
+    /// it against the sha1 mojang lists for it
+    pub async fn ensure_available(version: VersionInfo) -> Result<(), String> {
+        let jar = PathBuf::from(format!(
+            "./server_versions/{}/{}.jar",
+            version.id, version.id
+        ));
+        if jar.is_file() {
+            return Ok(());
         }
-        create_dir_all(&path).expect("could not create server directory");
-        version
-            .downloads
-            .server
-            .download(&format!("{}/{}.jar", path, &version.id))
-            .await
-            .expect("failed to download new server");
+        let Some(server) = version.downloads.server else {
+            return Err(format!("{} has no server download", version.id));
+        };
+        download(&server.url, &jar, Checksum::Sha1(server.sha1)).await
     }
 }
@@ -0,0 +1,397 @@
+use serde::Serialize;
+
+use crate::players::{is_valid_username, now};
+
+/// endings of the vanilla death messages, following the player's name
+const DEATH_MESSAGES: [&str; 38] = [
+    "was slain by",
+    "was shot by",
+    "was fireballed by",
+    "was pummeled by",
+    "was killed",
+    "was blown up by",
+    "blew up",
+    "was impaled",
+    "was skewered",
+    "was stung to death",
+    "was poked to death",
+    "was pricked to death",
+    "was squished",
+    "was squashed",
+    "was obliterated",
+    "was struck by lightning",
+    "was frozen to death",
+    "was roasted",
+    "was doomed to fall",
+    "drowned",
+    "died",
+    "didn't want to live",
+    "experienced kinetic energy",
+    "fell ",
+    "hit the ground too hard",
+    "burned to death",
+    "went up in flames",
+    "walked into",
+    "went off with a bang",
+    "tried to swim in lava",
+    "discovered the floor was lava",
+    "starved to death",
+    "suffocated in a wall",
+    "froze to death",
+    "withered away",
+    "left the confines of this world",
+    "was sniped by",
+    "was spitballed by",
+];
+
+const ADVANCEMENT_MESSAGES: [&str; 3] = [
+    " has made the advancement ",
+    " has completed the challenge ",
+    " has reached the goal ",
+];
+
+/// something that happened on a server, parsed from its console output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GameEvent {
+    PlayerUuid {
+        username: String,
+        uuid: String,
+    },
+    PlayerLoggedIn {
+        username: String,
+        ip: String,
+    },
+    PlayerJoined {
+        username: String,
+    },
+    PlayerLeft {
+        username: String,
+    },
+    Chat {
+        username: String,
+        message: String,
+    },
+    Death {
+        username: String,
+        message: String,
+    },
+    Advancement {
+        username: String,
+        advancement: String,
+    },
+    ServerStarted {
+        startup_secs: Option<f64>,
+    },
+    ServerStopping,
+    /// a `save-all` finished writing the world
+    Saved,
+    /// "Can't keep up!" warnings
+    Overloaded {
+        behind_ms: Option<u64>,
+    },
+    /// output of the `tps` command on servers that have one
+    TpsReport {
+        one_minute: f64,
+    },
+    Error {
+        message: String,
+    },
+    /// the process went away, crashed is set when it wasn't asked to stop
+    Exited {
+        crashed: bool,
+    },
+    BackupFinished {
+        backup: String,
+        size_bytes: u64,
+    },
+    BackupFailed {
+        error: String,
+    },
+}
+
+/// an event along with the server it happened on
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerEvent {
+    pub server_id: usize,
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+    pub event: GameEvent,
+}
+
+impl ServerEvent {
+    pub fn new(server_id: usize, event: GameEvent) -> Self {
+        Self {
+            server_id,
+            timestamp: now(),
+            event,
+        }
+    }
+}
+
+/// a console line split into `[time] [thread/LEVEL]: message`
+#[derive(Debug, Clone, Copy)]
+pub struct LogLine<'a> {
+    pub thread: Option<&'a str>,
+    pub level: Option<&'a str>,
+    pub message: &'a str,
+}
+
+impl<'a> LogLine<'a> {
+    pub fn split(line: &'a str) -> LogLine<'a> {
+        let unparsed = LogLine {
+            thread: None,
+            level: None,
+            message: line,
+        };
+        if !line.starts_with('[') {
+            return unparsed;
+        }
+        let Some((prefix, message)) = line.split_once("]: ") else {
+            return unparsed;
+        };
+        // prefix is now `[12:00:00] [Server thread/INFO`
+        let source = prefix.rsplit_once('[').map(|(_, x)| x).unwrap_or(prefix);
+        let (thread, level) = match source.rsplit_once('/') {
+            Some((thread, level)) => (Some(thread), Some(level)),
+            None => (Some(source), None),
+        };
+        LogLine {
+            thread,
+            level,
+            message,
+        }
+    }
+}
+
+fn parse_player_line(message: &str) -> Option<GameEvent> {
+    if let Some(rest) = message.strip_prefix("UUID of player ") {
+        let (username, uuid) = rest.split_once(" is ")?;
+        return is_valid_username(username).then(|| GameEvent::PlayerUuid {
+            username: username.to_string(),
+            uuid: uuid.trim().to_string(),
+        });
+    }
+    if let Some(username) = message.strip_suffix(" joined the game") {
+        return is_valid_username(username).then(|| GameEvent::PlayerJoined {
+            username: username.to_string(),
+        });
+    }
+    if let Some(username) = message.strip_suffix(" left the game") {
+        return is_valid_username(username).then(|| GameEvent::PlayerLeft {
+            username: username.to_string(),
+        });
+    }
+    if message.contains(" logged in with entity id ") {
+        // Steve[/127.0.0.1:51234] logged in with entity id 123 at (1.0, 2.0, 3.0)
+        let (username, rest) = message.split_once("[/")?;
+        let (address, _) = rest.split_once(']')?;
+        let ip = address
+            .rsplit_once(':')
+            .map(|(ip, _port)| ip)
+            .unwrap_or(address);
+        return is_valid_username(username).then(|| GameEvent::PlayerLoggedIn {
+            username: username.to_string(),
+            ip: ip.to_string(),
+        });
+    }
+    None
+}
+
+fn parse_chat(message: &str) -> Option<GameEvent> {
+    let message = message.strip_prefix("[Not Secure] ").unwrap_or(message);
+    let rest = message.strip_prefix('<')?;
+    let (username, text) = rest.split_once("> ")?;
+    is_valid_username(username).then(|| GameEvent::Chat {
+        username: username.to_string(),
+        message: text.to_string(),
+    })
+}
+
+fn parse_advancement(message: &str) -> Option<GameEvent> {
+    ADVANCEMENT_MESSAGES.iter().find_map(|separator| {
+        let (username, advancement) = message.split_once(separator)?;
+        let advancement = advancement.strip_prefix('[')?.strip_suffix(']')?;
+        is_valid_username(username).then(|| GameEvent::Advancement {
+            username: username.to_string(),
+            advancement: advancement.to_string(),
+        })
+    })
+}
+
+fn parse_death(message: &str) -> Option<GameEvent> {
+    let (username, rest) = message.split_once(' ')?;
+    if !is_valid_username(username) {
+        return None;
+    }
+    DEATH_MESSAGES
+        .iter()
+        .any(|x| rest.starts_with(x))
+        .then(|| GameEvent::Death {
+            username: username.to_string(),
+            message: message.to_string(),
+        })
+}
+
+fn parse_tps(message: &str) -> Option<GameEvent> {
+    let (_, values) = message.split_once("TPS from last 1m, 5m, 15m: ")?;
+    // paper colours the values so drop anything that isn't part of the number
+    let one_minute: String = values
+        .split(',')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|x| x.is_ascii_digit() || *x == '.')
+        .collect();
+    Some(GameEvent::TpsReport {
+        one_minute: one_minute.parse().ok()?,
+    })
+}
+
+impl GameEvent {
+    pub fn parse(line: &str) -> Option<GameEvent> {
+        let line = LogLine::split(line);
+        let message = line.message;
+        if line.level == Some("ERROR") {
+            return Some(GameEvent::Error {
+                message: message.to_string(),
+            });
+        }
+        if let Some(rest) = message.strip_prefix("Can't keep up!") {
+            // Is the server overloaded? Running 2034ms or 40 ticks behind
+            let behind_ms = rest
+                .split_once("Running ")
+                .and_then(|(_, x)| x.split_once("ms"))
+                .and_then(|(x, _)| x.parse().ok());
+            return Some(GameEvent::Overloaded { behind_ms });
+        }
+        if let Some(rest) = message.strip_prefix("Done (") {
+            let startup_secs = rest.split_once("s)!").and_then(|(x, _)| x.parse().ok());
+            return Some(GameEvent::ServerStarted { startup_secs });
+        }
+        if message.starts_with("Stopping server") || message.starts_with("Stopping the server") {
+            return Some(GameEvent::ServerStopping);
+        }
+        if message == "Saved the game" {
+            return Some(GameEvent::Saved);
+        }
+        parse_player_line(message)
+            .or_else(|| parse_chat(message))
+            .or_else(|| parse_advancement(message))
+            .or_else(|| parse_tps(message))
+            .or_else(|| parse_death(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(message: &str) -> Option<GameEvent> {
+        GameEvent::parse(&format!("[12:00:00] [Server thread/INFO]: {}", message))
+    }
+
+    #[test]
+    fn splits_log_lines() {
+        let line = LogLine::split("[12:00:00] [User Authenticator #1/INFO]: hello: there");
+        assert_eq!(line.thread, Some("User Authenticator #1"));
+        assert_eq!(line.level, Some("INFO"));
+        assert_eq!(line.message, "hello: there");
+        let line = LogLine::split("Starting net.minecraft.server.Main");
+        assert_eq!(line.thread, None);
+        assert_eq!(line.message, "Starting net.minecraft.server.Main");
+    }
+
+    #[test]
+    fn parses_player_lines() {
+        assert_eq!(
+            parse("UUID of player Steve is 8667ba71-b85a-4004-af54-457a9734eed7"),
+            Some(GameEvent::PlayerUuid {
+                username: "Steve".to_string(),
+                uuid: "8667ba71-b85a-4004-af54-457a9734eed7".to_string(),
+            })
+        );
+        assert_eq!(
+            parse("Steve[/127.0.0.1:51234] logged in with entity id 123 at (1.0, 2.0, 3.0)"),
+            Some(GameEvent::PlayerLoggedIn {
+                username: "Steve".to_string(),
+                ip: "127.0.0.1".to_string(),
+            })
+        );
+        assert_eq!(
+            parse("Steve joined the game"),
+            Some(GameEvent::PlayerJoined {
+                username: "Steve".to_string(),
+            })
+        );
+        assert_eq!(
+            parse("Steve left the game"),
+            Some(GameEvent::PlayerLeft {
+                username: "Steve".to_string(),
+            })
+        );
+        assert_eq!(parse("two words joined the game"), None);
+    }
+
+    #[test]
+    fn parses_chat() {
+        let chat = Some(GameEvent::Chat {
+            username: "Alex".to_string(),
+            message: "hi <there>".to_string(),
+        });
+        assert_eq!(parse("<Alex> hi <there>"), chat);
+        assert_eq!(parse("[Not Secure] <Alex> hi <there>"), chat);
+    }
+
+    #[test]
+    fn parses_advancements_and_deaths() {
+        assert_eq!(
+            parse("Alex has made the advancement [Stone Age]"),
+            Some(GameEvent::Advancement {
+                username: "Alex".to_string(),
+                advancement: "Stone Age".to_string(),
+            })
+        );
+        assert_eq!(
+            parse("Alex was slain by Zombie"),
+            Some(GameEvent::Death {
+                username: "Alex".to_string(),
+                message: "Alex was slain by Zombie".to_string(),
+            })
+        );
+        assert_eq!(parse("Alex was here"), None);
+    }
+
+    #[test]
+    fn parses_server_lines() {
+        assert_eq!(
+            parse("Done (3.512s)! For help, type \"help\""),
+            Some(GameEvent::ServerStarted {
+                startup_secs: Some(3.512),
+            })
+        );
+        assert_eq!(
+            parse("Stopping the server"),
+            Some(GameEvent::ServerStopping)
+        );
+        assert_eq!(parse("Saved the game"), Some(GameEvent::Saved));
+        assert_eq!(
+            parse("Can't keep up! Is the server overloaded? Running 2034ms or 40 ticks behind"),
+            Some(GameEvent::Overloaded {
+                behind_ms: Some(2034),
+            })
+        );
+        assert_eq!(
+            parse("TPS from last 1m, 5m, 15m: §a19.98, §a20.0, §a20.0"),
+            Some(GameEvent::TpsReport { one_minute: 19.98 })
+        );
+        assert_eq!(
+            GameEvent::parse(
+                "[12:00:00] [Server thread/ERROR]: Encountered an unexpected exception"
+            ),
+            Some(GameEvent::Error {
+                message: "Encountered an unexpected exception".to_string(),
+            })
+        );
+    }
+}
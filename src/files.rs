@@ -0,0 +1,474 @@
+use std::fs::File;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+use tokio::fs;
+use tokio::task::spawn_blocking;
+use zip::{CompressionMethod, ZipArchive};
+
+use crate::archive::{copy_capped, zip_directory};
+use crate::worlds::game_dir;
+
+/// files bigger than this are only offered for download
+pub const MAX_EDIT_BYTES: u64 = 2 << 20;
+/// uploads are cut off once they get past this
+pub const MAX_UPLOAD_BYTES: u64 = 1 << 30;
+/// zips that unpack to more than this are refused
+pub const MAX_UNZIP_BYTES: u64 = 32 << 30;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileEntry {
+    pub name: String,
+    /// relative to the server's directory, with forward slashes
+    pub path: String,
+    pub is_dir: bool,
+    pub size_bytes: u64,
+    /// unix seconds
+    pub modified: u64,
+}
+
+/// a path relative to the server's directory, without empty, `.` or `..` parts
+pub fn clean_path(path: &str) -> Result<PathBuf, String> {
+    let mut clean = PathBuf::new();
+    for component in Path::new(path.trim_matches('/')).components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {}
+            _ => return Err("invalid path".to_string()),
+        }
+    }
+    Ok(clean)
+}
+
+/// forward slashed form of a relative path for links and forms
+fn display_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+pub fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', '\0'])
+}
+
+/// turns a path from the browser into one on disk. whatever it names, or its parent
+/// when it doesn't exist yet, has to resolve inside the server's directory after
+/// following symlinks, so neither `..` nor a link can reach anything outside of it
+pub async fn resolve(server_id: usize, path: &str) -> Result<PathBuf, String> {
+    resolve_in(&game_dir(server_id), path).await
+}
+
+async fn resolve_in(dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let root = fs::canonicalize(dir)
+        .await
+        .map_err(|_| "the server has no files yet".to_string())?;
+    let relative = clean_path(path)?;
+    let full = root.join(&relative);
+    if fs::symlink_metadata(&full).await.is_ok() {
+        let canonical = fs::canonicalize(&full)
+            .await
+            .map_err(|err| format!("failed to resolve path: {}", err))?;
+        if !canonical.starts_with(&root) {
+            return Err("path leads outside of the server".to_string());
+        }
+        return Ok(full);
+    }
+    let (Some(parent), Some(name)) = (full.parent(), full.file_name()) else {
+        return Err("invalid path".to_string());
+    };
+    let parent = fs::canonicalize(parent)
+        .await
+        .map_err(|_| "parent directory doesn't exist".to_string())?;
+    if !parent.starts_with(&root) {
+        return Err("path leads outside of the server".to_string());
+    }
+    Ok(parent.join(name))
+}
+
+/// the server's directory itself can't be renamed or deleted
+async fn resolve_inner(server_id: usize, path: &str) -> Result<PathBuf, String> {
+    if clean_path(path)?.as_os_str().is_empty() {
+        return Err("can't change the server's directory itself".to_string());
+    }
+    resolve(server_id, path).await
+}
+
+/// like `resolve_inner` but without following a symlink at the end, for renaming and
+/// deleting the link itself wherever it points
+async fn resolve_entry(server_id: usize, path: &str) -> Result<PathBuf, String> {
+    let relative = clean_path(path)?;
+    let (Some(parent), Some(name)) = (relative.parent(), relative.file_name()) else {
+        return Err("can't change the server's directory itself".to_string());
+    };
+    let parent = resolve(server_id, &display_path(parent)).await?;
+    Ok(parent.join(name))
+}
+
+/// whether anything under `dir` is a symlink leading outside of `root`. blocking
+fn links_outside(dir: &Path, root: &Path) -> io::Result<bool> {
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                match std::fs::canonicalize(entry.path()) {
+                    Ok(target) if target.starts_with(root) => {}
+                    _ => return Ok(true),
+                }
+            } else if file_type.is_dir() {
+                stack.push(entry.path());
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// the entries of a directory, directories first
+pub async fn list_dir(server_id: usize, path: &str) -> Result<Vec<FileEntry>, String> {
+    let dir = resolve(server_id, path).await?;
+    let relative = clean_path(path)?;
+    let mut entries = fs::read_dir(&dir)
+        .await
+        .map_err(|err| format!("failed to open directory: {}", err))?;
+    let mut files = vec![];
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        files.push(FileEntry {
+            path: display_path(&relative.join(&name)),
+            name,
+            is_dir: metadata.is_dir(),
+            size_bytes: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+                .map(|x| x.as_secs())
+                .unwrap_or(0),
+        });
+    }
+    files.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(files)
+}
+
+pub async fn is_dir(server_id: usize, path: &str) -> bool {
+    match resolve(server_id, path).await {
+        Ok(path) => fs::metadata(path).await.is_ok_and(|x| x.is_dir()),
+        Err(_) => false,
+    }
+}
+
+/// a file's contents if it's small enough and utf-8, `None` when it isn't text
+pub async fn read_text_file(server_id: usize, path: &str) -> Result<Option<String>, String> {
+    let file = resolve(server_id, path).await?;
+    let metadata = fs::metadata(&file)
+        .await
+        .map_err(|_| "file not found".to_string())?;
+    if !metadata.is_file() {
+        return Err("not a file".to_string());
+    }
+    if metadata.len() > MAX_EDIT_BYTES {
+        return Ok(None);
+    }
+    let contents = fs::read(&file)
+        .await
+        .map_err(|err| format!("failed to read file: {}", err))?;
+    Ok(String::from_utf8(contents).ok())
+}
+
+/// writes next to the file and moves it over so a failed save keeps the old contents
+pub async fn write_text_file(server_id: usize, path: &str, contents: &str) -> Result<(), String> {
+    if contents.len() as u64 > MAX_EDIT_BYTES {
+        return Err("file is too big to edit here".to_string());
+    }
+    let file = resolve_inner(server_id, path).await?;
+    if fs::metadata(&file).await.is_ok_and(|x| x.is_dir()) {
+        return Err("not a file".to_string());
+    }
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let saving = file.with_file_name(format!(".{}.saving", name));
+    fs::write(&saving, contents)
+        .await
+        .map_err(|err| format!("failed to write file: {}", err))?;
+    fs::rename(&saving, &file)
+        .await
+        .map_err(|err| format!("failed to move file into place: {}", err))
+}
+
+/// where an upload named `name` is written to before it's complete, and where it goes
+pub async fn upload_paths(
+    server_id: usize,
+    dir: &str,
+    name: &str,
+) -> Result<(PathBuf, PathBuf), String> {
+    if !is_valid_file_name(name) {
+        return Err("invalid file name".to_string());
+    }
+    let dir = resolve(server_id, dir).await?;
+    if !fs::metadata(&dir).await.is_ok_and(|x| x.is_dir()) {
+        return Err("directory not found".to_string());
+    }
+    Ok((dir.join(format!(".{}.uploading", name)), dir.join(name)))
+}
+
+pub async fn create_dir(server_id: usize, dir: &str, name: &str) -> Result<(), String> {
+    if !is_valid_file_name(name) {
+        return Err("invalid directory name".to_string());
+    }
+    let parent = resolve(server_id, dir).await?;
+    fs::create_dir(parent.join(name))
+        .await
+        .map_err(|err| format!("failed to create directory: {}", err))
+}
+
+/// renames a file or directory without moving it to another directory
+pub async fn rename_path(server_id: usize, path: &str, name: &str) -> Result<(), String> {
+    if !is_valid_file_name(name) {
+        return Err("invalid name".to_string());
+    }
+    let from = resolve_entry(server_id, path).await?;
+    let to = from.with_file_name(name);
+    if fs::symlink_metadata(&to).await.is_ok() {
+        return Err(format!("{} already exists", name));
+    }
+    fs::rename(&from, &to)
+        .await
+        .map_err(|err| format!("failed to rename: {}", err))
+}
+
+/// deletes a file or a whole directory, a symlink is removed without touching what it
+/// points at
+pub async fn delete_path(server_id: usize, path: &str) -> Result<(), String> {
+    let path = resolve_entry(server_id, path).await?;
+    let metadata = fs::symlink_metadata(&path)
+        .await
+        .map_err(|_| "file not found".to_string())?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(&path).await
+    } else {
+        fs::remove_file(&path).await
+    }
+    .map_err(|err| format!("failed to delete: {}", err))
+}
+
+/// zips a file or directory into `{name}.zip` beside it
+pub async fn zip_path(server_id: usize, path: &str) -> Result<(), String> {
+    let source = resolve_inner(server_id, path).await?;
+    let metadata = fs::metadata(&source)
+        .await
+        .map_err(|_| "file not found".to_string())?;
+    let name = source
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let archive = source.with_file_name(format!("{}.zip", name));
+    if fs::symlink_metadata(&archive).await.is_ok() {
+        return Err(format!("{}.zip already exists", name));
+    }
+    let root = fs::canonicalize(game_dir(server_id))
+        .await
+        .map_err(|err| err.to_string())?;
+    let destination = archive.clone();
+    let result = spawn_blocking(move || {
+        if metadata.is_dir() {
+            // the zip would hold whatever the link points at
+            if links_outside(&source, &root)? {
+                return Err(io::Error::other(
+                    "it contains a link leading outside of the server",
+                ));
+            }
+            zip_directory(
+                &source,
+                &destination,
+                Path::new(&name),
+                CompressionMethod::Deflated,
+            )
+        } else {
+            zip_file(&source, &destination, &name)
+        }
+    })
+    .await
+    .map_err(|err| err.to_string())?;
+    if let Err(err) = result {
+        let _ = fs::remove_file(&archive).await;
+        return Err(format!("failed to zip: {}", err));
+    }
+    Ok(())
+}
+
+/// blocking
+fn zip_file(source: &Path, archive: &Path, name: &str) -> io::Result<()> {
+    let mut writer = zip::ZipWriter::new(File::create(archive)?);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+    writer.start_file(name, options)?;
+    io::copy(&mut File::open(source)?, &mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// unpacks entries into `destination`, skipping any that would land outside of it.
+/// blocking
+fn unpack(archive: &Path, destination: &Path) -> Result<(), String> {
+    let file = File::open(archive).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|err| format!("not a zip file: {}", err))?;
+    let mut total: u64 = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+        let Some(relative) = entry.enclosed_name() else {
+            continue;
+        };
+        let target = destination.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target).map_err(|err| err.to_string())?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let mut out = File::create(&target).map_err(|err| err.to_string())?;
+        if !copy_capped(&mut entry, &mut out, &mut total, MAX_UNZIP_BYTES)
+            .map_err(|err| err.to_string())?
+        {
+            return Err("zip unpacks to too much".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// unpacks a zip into the directory it's in. nothing there is replaced, the whole
+/// unzip fails instead
+pub async fn unzip_path(server_id: usize, path: &str) -> Result<(), String> {
+    let archive = resolve_inner(server_id, path).await?;
+    if !fs::metadata(&archive).await.is_ok_and(|x| x.is_file()) {
+        return Err("file not found".to_string());
+    }
+    let stem = archive
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    // unpack beside it first so a failed unzip doesn't leave half the files behind
+    let unpacking = archive.with_file_name(format!(".{}.unpacking", stem));
+    let _ = fs::remove_dir_all(&unpacking).await;
+    let result = {
+        let archive = archive.clone();
+        let unpacking = unpacking.clone();
+        spawn_blocking(move || {
+            std::fs::create_dir(&unpacking).map_err(|err| err.to_string())?;
+            unpack(&archive, &unpacking)
+        })
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|x| x)
+    };
+    let result = match result {
+        Ok(()) => move_into(&unpacking, archive.parent().unwrap_or(Path::new("."))).await,
+        Err(err) => Err(err),
+    };
+    let _ = fs::remove_dir_all(&unpacking).await;
+    result
+}
+
+/// moves everything at the top of `from` into `to` if none of it is there already
+async fn move_into(from: &Path, to: &Path) -> Result<(), String> {
+    let mut names = vec![];
+    let mut entries = fs::read_dir(from).await.map_err(|err| err.to_string())?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        if fs::symlink_metadata(to.join(&name)).await.is_ok() {
+            return Err(format!("{} already exists", name.to_string_lossy()));
+        }
+        names.push(name);
+    }
+    for name in names {
+        fs::rename(from.join(&name), to.join(&name))
+            .await
+            .map_err(|err| format!("failed to move files into place: {}", err))?;
+    }
+    Ok(())
+}
+
+/// the parent of a relative path, empty at the top
+pub fn parent_path(path: &str) -> String {
+    clean_path(path)
+        .ok()
+        .and_then(|x| x.parent().map(display_path))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_paths() {
+        assert_eq!(clean_path("").unwrap(), PathBuf::new());
+        assert_eq!(clean_path("/").unwrap(), PathBuf::new());
+        assert_eq!(
+            clean_path("/world/./region/").unwrap(),
+            PathBuf::from("world/region")
+        );
+        assert!(clean_path("..").is_err());
+        assert!(clean_path("world/../../etc").is_err());
+        assert!(clean_path("world/..").is_err());
+        // leading slashes are trimmed rather than making it absolute
+        assert_eq!(
+            clean_path("//etc/passwd").unwrap(),
+            PathBuf::from("etc/passwd")
+        );
+    }
+
+    #[test]
+    fn checks_file_names() {
+        assert!(is_valid_file_name("server.properties"));
+        assert!(is_valid_file_name(".hidden"));
+        for name in ["", ".", "..", "a/b", "a\\b", "a\0b"] {
+            assert!(!is_valid_file_name(name), "{:?}", name);
+        }
+        assert!(!is_valid_file_name(&"a".repeat(256)));
+    }
+
+    #[tokio::test]
+    async fn resolves_inside_the_server_only() {
+        let root = std::env::temp_dir().join(format!("mcmanage-files-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let game = root.join("game");
+        std::fs::create_dir_all(game.join("world")).unwrap();
+        std::fs::create_dir_all(root.join("secret")).unwrap();
+        std::fs::write(root.join("secret/key"), "x").unwrap();
+        std::os::unix::fs::symlink(root.join("secret"), game.join("out")).unwrap();
+        std::os::unix::fs::symlink(game.join("world"), game.join("in")).unwrap();
+        let game = std::fs::canonicalize(&game).unwrap();
+
+        assert_eq!(
+            resolve_in(&game, "world").await.unwrap(),
+            game.join("world")
+        );
+        assert_eq!(resolve_in(&game, "").await.unwrap(), game);
+        // doesn't exist yet, so only its parent is checked
+        assert_eq!(
+            resolve_in(&game, "world/new.txt").await.unwrap(),
+            game.join("world/new.txt")
+        );
+        assert!(resolve_in(&game, "../secret/key").await.is_err());
+        assert!(resolve_in(&game, "world/../../secret").await.is_err());
+        assert!(resolve_in(&game, "missing/new.txt").await.is_err());
+        // links are fine as long as they stay inside
+        assert_eq!(resolve_in(&game, "in").await.unwrap(), game.join("in"));
+        assert!(resolve_in(&game, "in/new.txt").await.is_ok());
+        assert!(resolve_in(&game, "out").await.is_err());
+        assert!(resolve_in(&game, "out/key").await.is_err());
+        assert!(resolve_in(&game, "out/new.txt").await.is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
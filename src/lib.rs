@@ -1,5 +1,25 @@
+pub mod access;
+pub mod archive;
+pub mod backups;
+pub mod chunks;
 pub mod configuration;
 pub mod downloader;
+pub mod events;
+pub mod files;
+pub mod loaders;
+pub mod metrics;
+pub mod mods;
+pub mod players;
+pub mod ports;
+pub mod properties;
+pub mod protocol;
+pub mod proxy;
+pub mod scheduler;
 pub mod server_runner;
+pub mod sleeper;
+pub mod targets;
+pub mod upgrades;
 pub mod versions;
+pub mod webhooks;
 pub mod webui;
+pub mod worlds;
@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+use crate::configuration::ServerType;
+use crate::loaders::get_json;
+
+pub const META_ENDPOINT: &str = "https://meta.fabricmc.net/v2";
+/// how long the loader list for the new server page is reused
+const LOADERS_TTL: Duration = Duration::from_secs(600);
+/// the new server page doesn't wait on fabric any longer than this
+const LOADERS_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FabricVersion {
+    pub version: String,
+    pub stable: bool,
+}
+
+/// one entry of the loaders available for a game version
+#[derive(Debug, Clone, Deserialize)]
+struct GameLoader {
+    loader: FabricVersion,
+}
+
+async fn get<T: for<'a> Deserialize<'a>>(path: &str) -> Result<T, String> {
+    get_json("fabric", &format!("{}{}", META_ENDPOINT, path)).await
+}
+
+/// every loader version, newest first
+pub async fn loader_versions() -> Result<Vec<FabricVersion>, String> {
+    get("/versions/loader").await
+}
+
+lazy_static! {
+    /// also keeps two page loads from asking fabric at the same time
+    static ref LOADERS: Mutex<Option<(Instant, Vec<FabricVersion>)>> = Mutex::new(None);
+}
+
+/// loader versions to pick from, cached for a while. when fabric doesn't answer in time
+/// the last list is used, or none at all
+pub async fn cached_loader_versions() -> Vec<FabricVersion> {
+    let mut cached = LOADERS.lock().await;
+    if let Some((fetched, versions)) = &*cached
+        && fetched.elapsed() < LOADERS_TTL
+    {
+        return versions.clone();
+    }
+    let err = match timeout(LOADERS_TIMEOUT, loader_versions()).await {
+        Ok(Ok(versions)) => {
+            *cached = Some((Instant::now(), versions.clone()));
+            return versions;
+        }
+        Ok(Err(err)) => err,
+        Err(_) => "fabric didn't answer in time".to_string(),
+    };
+    println!("could not list fabric loaders: {}", err);
+    cached
+        .as_ref()
+        .map(|(_, versions)| versions.clone())
+        .unwrap_or_default()
+}
+
+/// picks a loader for the game version, the newest stable one unless asked for a
+/// specific one, and the newest stable installer to build the launcher with
+pub async fn resolve(mc_version: &str, loader_version: Option<&str>) -> Result<ServerType, String> {
+    let loaders: Vec<GameLoader> = get(&format!("/versions/loader/{}", mc_version)).await?;
+    if loaders.is_empty() {
+        return Err(format!("fabric doesn't support {}", mc_version));
+    }
+    let loader = match loader_version {
+        Some(wanted) => loaders
+            .iter()
+            .find(|x| x.loader.version == wanted)
+            .ok_or(format!(
+                "fabric loader {} isn't available for {}",
+                wanted, mc_version
+            ))?,
+        None => loaders
+            .iter()
+            .find(|x| x.loader.stable)
+            .unwrap_or(&loaders[0]),
+    };
+    let installers: Vec<FabricVersion> = get("/versions/installer").await?;
+    let installer = installers
+        .iter()
+        .find(|x| x.stable)
+        .or(installers.first())
+        .ok_or("fabric has no installer versions")?;
+    Ok(ServerType::Fabric {
+        loader_version: loader.loader.version.clone(),
+        installer_version: installer.version.clone(),
+    })
+}
+
+/// the server launcher jar, it fetches the loader's libraries on first start
+pub fn server_jar_url(mc_version: &str, loader_version: &str, installer_version: &str) -> String {
+    format!(
+        "{}/versions/loader/{}/{}/{}/server/jar",
+        META_ENDPOINT, mc_version, loader_version, installer_version
+    )
+}
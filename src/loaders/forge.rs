@@ -0,0 +1,331 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+
+use crate::configuration::{Server, ServerType};
+use crate::loaders::get_json;
+
+pub const FORGE_MAVEN: &str = "https://maven.minecraftforge.net/net/minecraftforge/forge";
+pub const NEOFORGE_MAVEN: &str = "https://maven.neoforged.net/releases/net/neoforged/neoforge";
+const FORGE_PROMOTIONS: &str =
+    "https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json";
+/// lines of installer output kept for the install page
+const OUTPUT_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForgeKind {
+    Forge,
+    NeoForge,
+}
+
+impl ForgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForgeKind::Forge => "forge",
+            ForgeKind::NeoForge => "neoforge",
+        }
+    }
+    fn maven(&self) -> &'static str {
+        match self {
+            ForgeKind::Forge => FORGE_MAVEN,
+            ForgeKind::NeoForge => NEOFORGE_MAVEN,
+        }
+    }
+    /// the version as it's named in the maven, forge puts the game version in front
+    fn artifact_version(&self, mc_version: &str, version: &str) -> String {
+        match self {
+            ForgeKind::Forge => format!("{}-{}", mc_version, version),
+            ForgeKind::NeoForge => version.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Promotions {
+    promos: HashMap<String, String>,
+}
+
+/// numeric parts of a version for ordering, 47.3.10 after 47.3.9
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split(|x: char| !x.is_ascii_digit())
+        .filter_map(|x| x.parse().ok())
+        .collect()
+}
+
+/// neoforge drops the leading 1 of the game version, 1.21.1 is 21.1.x and 1.21 is 21.0.x
+fn neoforge_prefix(mc_version: &str) -> String {
+    let mut parts = mc_version.split('.');
+    let first = parts.next().unwrap_or_default();
+    let parts: Vec<&str> = if first == "1" {
+        parts.collect()
+    } else {
+        mc_version.split('.').collect()
+    };
+    match parts.as_slice() {
+        [major] => format!("{}.0.", major),
+        [major, minor, ..] => format!("{}.{}.", major, minor),
+        [] => String::new(),
+    }
+}
+
+/// every version of the loader for a game version, newest first
+pub async fn versions(kind: ForgeKind, mc_version: &str) -> Result<Vec<String>, String> {
+    let metadata = reqwest::get(format!("{}/maven-metadata.xml", kind.maven()))
+        .await
+        .and_then(|x| x.error_for_status())
+        .map_err(|err| format!("failed to communicate with {}: {}", kind.name(), err))?
+        .text()
+        .await
+        .map_err(|err| format!("invalid response from {}: {}", kind.name(), err))?;
+    let mut versions: Vec<String> = metadata
+        .split("<version>")
+        .skip(1)
+        .filter_map(|x| {
+            x.split_once("</version>")
+                .map(|(version, _)| version.trim())
+        })
+        .filter_map(|version| match kind {
+            ForgeKind::Forge => version
+                .strip_prefix(&format!("{}-", mc_version))
+                .map(str::to_string),
+            ForgeKind::NeoForge => version
+                .starts_with(&neoforge_prefix(mc_version))
+                .then(|| version.to_string()),
+        })
+        .collect();
+    versions.sort_by_key(|x| std::cmp::Reverse(version_key(x)));
+    Ok(versions)
+}
+
+/// checks the version exists for the game version, the recommended one when none is
+/// asked for
+pub async fn resolve(
+    kind: ForgeKind,
+    mc_version: &str,
+    version: Option<&str>,
+) -> Result<ServerType, String> {
+    let versions = versions(kind, mc_version).await?;
+    if versions.is_empty() {
+        return Err(format!("{} doesn't support {}", kind.name(), mc_version));
+    }
+    let version = match version {
+        Some(wanted) if versions.iter().any(|x| x == wanted) => wanted.to_string(),
+        Some(wanted) => {
+            return Err(format!(
+                "{} {} isn't available for {}",
+                kind.name(),
+                wanted,
+                mc_version
+            ));
+        }
+        None => match kind {
+            ForgeKind::Forge => {
+                let promotions: Promotions = get_json("forge", FORGE_PROMOTIONS).await?;
+                promotions
+                    .promos
+                    .get(&format!("{}-recommended", mc_version))
+                    .or(promotions.promos.get(&format!("{}-latest", mc_version)))
+                    .cloned()
+                    .unwrap_or(versions[0].clone())
+            }
+            ForgeKind::NeoForge => versions
+                .iter()
+                .find(|x| !x.contains("beta"))
+                .unwrap_or(&versions[0])
+                .clone(),
+        },
+    };
+    Ok(match kind {
+        ForgeKind::Forge => ServerType::Forge { version },
+        ForgeKind::NeoForge => ServerType::NeoForge { version },
+    })
+}
+
+pub fn installer_url(kind: ForgeKind, mc_version: &str, version: &str) -> String {
+    let artifact = kind.artifact_version(mc_version, version);
+    format!(
+        "{}/{}/{}-{}-installer.jar",
+        kind.maven(),
+        artifact,
+        kind.name(),
+        artifact
+    )
+}
+
+/// the java arguments file the installer writes, relative to the server's directory
+pub fn args_file(kind: ForgeKind, mc_version: &str, version: &str) -> String {
+    let group = match kind {
+        ForgeKind::Forge => "net/minecraftforge/forge",
+        ForgeKind::NeoForge => "net/neoforged/neoforge",
+    };
+    let file = if cfg!(windows) {
+        "win_args.txt"
+    } else {
+        "unix_args.txt"
+    };
+    format!(
+        "libraries/{}/{}/{}",
+        group,
+        kind.artifact_version(mc_version, version),
+        file
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallState {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// an installer run for one server and what it printed
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallJob {
+    pub description: String,
+    pub state: InstallState,
+    pub error: Option<String>,
+    pub output: VecDeque<String>,
+}
+
+lazy_static! {
+    static ref INSTALL_JOBS: Mutex<HashMap<usize, InstallJob>> = Mutex::new(HashMap::new());
+}
+
+/// the last installer run for a server since mcmanage started
+pub fn install_job(server_id: usize) -> Option<InstallJob> {
+    INSTALL_JOBS.lock().unwrap().get(&server_id).cloned()
+}
+
+pub fn is_installing(server_id: usize) -> bool {
+    install_job(server_id).is_some_and(|x| x.state == InstallState::Running)
+}
+
+fn record_line(server_id: usize, line: String) {
+    if let Some(job) = INSTALL_JOBS.lock().unwrap().get_mut(&server_id) {
+        if job.output.len() >= OUTPUT_LINES {
+            job.output.pop_front();
+        }
+        job.output.push_back(line);
+    }
+}
+
+async fn capture(server_id: usize, stream: impl AsyncRead + Unpin) {
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        record_line(server_id, line);
+    }
+}
+
+/// runs the installer with `--installServer` in the server's directory, which fetches
+/// the game and the loader's libraries and writes the arguments file it's started with.
+/// the installer has to be downloaded already
+pub async fn run_installer(server: &Server) -> Result<(), String> {
+    let Some((kind, version)) = server.server_type.forge() else {
+        return Err("only forge and neoforge servers have an installer".to_string());
+    };
+    {
+        let mut jobs = INSTALL_JOBS.lock().unwrap();
+        if jobs
+            .get(&server.id)
+            .is_some_and(|x| x.state == InstallState::Running)
+        {
+            return Err("the installer is already running".to_string());
+        }
+        jobs.insert(
+            server.id,
+            InstallJob {
+                description: format!("{} {} for {}", kind.name(), version, server.mc_version_id),
+                state: InstallState::Running,
+                error: None,
+                output: VecDeque::new(),
+            },
+        );
+    }
+    let result = install(server, kind, version).await;
+    let mut jobs = INSTALL_JOBS.lock().unwrap();
+    if let Some(job) = jobs.get_mut(&server.id) {
+        match &result {
+            Ok(()) => job.state = InstallState::Succeeded,
+            Err(err) => {
+                job.state = InstallState::Failed;
+                job.error = Some(err.clone());
+            }
+        }
+    }
+    result
+}
+
+async fn install(server: &Server, kind: ForgeKind, version: &str) -> Result<(), String> {
+    let dir = format!("./servers/{}/game", server.id);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|err| format!("could not create server directory: {}", err))?;
+    let installer = format!(
+        "../../../server_versions/{}",
+        server.server_type.jar(&server.mc_version_id)
+    );
+    let mut child = Command::new("java")
+        .current_dir(&dir)
+        .arg("-jar")
+        .arg(&installer)
+        .arg("--installServer")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("could not run the installer: {}", err))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let ((), (), status) = tokio::join!(
+        capture(server.id, stdout),
+        capture(server.id, stderr),
+        child.wait()
+    );
+    let status = status.map_err(|err| format!("installer failed: {}", err))?;
+    if !status.success() {
+        return Err(format!("installer exited with {}", status));
+    }
+    let args = Path::new(&dir).join(args_file(kind, &server.mc_version_id, version));
+    if !args.is_file() {
+        return Err(format!(
+            "the installer didn't write {}, only versions for 1.17 and newer are supported",
+            args.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_game_versions_to_neoforge() {
+        assert_eq!(neoforge_prefix("1.21.1"), "21.1.");
+        assert_eq!(neoforge_prefix("1.21"), "21.0.");
+        assert_eq!(neoforge_prefix("1.20.4"), "20.4.");
+        // versions numbered after the year don't have the leading 1
+        assert_eq!(neoforge_prefix("26.1"), "26.1.");
+    }
+
+    #[test]
+    fn orders_versions_numerically() {
+        assert!(version_key("21.1.100") > version_key("21.1.77"));
+        assert!(version_key("1.20.1-47.3.10") > version_key("1.20.1-47.3.9"));
+        assert!(version_key("21.0.1") > version_key("21.0.0-beta"));
+        assert_eq!(version_key("21.0.0-beta"), vec![21, 0, 0]);
+        let mut versions = vec!["47.2.0", "47.10.1", "47.3.12", "47.3.2"];
+        versions.sort_by_key(|x| std::cmp::Reverse(version_key(x)));
+        assert_eq!(versions, ["47.10.1", "47.3.12", "47.3.2", "47.2.0"]);
+    }
+}
@@ -0,0 +1,242 @@
+use std::path::Path;
+use std::time::Instant;
+
+use md5::Md5;
+use serde::Deserialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio_stream::StreamExt;
+
+use crate::configuration::{ConfigurationManager, Server, ServerType};
+use crate::loaders::forge::{ForgeKind, is_installing};
+use crate::metrics::DOWNLOAD_DURATIONS;
+use crate::server_runner::ServerRunnerHandle;
+
+pub mod fabric;
+pub mod forge;
+pub mod paper;
+pub mod purpur;
+
+/// what a download has to hash to, as lowercase hex
+pub enum Checksum {
+    None,
+    Sha1(String),
+    Sha256(String),
+    Sha512(String),
+    Md5(String),
+}
+
+/// a json document from one of the loader apis, `who` names it in errors
+pub async fn get_json<T: for<'a> Deserialize<'a>>(who: &str, url: &str) -> Result<T, String> {
+    reqwest::get(url)
+        .await
+        .and_then(|x| x.error_for_status())
+        .map_err(|err| format!("failed to communicate with {}: {}", who, err))?
+        .json::<T>()
+        .await
+        .map_err(|err| format!("invalid response from {}: {}", who, err))
+}
+
+/// downloads next to `path` and moves it over once complete and matching the
+/// checksum, so a failed download doesn't leave a broken jar that looks like it's there
+pub async fn download(url: &str, path: &Path, checksum: Checksum) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| format!("could not create {}: {}", parent.display(), err))?;
+    }
+    println!("Downloading {}...", url);
+    let started = Instant::now();
+    let partial = path.with_extension("partial");
+    let result = async {
+        let response = reqwest::get(url)
+            .await
+            .and_then(|x| x.error_for_status())
+            .map_err(|err| err.to_string())?;
+        let mut file = fs::File::create(&partial)
+            .await
+            .map_err(|err| err.to_string())?;
+        let mut sha1 = matches!(checksum, Checksum::Sha1(_)).then(Sha1::new);
+        let mut sha256 = matches!(checksum, Checksum::Sha256(_)).then(Sha256::new);
+        let mut sha512 = matches!(checksum, Checksum::Sha512(_)).then(Sha512::new);
+        let mut md5 = matches!(checksum, Checksum::Md5(_)).then(Md5::new);
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|err| err.to_string())?;
+            if let Some(sha1) = &mut sha1 {
+                sha1.update(&chunk);
+            }
+            if let Some(sha256) = &mut sha256 {
+                sha256.update(&chunk);
+            }
+            if let Some(sha512) = &mut sha512 {
+                sha512.update(&chunk);
+            }
+            if let Some(md5) = &mut md5 {
+                md5.update(&chunk);
+            }
+            file.write_all(&chunk)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        file.flush().await.map_err(|err| err.to_string())?;
+        let (expected, actual) = match &checksum {
+            Checksum::None => (String::new(), String::new()),
+            Checksum::Sha1(expected) => (
+                expected.to_lowercase(),
+                hex::encode(sha1.unwrap_or_default().finalize()),
+            ),
+            Checksum::Sha256(expected) => (
+                expected.to_lowercase(),
+                hex::encode(sha256.unwrap_or_default().finalize()),
+            ),
+            Checksum::Sha512(expected) => (
+                expected.to_lowercase(),
+                hex::encode(sha512.unwrap_or_default().finalize()),
+            ),
+            Checksum::Md5(expected) => (
+                expected.to_lowercase(),
+                hex::encode(md5.unwrap_or_default().finalize()),
+            ),
+        };
+        if expected != actual {
+            return Err(format!("checksum is {} instead of {}", actual, expected));
+        }
+        fs::rename(&partial, path)
+            .await
+            .map_err(|err| err.to_string())
+    }
+    .await;
+    if let Err(err) = result {
+        let _ = fs::remove_file(&partial).await;
+        return Err(format!("failed to download {}: {}", url, err));
+    }
+    DOWNLOAD_DURATIONS.lock().unwrap().record(started.elapsed());
+    println!("Downloaded {}", url);
+    Ok(())
+}
+
+/// fetches what a server type runs on top of the vanilla jar
+pub async fn ensure_available(server_type: &ServerType, mc_version: &str) -> Result<(), String> {
+    let jar = Path::new("./server_versions").join(server_type.jar(mc_version));
+    if jar.is_file() {
+        return Ok(());
+    }
+    match server_type {
+        ServerType::Vanilla => Ok(()),
+        ServerType::Fabric {
+            loader_version,
+            installer_version,
+        } => {
+            let url = fabric::server_jar_url(mc_version, loader_version, installer_version);
+            download(&url, &jar, Checksum::None).await
+        }
+        ServerType::Paper { build } => {
+            let (url, sha256) = paper::download_info(mc_version, *build).await?;
+            download(&url, &jar, Checksum::Sha256(sha256)).await
+        }
+        ServerType::Purpur { build } => {
+            let (url, md5) = purpur::download_info(mc_version, *build).await?;
+            download(&url, &jar, Checksum::Md5(md5)).await
+        }
+        ServerType::Forge { version } => {
+            download_installer(ForgeKind::Forge, mc_version, version, &jar).await
+        }
+        ServerType::NeoForge { version } => {
+            download_installer(ForgeKind::NeoForge, mc_version, version, &jar).await
+        }
+    }
+}
+
+/// the mavens publish an md5 beside every file
+async fn download_installer(
+    kind: ForgeKind,
+    mc_version: &str,
+    version: &str,
+    jar: &Path,
+) -> Result<(), String> {
+    let url = forge::installer_url(kind, mc_version, version);
+    let md5 = reqwest::get(format!("{}.md5", url))
+        .await
+        .and_then(|x| x.error_for_status())
+        .map_err(|err| format!("failed to communicate with {}: {}", kind.name(), err))?
+        .text()
+        .await
+        .map_err(|err| format!("invalid response from {}: {}", kind.name(), err))?;
+    let md5 = md5
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    download(&url, jar, Checksum::Md5(md5)).await
+}
+
+/// writes whatever the server type reads at startup into the server's directory, fails
+/// if it can't start yet
+pub async fn prepare(server: &Server) -> std::io::Result<()> {
+    if let Some((kind, version)) = server.server_type.forge() {
+        let args = Path::new("./servers")
+            .join(server.id.to_string())
+            .join("game")
+            .join(forge::args_file(kind, &server.mc_version_id, version));
+        if is_installing(server.id) || !args.is_file() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "the installer hasn't finished",
+            ));
+        }
+        return Ok(());
+    }
+    match &server.server_type {
+        // points the launcher at the vanilla jar that's already here instead of having
+        // every server download its own
+        ServerType::Fabric { .. } => {
+            fs::write(
+                format!(
+                    "./servers/{}/game/fabric-server-launcher.properties",
+                    server.id
+                ),
+                format!(
+                    "serverJar=../../../server_versions/{}\n",
+                    ServerType::Vanilla.jar(&server.mc_version_id)
+                ),
+            )
+            .await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// moves a paper or purpur server to the newest build of its game version, returning
+/// the build it's on now or none when it already was on the newest. a running server
+/// keeps the old build until it's restarted
+pub async fn update_to_latest_build(
+    config: &ConfigurationManager,
+    runner_handle: &ServerRunnerHandle,
+    server: &Server,
+) -> Result<Option<u32>, String> {
+    let mc_version = &server.mc_version_id;
+    let (current, latest, updated) = match &server.server_type {
+        ServerType::Paper { build } => {
+            let builds = paper::builds(mc_version).await?;
+            let latest = paper::latest(&builds)
+                .ok_or(format!("paper has no builds for {}", mc_version))?
+                .build;
+            (*build, latest, ServerType::Paper { build: latest })
+        }
+        ServerType::Purpur { build } => {
+            let (_, latest) = purpur::builds(mc_version).await?;
+            (*build, latest, ServerType::Purpur { build: latest })
+        }
+        _ => return Err("only paper and purpur servers have builds".to_string()),
+    };
+    if latest <= current {
+        return Ok(None);
+    }
+    ensure_available(&updated, mc_version).await?;
+    config.set_server_type(server.id, updated).await;
+    runner_handle.require_restart(server.id);
+    Ok(Some(latest))
+}
@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+use crate::configuration::ServerType;
+use crate::loaders::get_json;
+
+pub const API_ENDPOINT: &str = "https://api.papermc.io/v2/projects/paper";
+
+#[derive(Debug, Clone, Deserialize)]
+struct BuildList {
+    builds: Vec<PaperBuild>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaperBuild {
+    pub build: u32,
+    /// "default" for regular builds, "experimental" for early ones of a new version
+    pub channel: String,
+    downloads: PaperDownloads,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PaperDownloads {
+    application: PaperDownload,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PaperDownload {
+    name: String,
+    sha256: String,
+}
+
+/// every build for a game version, oldest first
+pub async fn builds(mc_version: &str) -> Result<Vec<PaperBuild>, String> {
+    get_json(
+        "paper",
+        &format!("{}/versions/{}/builds", API_ENDPOINT, mc_version),
+    )
+    .await
+    .map(|x: BuildList| x.builds)
+    .map_err(|err| format!("paper doesn't have {}: {}", mc_version, err))
+}
+
+/// the newest build that isn't experimental, or the newest one when they all are
+pub fn latest(builds: &[PaperBuild]) -> Option<&PaperBuild> {
+    builds
+        .iter()
+        .rev()
+        .find(|x| x.channel == "default")
+        .or(builds.last())
+}
+
+/// checks a build exists for the game version, the newest when none is asked for
+pub async fn resolve(mc_version: &str, build: Option<u32>) -> Result<ServerType, String> {
+    let builds = builds(mc_version).await?;
+    let build = match build {
+        Some(wanted) => builds
+            .iter()
+            .find(|x| x.build == wanted)
+            .ok_or(format!("paper has no build {} for {}", wanted, mc_version))?,
+        None => latest(&builds).ok_or(format!("paper has no builds for {}", mc_version))?,
+    };
+    Ok(ServerType::Paper { build: build.build })
+}
+
+/// where to download a build from and its sha256
+pub async fn download_info(mc_version: &str, build: u32) -> Result<(String, String), String> {
+    let builds = builds(mc_version).await?;
+    let found = builds
+        .iter()
+        .find(|x| x.build == build)
+        .ok_or(format!("paper has no build {} for {}", build, mc_version))?;
+    let application = &found.downloads.application;
+    Ok((
+        format!(
+            "{}/versions/{}/builds/{}/downloads/{}",
+            API_ENDPOINT, mc_version, build, application.name
+        ),
+        application.sha256.clone(),
+    ))
+}
@@ -0,0 +1,69 @@
+use serde::Deserialize;
+
+use crate::configuration::ServerType;
+use crate::loaders::get_json;
+
+pub const API_ENDPOINT: &str = "https://api.purpurmc.org/v2/purpur";
+
+#[derive(Debug, Clone, Deserialize)]
+struct VersionBuilds {
+    builds: BuildList,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BuildList {
+    latest: String,
+    /// oldest first
+    all: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BuildInfo {
+    /// purpur only publishes an md5 of each jar
+    md5: String,
+}
+
+/// every build for a game version, oldest first, and the newest one
+pub async fn builds(mc_version: &str) -> Result<(Vec<u32>, u32), String> {
+    let version: VersionBuilds = get_json("purpur", &format!("{}/{}", API_ENDPOINT, mc_version))
+        .await
+        .map_err(|err| format!("purpur doesn't have {}: {}", mc_version, err))?;
+    let all = version
+        .builds
+        .all
+        .iter()
+        .filter_map(|x| x.parse().ok())
+        .collect();
+    let latest = version
+        .builds
+        .latest
+        .parse()
+        .map_err(|_| "invalid latest build from purpur".to_string())?;
+    Ok((all, latest))
+}
+
+/// checks a build exists for the game version, the newest when none is asked for
+pub async fn resolve(mc_version: &str, build: Option<u32>) -> Result<ServerType, String> {
+    let (all, latest) = builds(mc_version).await?;
+    let build = match build {
+        Some(wanted) if all.contains(&wanted) => wanted,
+        Some(wanted) => {
+            return Err(format!("purpur has no build {} for {}", wanted, mc_version));
+        }
+        None => latest,
+    };
+    Ok(ServerType::Purpur { build })
+}
+
+/// where to download a build from and its md5
+pub async fn download_info(mc_version: &str, build: u32) -> Result<(String, String), String> {
+    let info: BuildInfo = get_json(
+        "purpur",
+        &format!("{}/{}/{}", API_ENDPOINT, mc_version, build),
+    )
+    .await?;
+    Ok((
+        format!("{}/{}/{}/download", API_ENDPOINT, mc_version, build),
+        info.md5,
+    ))
+}
@@ -1,7 +1,14 @@
 use actix_web::{App, HttpServer, web::Data};
 use mcmanage::{
+    backups::Backups,
     configuration::ConfigurationManager,
+    mods::updates::ModUpdater,
+    proxy::Proxy,
+    scheduler::Scheduler,
     server_runner::ServerRunner,
+    sleeper::Sleeper,
+    targets::Uploader,
+    webhooks::WebhookDispatcher,
     webui::{routes::get_api_routes, state::WebState},
 };
 
@@ -9,13 +16,22 @@ use mcmanage::{
 async fn main() -> std::io::Result<()> {
     let config = ConfigurationManager::new().await;
     let handle = ServerRunner::begin().await;
+    // subscribe before anything starts so the first server events aren't missed
+    WebhookDispatcher::begin(config.clone(), handle.clone());
+    Uploader::begin(config.clone(), handle.clone());
     config.start_all(handle.clone()).await;
+    Scheduler::begin(config.clone(), handle.clone());
+    Backups::begin(config.clone(), handle.clone());
+    ModUpdater::begin(config.clone());
+    let sleeper = Sleeper::begin(config.clone(), handle.clone());
+    Proxy::begin(config.clone()).await;
 
     HttpServer::new(move || {
         App::new()
             .app_data(Data::new(WebState {
                 config: config.clone(),
                 runner_handle: handle.clone(),
+                sleeper: sleeper.clone(),
             }))
             .service(get_api_routes())
     })
@@ -0,0 +1,359 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::RwLock;
+
+use crate::events::GameEvent;
+use crate::players::now;
+
+/// how often every running server gets sampled
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// full resolution samples kept, 10 minutes worth
+const RECENT_SAMPLES: usize = 120;
+/// number of recent samples averaged into one downsampled point, one per minute
+const DOWNSAMPLE_EVERY: usize = 12;
+/// downsampled points kept, 24 hours worth
+const HISTORY_SAMPLES: usize = 1440;
+/// disk usage walks the whole game directory so only do it every few minutes
+const DISK_EVERY: usize = 60;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcessSample {
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+    pub cpu_percent: f64,
+    pub rss_mb: f64,
+    pub threads: u64,
+    pub open_fds: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub disk_mb: f64,
+}
+
+impl ProcessSample {
+    fn average(samples: &[ProcessSample]) -> ProcessSample {
+        let count = samples.len().max(1) as f64;
+        let last = samples.last().cloned().unwrap_or_default();
+        ProcessSample {
+            timestamp: last.timestamp,
+            cpu_percent: samples.iter().map(|x| x.cpu_percent).sum::<f64>() / count,
+            rss_mb: samples.iter().map(|x| x.rss_mb).sum::<f64>() / count,
+            threads: (samples.iter().map(|x| x.threads).sum::<u64>() as f64 / count) as u64,
+            open_fds: (samples.iter().map(|x| x.open_fds).sum::<u64>() as f64 / count) as u64,
+            // io counters and disk usage only ever grow, keep the latest rather than averaging
+            read_bytes: last.read_bytes,
+            write_bytes: last.write_bytes,
+            disk_mb: last.disk_mb,
+        }
+    }
+}
+
+/// running totals for how long some repeated operation takes
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DurationStats {
+    pub count: u64,
+    pub sum_secs: f64,
+    pub last_secs: f64,
+}
+
+impl DurationStats {
+    pub fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        self.sum_secs += duration.as_secs_f64();
+        self.last_secs = duration.as_secs_f64();
+    }
+}
+
+lazy_static! {
+    /// USER_HZ, the unit of the cpu times in /proc/<pid>/stat
+    static ref CLOCK_TICKS: f64 = match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as f64,
+        _ => 100.0,
+    };
+    pub static ref DOWNLOAD_DURATIONS: std::sync::Mutex<DurationStats> =
+        std::sync::Mutex::new(DurationStats::default());
+}
+
+/// figures picked out of the console events
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConsoleStats {
+    /// ticks per second over the last minute, only reported by servers with a `tps` command
+    pub tps: Option<f64>,
+    /// number of "Can't keep up!" warnings logged
+    pub overloaded_warnings: u64,
+}
+
+impl ConsoleStats {
+    pub fn handle_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Overloaded { .. } => self.overloaded_warnings += 1,
+            GameEvent::TpsReport { one_minute } => self.tps = Some(*one_minute),
+            _ => {}
+        }
+    }
+}
+
+/// a recent full resolution series plus a downsampled long term one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsHistory {
+    pub recent: VecDeque<ProcessSample>,
+    pub history: VecDeque<ProcessSample>,
+    #[serde(skip)]
+    pending: Vec<ProcessSample>,
+}
+
+impl MetricsHistory {
+    pub fn latest(&self) -> Option<&ProcessSample> {
+        self.recent.back()
+    }
+    fn push(&mut self, sample: ProcessSample) {
+        self.recent.push_back(sample.clone());
+        if self.recent.len() > RECENT_SAMPLES {
+            let _ = self.recent.pop_front();
+        }
+        self.pending.push(sample);
+        if self.pending.len() >= DOWNSAMPLE_EVERY {
+            self.history
+                .push_back(ProcessSample::average(&self.pending));
+            self.pending.clear();
+            if self.history.len() > HISTORY_SAMPLES {
+                let _ = self.history.pop_front();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CpuTimes {
+    ticks: u64,
+    timestamp: f64,
+}
+
+fn parse_stat(stat: &str) -> Option<(u64, u64)> {
+    // the command name is in parens and may contain spaces, fields after it are space separated
+    let (_, rest) = stat.rsplit_once(") ")?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // field 14 utime, 15 stime, 20 num_threads counted from 1 with pid and comm first
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    let threads: u64 = fields.get(17)?.parse().ok()?;
+    Some((utime + stime, threads))
+}
+
+/// resident memory in kB from /proc/<pid>/status, which unlike the page count in stat
+/// doesn't depend on the kernel's page size
+fn parse_rss_kb(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|x| x.strip_prefix("VmRSS:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn parse_io(io: &str) -> (u64, u64) {
+    let mut read_bytes = 0;
+    let mut write_bytes = 0;
+    for line in io.lines() {
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim().parse().unwrap_or(0);
+            match key {
+                "read_bytes" => read_bytes = value,
+                "write_bytes" => write_bytes = value,
+                _ => {}
+            }
+        }
+    }
+    (read_bytes, write_bytes)
+}
+
+async fn count_entries(path: &Path) -> u64 {
+    let Ok(mut entries) = fs::read_dir(path).await else {
+        return 0;
+    };
+    let mut count = 0;
+    while let Ok(Some(_)) = entries.next_entry().await {
+        count += 1;
+    }
+    count
+}
+
+/// total size in bytes of everything below a directory
+pub async fn directory_size(path: &Path) -> u64 {
+    let mut total = 0;
+    let mut stack = vec![path.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if metadata.is_dir() {
+                stack.push(entry.path());
+            } else {
+                total += metadata.len();
+            }
+        }
+    }
+    total
+}
+
+/// samples a process through procfs until it goes away
+pub struct ProcessSampler {
+    pid: u32,
+    game_dir: PathBuf,
+    previous: Option<CpuTimes>,
+    disk_mb: f64,
+    samples_taken: usize,
+}
+
+impl ProcessSampler {
+    pub fn new(pid: u32, game_dir: PathBuf) -> Self {
+        Self {
+            pid,
+            game_dir,
+            previous: None,
+            disk_mb: 0.0,
+            samples_taken: 0,
+        }
+    }
+    /// returns none once the process no longer exists
+    pub async fn sample(&mut self) -> Option<ProcessSample> {
+        let proc_dir = PathBuf::from(format!("/proc/{}", self.pid));
+        let stat = fs::read_to_string(proc_dir.join("stat")).await.ok()?;
+        let (ticks, threads) = parse_stat(&stat)?;
+        let status = fs::read_to_string(proc_dir.join("status"))
+            .await
+            .unwrap_or_default();
+        let rss_kb = parse_rss_kb(&status).unwrap_or(0);
+        let io = fs::read_to_string(proc_dir.join("io"))
+            .await
+            .unwrap_or_default();
+        let (read_bytes, write_bytes) = parse_io(&io);
+        let open_fds = count_entries(&proc_dir.join("fd")).await;
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|x| x.as_secs_f64())
+            .unwrap_or_default();
+        let current = CpuTimes { ticks, timestamp };
+        let cpu_percent = match self.previous {
+            Some(previous) if current.timestamp > previous.timestamp => {
+                let cpu_secs = current.ticks.saturating_sub(previous.ticks) as f64 / *CLOCK_TICKS;
+                cpu_secs / (current.timestamp - previous.timestamp) * 100.0
+            }
+            _ => 0.0,
+        };
+        self.previous = Some(current);
+
+        if self.samples_taken.is_multiple_of(DISK_EVERY) {
+            self.disk_mb = directory_size(&self.game_dir).await as f64 / 1024.0 / 1024.0;
+        }
+        self.samples_taken += 1;
+
+        Some(ProcessSample {
+            timestamp: now(),
+            cpu_percent,
+            rss_mb: rss_kb as f64 / 1024.0,
+            threads,
+            open_fds,
+            read_bytes,
+            write_bytes,
+            disk_mb: self.disk_mb,
+        })
+    }
+    /// samples on an interval into the history until the process exits
+    pub async fn run(mut self, metrics: Arc<RwLock<MetricsHistory>>) {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(sample) = self.sample().await else {
+                break;
+            };
+            metrics.write().await.push(sample);
+        }
+    }
+}
+
+/// renders a series as the `points` attribute of an svg polyline
+pub fn svg_points(values: &[f64], width: f64, height: f64) -> String {
+    let max = values
+        .iter()
+        .cloned()
+        .fold(0.0_f64, f64::max)
+        .max(f64::EPSILON);
+    let step = if values.len() > 1 {
+        width / (values.len() - 1) as f64
+    } else {
+        0.0
+    };
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            format!(
+                "{:.1},{:.1}",
+                i as f64 * step,
+                height - value / max * height
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Chart {
+    pub name: String,
+    pub unit: String,
+    pub max: f64,
+    pub points: String,
+}
+
+impl Chart {
+    pub fn new(name: &str, unit: &str, values: &[f64]) -> Self {
+        Self {
+            name: name.to_string(),
+            unit: unit.to_string(),
+            max: values.iter().cloned().fold(0.0_f64, f64::max),
+            points: svg_points(values, 600.0, 100.0),
+        }
+    }
+    /// the charts shown on a server page for a series of samples
+    pub fn for_samples<'a>(samples: impl Iterator<Item = &'a ProcessSample> + Clone) -> Vec<Chart> {
+        let series = |f: fn(&ProcessSample) -> f64| samples.clone().map(f).collect::<Vec<f64>>();
+        vec![
+            Chart::new("cpu", "%", &series(|x| x.cpu_percent)),
+            Chart::new("memory", "MB", &series(|x| x.rss_mb)),
+            Chart::new("threads", "", &series(|x| x.threads as f64)),
+            Chart::new("open files", "", &series(|x| x.open_fds as f64)),
+            Chart::new("disk", "MB", &series(|x| x.disk_mb)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stat_with_spaces_in_the_name() {
+        let stat = "1234 (java (main)) S 1 1234 1234 0 -1 4194560 5000 0 0 0 \
+                    250 50 0 0 20 0 42 0 100 5000000 12000 18446744073709551615";
+        assert_eq!(parse_stat(stat), Some((300, 42)));
+    }
+
+    #[test]
+    fn parses_rss_from_status() {
+        let status = "Name:\tjava\nVmPeak:\t 9000 kB\nVmRSS:\t  204800 kB\nThreads:\t42\n";
+        assert_eq!(parse_rss_kb(status), Some(204800));
+        assert_eq!(parse_rss_kb("Name:\tkthreadd\n"), None);
+    }
+}
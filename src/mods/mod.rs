@@ -0,0 +1,434 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::configuration::{Server, ServerType};
+use crate::files::is_valid_file_name;
+use crate::loaders::{Checksum, download};
+use crate::mods::modrinth::{DependencyType, Project, Version};
+use crate::players::now;
+
+pub mod modrinth;
+pub mod mrpack;
+pub mod updates;
+
+/// where a server type loads mods or plugins from and which modrinth loaders fit it
+#[derive(Debug, Clone, Serialize)]
+pub struct ModTarget {
+    /// "mod" or "plugin"
+    pub project_type: &'static str,
+    pub loaders: &'static [&'static str],
+    /// relative to the game directory
+    pub dir: &'static str,
+}
+
+impl ModTarget {
+    pub fn for_server(server_type: &ServerType) -> Option<ModTarget> {
+        let (project_type, loaders, dir): (_, &'static [&'static str], _) = match server_type {
+            ServerType::Vanilla => return None,
+            ServerType::Fabric { .. } => ("mod", &["fabric"], "mods"),
+            ServerType::Forge { .. } => ("mod", &["forge"], "mods"),
+            ServerType::NeoForge { .. } => ("mod", &["neoforge"], "mods"),
+            ServerType::Paper { .. } => ("plugin", &["paper", "spigot", "bukkit"], "plugins"),
+            ServerType::Purpur { .. } => (
+                "plugin",
+                &["purpur", "paper", "spigot", "bukkit"],
+                "plugins",
+            ),
+        };
+        Some(ModTarget {
+            project_type,
+            loaders,
+            dir,
+        })
+    }
+    /// where a file goes. the name comes from modrinth or the lockfile, so one that
+    /// would lead out of the directory is refused before it's joined onto anything
+    fn path(&self, server_id: usize, file_name: &str) -> Result<PathBuf, String> {
+        if !is_valid_file_name(file_name) {
+            return Err(format!("invalid file name {:?}", file_name));
+        }
+        Ok(Path::new("./servers")
+            .join(server_id.to_string())
+            .join("game")
+            .join(self.dir)
+            .join(file_name))
+    }
+}
+
+/// a modrinth project installed on a server and the version it's on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledProject {
+    pub project_id: String,
+    pub slug: String,
+    pub title: String,
+    pub version_id: String,
+    pub version_number: String,
+    pub file_name: String,
+    pub sha512: String,
+    /// installed because another project needs it rather than picked
+    #[serde(default)]
+    pub dependency: bool,
+    /// projects this version needs
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// projects this version can't run beside
+    #[serde(default)]
+    pub incompatible: Vec<String>,
+    /// unix timestamp in seconds
+    pub installed: u64,
+}
+
+/// every modrinth project installed on a server, so they can be removed and updated
+/// without guessing from file names
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModLock {
+    pub projects: Vec<InstalledProject>,
+}
+
+lazy_static! {
+    /// lockfile changes download files in between reading and writing it
+    static ref CHANGES: Mutex<()> = Mutex::new(());
+}
+
+fn lock_path(server_id: usize) -> String {
+    format!("./servers/{}/mods.lock.toml", server_id)
+}
+
+impl ModLock {
+    pub async fn load(server_id: usize) -> Result<ModLock, String> {
+        match fs::read_to_string(lock_path(server_id)).await {
+            Ok(contents) => toml::from_str(&contents).map_err(|err| {
+                format!("invalid mod lockfile in {}: {}", lock_path(server_id), err)
+            }),
+            Err(_) => Ok(ModLock::default()),
+        }
+    }
+    /// written beside the lockfile and moved over it so a crash can't leave half a file
+    async fn write(&self, server_id: usize) -> std::io::Result<()> {
+        fs::create_dir_all(format!("./servers/{}", server_id)).await?;
+        let partial = format!("{}.partial", lock_path(server_id));
+        let mut file = File::create(&partial).await?;
+        file.write_all(toml::to_string_pretty(&self).unwrap().as_bytes())
+            .await?;
+        file.flush().await?;
+        fs::rename(&partial, lock_path(server_id)).await
+    }
+    pub fn get(&self, project_id: &str) -> Option<&InstalledProject> {
+        self.projects.iter().find(|x| x.project_id == project_id)
+    }
+}
+
+/// a version about to be installed
+struct Planned {
+    project: Project,
+    version: Version,
+    dependency: bool,
+}
+
+/// the version to install of a project, the newest compatible one unless asked for a
+/// specific one
+async fn pick_version(
+    target: &ModTarget,
+    mc_version: &str,
+    project: &Project,
+    version_id: Option<&str>,
+) -> Result<Version, String> {
+    match version_id {
+        Some(version_id) => {
+            let version = modrinth::version(version_id).await?;
+            if version.project_id != project.id {
+                return Err(format!(
+                    "{} isn't a version of {}",
+                    version_id, project.title
+                ));
+            }
+            if !version.supports(target.loaders, mc_version) {
+                return Err(format!(
+                    "{} {} doesn't support this server",
+                    project.title, version.version_number
+                ));
+            }
+            Ok(version)
+        }
+        None => {
+            let versions =
+                modrinth::versions(&project.id, target.loaders, Some(mc_version)).await?;
+            modrinth::newest(&versions).cloned().ok_or(format!(
+                "{} has no version for {} on {}",
+                project.title,
+                target.loaders.join("/"),
+                mc_version
+            ))
+        }
+    }
+}
+
+/// the project and everything it needs that isn't installed yet, failing on anything
+/// that can't run together
+async fn plan(
+    lock: &ModLock,
+    target: &ModTarget,
+    server: &Server,
+    project: &str,
+    version_id: Option<&str>,
+) -> Result<Vec<Planned>, String> {
+    let mc_version = &server.mc_version_id;
+    let root = modrinth::project(project).await?;
+    if root.server_side == "unsupported" {
+        return Err(format!("{} only runs on clients", root.title));
+    }
+    let version = pick_version(target, mc_version, &root, version_id).await?;
+    if lock
+        .get(&root.id)
+        .is_some_and(|x| x.version_id == version.id)
+    {
+        return Err(format!(
+            "{} {} is already installed",
+            root.title, version.version_number
+        ));
+    }
+    let mut planned = vec![Planned {
+        project: root,
+        version,
+        dependency: false,
+    }];
+    let mut seen: HashSet<String> = planned.iter().map(|x| x.project.id.clone()).collect();
+    // project and version ids, either can be missing
+    let mut queue: VecDeque<(Option<String>, Option<String>)> = VecDeque::new();
+    let queue_dependencies = |planned: &Planned, queue: &mut VecDeque<_>| {
+        for dependency in &planned.version.dependencies {
+            if dependency.dependency_type == DependencyType::Required {
+                queue.push_back((dependency.project_id.clone(), dependency.version_id.clone()));
+            }
+        }
+    };
+    queue_dependencies(&planned[0], &mut queue);
+    while let Some((project_id, version_id)) = queue.pop_front() {
+        // some dependencies only name a version
+        let project_id = match (project_id, &version_id) {
+            (Some(project_id), _) => project_id,
+            (None, Some(version_id)) => modrinth::version(version_id).await?.project_id,
+            (None, None) => continue,
+        };
+        if lock.get(&project_id).is_some() || !seen.insert(project_id.clone()) {
+            continue;
+        }
+        let project = modrinth::project(&project_id).await?;
+        // libraries for the client side only aren't needed here
+        if project.server_side == "unsupported" {
+            continue;
+        }
+        let version = pick_version(target, mc_version, &project, version_id.as_deref())
+            .await
+            .map_err(|err| format!("a required dependency can't be installed: {}", err))?;
+        let dependency = Planned {
+            project,
+            version,
+            dependency: true,
+        };
+        queue_dependencies(&dependency, &mut queue);
+        planned.push(dependency);
+    }
+    check_conflicts(lock, target, server.id, &planned)?;
+    Ok(planned)
+}
+
+fn check_conflicts(
+    lock: &ModLock,
+    target: &ModTarget,
+    server_id: usize,
+    planned: &[Planned],
+) -> Result<(), String> {
+    let title = |project_id: &str| {
+        planned
+            .iter()
+            .find(|x| x.project.id == project_id)
+            .map(|x| x.project.title.clone())
+            .or(lock.get(project_id).map(|x| x.title.clone()))
+            .unwrap_or(project_id.to_string())
+    };
+    // what stays installed next to the planned versions
+    let kept: Vec<&InstalledProject> = lock
+        .projects
+        .iter()
+        .filter(|x| !planned.iter().any(|p| p.project.id == x.project_id))
+        .collect();
+    for new in planned {
+        for other in new.version.dependency_ids(DependencyType::Incompatible) {
+            if planned.iter().any(|x| x.project.id == other)
+                || kept.iter().any(|x| x.project_id == other)
+            {
+                return Err(format!(
+                    "{} is incompatible with {}",
+                    new.project.title,
+                    title(&other)
+                ));
+            }
+        }
+        if let Some(old) = kept
+            .iter()
+            .find(|x| x.incompatible.contains(&new.project.id))
+        {
+            return Err(format!(
+                "{} is incompatible with {}",
+                old.title, new.project.title
+            ));
+        }
+        let file = new
+            .version
+            .primary_file()
+            .ok_or(format!("{} has no files", new.project.title))?;
+        // a file of the same name that modrinth didn't put there
+        if target.path(server_id, &file.filename)?.exists()
+            && !lock.projects.iter().any(|x| x.file_name == file.filename)
+        {
+            return Err(format!(
+                "{}/{} already exists and wasn't installed from modrinth",
+                target.dir, file.filename
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// installs a version of a project and whatever it requires into the server's mods or
+/// plugins, returning what was installed
+pub async fn install(
+    server: &Server,
+    project: &str,
+    version_id: Option<&str>,
+) -> Result<Vec<InstalledProject>, String> {
+    let target = ModTarget::for_server(&server.server_type)
+        .ok_or("vanilla servers can't load mods or plugins")?;
+    let _changes = CHANGES.lock().await;
+    let mut lock = ModLock::load(server.id).await?;
+    let planned = plan(&lock, &target, server, project, version_id).await?;
+    let mut installed = vec![];
+    for new in planned {
+        let file = new
+            .version
+            .primary_file()
+            .ok_or(format!("{} has no files", new.project.title))?;
+        download(
+            &file.url,
+            &target.path(server.id, &file.filename)?,
+            Checksum::Sha512(file.hashes.sha512.clone()),
+        )
+        .await?;
+        let previous = lock
+            .projects
+            .iter()
+            .position(|x| x.project_id == new.project.id);
+        // a project that's already here keeps being a dependency or not when it's
+        // moved to another version
+        let dependency = previous
+            .map(|position| lock.projects[position].dependency)
+            .unwrap_or(new.dependency);
+        if let Some(position) = previous {
+            let old = lock.projects.remove(position);
+            if old.file_name != file.filename
+                && let Ok(path) = target.path(server.id, &old.file_name)
+            {
+                let _ = fs::remove_file(path).await;
+            }
+        }
+        let entry = InstalledProject {
+            project_id: new.project.id.clone(),
+            slug: new.project.slug.clone(),
+            title: new.project.title.clone(),
+            version_id: new.version.id.clone(),
+            version_number: new.version.version_number.clone(),
+            file_name: file.filename.clone(),
+            sha512: file.hashes.sha512.clone(),
+            dependency,
+            requires: new.version.dependency_ids(DependencyType::Required),
+            incompatible: new.version.dependency_ids(DependencyType::Incompatible),
+            installed: now(),
+        };
+        lock.projects.push(entry.clone());
+        // written after every file so a failed download later on leaves the lockfile
+        // matching what's on disk
+        lock.write(server.id)
+            .await
+            .map_err(|err| format!("could not write the mod lockfile: {}", err))?;
+        installed.push(entry);
+    }
+    Ok(installed)
+}
+
+/// removes a project along with the dependencies nothing else needs anymore, returning
+/// the titles of everything removed
+pub async fn remove(server: &Server, project_id: &str) -> Result<Vec<String>, String> {
+    let target = ModTarget::for_server(&server.server_type)
+        .ok_or("vanilla servers can't load mods or plugins")?;
+    let _changes = CHANGES.lock().await;
+    let mut lock = ModLock::load(server.id).await?;
+    let project = lock
+        .get(project_id)
+        .ok_or("the project isn't installed")?
+        .clone();
+    if let Some(other) = lock
+        .projects
+        .iter()
+        .find(|x| x.requires.contains(&project.project_id))
+    {
+        return Err(format!("{} is needed by {}", project.title, other.title));
+    }
+    let mut removed = vec![project];
+    lock.projects.retain(|x| x.project_id != project_id);
+    loop {
+        let unneeded = lock.projects.iter().position(|x| {
+            x.dependency
+                && !lock
+                    .projects
+                    .iter()
+                    .any(|other| other.requires.contains(&x.project_id))
+        });
+        let Some(position) = unneeded else {
+            break;
+        };
+        removed.push(lock.projects.remove(position));
+    }
+    let paths = removed
+        .iter()
+        .map(|x| target.path(server.id, &x.file_name))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (project, path) in removed.iter().zip(paths) {
+        match fs::remove_file(path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("could not remove {}: {}", project.file_name, err)),
+        }
+    }
+    lock.write(server.id)
+        .await
+        .map_err(|err| format!("could not write the mod lockfile: {}", err))?;
+    Ok(removed.into_iter().map(|x| x.title).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_files_in_the_mods_directory() {
+        let target = ModTarget {
+            project_type: "mod",
+            loaders: &["fabric"],
+            dir: "mods",
+        };
+        assert_eq!(
+            target.path(3, "sodium-0.6.jar").unwrap(),
+            Path::new("./servers/3/game/mods/sodium-0.6.jar")
+        );
+        for name in ["../../../config.toml", "/etc/passwd", "..", "a\\b.jar", ""] {
+            assert!(target.path(3, name).is_err(), "{:?}", name);
+        }
+    }
+}
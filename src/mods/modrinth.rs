@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+pub const API_ENDPOINT: &str = "https://api.modrinth.com/v2";
+const SEARCH_LIMIT: usize = 20;
+
+lazy_static! {
+    /// modrinth asks every client to identify itself
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .user_agent(concat!("mcmanage/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("failed to build http client");
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub project_id: String,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    pub downloads: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SearchResults {
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    /// "required", "optional", "unsupported" or "unknown"
+    pub server_side: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyType {
+    Required,
+    Optional,
+    Incompatible,
+    /// shipped inside the file already
+    Embedded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dependency {
+    pub version_id: Option<String>,
+    pub project_id: Option<String>,
+    pub dependency_type: DependencyType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileHashes {
+    pub sha512: String,
+    pub sha1: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionFile {
+    pub hashes: FileHashes,
+    pub url: String,
+    pub filename: String,
+    pub primary: bool,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Version {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub version_number: String,
+    /// "release", "beta" or "alpha"
+    pub version_type: String,
+    pub date_published: String,
+    #[serde(default)]
+    pub changelog: Option<String>,
+    pub loaders: Vec<String>,
+    pub game_versions: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+    pub files: Vec<VersionFile>,
+}
+
+impl Version {
+    /// the file to install, the first one when none is marked primary
+    pub fn primary_file(&self) -> Option<&VersionFile> {
+        self.files.iter().find(|x| x.primary).or(self.files.first())
+    }
+    /// the projects this version has a dependency of that type on
+    pub fn dependency_ids(&self, dependency_type: DependencyType) -> Vec<String> {
+        self.dependencies
+            .iter()
+            .filter(|x| x.dependency_type == dependency_type)
+            .filter_map(|x| x.project_id.clone())
+            .collect()
+    }
+    pub fn supports(&self, loaders: &[&str], mc_version: &str) -> bool {
+        self.game_versions.iter().any(|x| x == mc_version)
+            && self.loaders.iter().any(|x| loaders.contains(&x.as_str()))
+    }
+}
+
+async fn get<T: for<'a> Deserialize<'a>>(
+    path: &str,
+    query: &[(&str, String)],
+) -> Result<T, String> {
+    CLIENT
+        .get(format!("{}{}", API_ENDPOINT, path))
+        .query(query)
+        .send()
+        .await
+        .and_then(|x| x.error_for_status())
+        .map_err(|err| format!("failed to communicate with modrinth: {}", err))?
+        .json::<T>()
+        .await
+        .map_err(|err| format!("invalid response from modrinth: {}", err))
+}
+
+/// projects of `project_type` that run on a server, most relevant first. empty loaders
+/// and no game version leave those unfiltered
+pub async fn search(
+    query: &str,
+    project_type: &str,
+    loaders: &[&str],
+    mc_version: Option<&str>,
+) -> Result<Vec<SearchHit>, String> {
+    let mut facets = vec![
+        vec![format!("project_type:{}", project_type)],
+        vec![
+            "server_side:required".to_string(),
+            "server_side:optional".to_string(),
+        ],
+    ];
+    if !loaders.is_empty() {
+        facets.push(
+            loaders
+                .iter()
+                .map(|x| format!("categories:{}", x))
+                .collect(),
+        );
+    }
+    if let Some(mc_version) = mc_version {
+        facets.push(vec![format!("versions:{}", mc_version)]);
+    }
+    let results: SearchResults = get(
+        "/search",
+        &[
+            ("query", query.to_string()),
+            ("facets", serde_json::json!(facets).to_string()),
+            ("limit", SEARCH_LIMIT.to_string()),
+        ],
+    )
+    .await?;
+    Ok(results.hits)
+}
+
+/// takes the id or the slug
+pub async fn project(project: &str) -> Result<Project, String> {
+    get(&format!("/project/{}", project), &[]).await
+}
+
+/// the versions of a project for any of the loaders and the game version, newest
+/// first. empty loaders and no game version leave those unfiltered
+pub async fn versions(
+    project: &str,
+    loaders: &[&str],
+    mc_version: Option<&str>,
+) -> Result<Vec<Version>, String> {
+    let mut query = vec![];
+    if !loaders.is_empty() {
+        query.push(("loaders", serde_json::json!(loaders).to_string()));
+    }
+    if let Some(mc_version) = mc_version {
+        query.push(("game_versions", serde_json::json!([mc_version]).to_string()));
+    }
+    get(&format!("/project/{}/version", project), &query).await
+}
+
+pub async fn version(version_id: &str) -> Result<Version, String> {
+    get(&format!("/version/{}", version_id), &[]).await
+}
+
+/// takes ids or slugs, the ones modrinth doesn't know are left out
+pub async fn projects(projects: &[String]) -> Result<Vec<Project>, String> {
+    get(
+        "/projects",
+        &[("ids", serde_json::json!(projects).to_string())],
+    )
+    .await
+}
+
+/// the versions files belong to by their sha512, the ones modrinth doesn't know are
+/// left out
+pub async fn versions_from_hashes(hashes: &[String]) -> Result<HashMap<String, Version>, String> {
+    CLIENT
+        .post(format!("{}/version_files", API_ENDPOINT))
+        .json(&serde_json::json!({ "hashes": hashes, "algorithm": "sha512" }))
+        .send()
+        .await
+        .and_then(|x| x.error_for_status())
+        .map_err(|err| format!("failed to communicate with modrinth: {}", err))?
+        .json()
+        .await
+        .map_err(|err| format!("invalid response from modrinth: {}", err))
+}
+
+/// the newest release, or the newest version when there are only betas and alphas
+pub fn newest(versions: &[Version]) -> Option<&Version> {
+    versions
+        .iter()
+        .find(|x| x.version_type == "release")
+        .or(versions.first())
+}
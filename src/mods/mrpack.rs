@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use zip::ZipArchive;
+
+use crate::archive::copy_capped;
+use crate::configuration::{Server, ServerType};
+use crate::files::MAX_UNZIP_BYTES;
+use crate::loaders::forge::{self, ForgeKind, InstallState};
+use crate::loaders::{Checksum, download, ensure_available, fabric};
+use crate::mods::modrinth::{self, DependencyType};
+use crate::mods::{CHANGES, InstalledProject, ModLock, ModTarget};
+use crate::players::now;
+
+const INDEX_FILE: &str = "modrinth.index.json";
+/// the hosts the pack format lets files come from
+const ALLOWED_HOSTS: [&str; 4] = [
+    "cdn.modrinth.com",
+    "github.com",
+    "raw.githubusercontent.com",
+    "gitlab.com",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackIndex {
+    pub format_version: u32,
+    pub game: String,
+    pub version_id: String,
+    pub name: String,
+    pub files: Vec<PackFile>,
+    /// "minecraft" and the loader, like "fabric-loader" or "forge", to their versions
+    pub dependencies: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackHashes {
+    pub sha512: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackEnv {
+    pub server: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackFile {
+    pub path: String,
+    pub hashes: PackHashes,
+    /// missing means the file is needed on both sides
+    pub env: Option<PackEnv>,
+    /// mirrors of the same file
+    pub downloads: Vec<String>,
+}
+
+impl PackFile {
+    fn on_server(&self) -> bool {
+        self.env.as_ref().is_none_or(|x| x.server != "unsupported")
+    }
+    /// the mirrors on hosts a pack is allowed to download from
+    fn allowed_downloads(&self) -> impl Iterator<Item = &String> {
+        self.downloads.iter().filter(|x| is_allowed_download(x))
+    }
+}
+
+fn is_allowed_download(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| {
+        url.scheme() == "https" && url.host_str().is_some_and(|x| ALLOWED_HOSTS.contains(&x))
+    })
+}
+
+impl PackIndex {
+    pub fn mc_version(&self) -> Result<&str, String> {
+        self.dependencies
+            .get("minecraft")
+            .map(String::as_str)
+            .ok_or("the pack doesn't say which minecraft version it's for".to_string())
+    }
+    /// the server type that runs the pack's loader at the version it asks for
+    pub async fn server_type(&self) -> Result<ServerType, String> {
+        let mc_version = self.mc_version()?;
+        if let Some(loader) = self.dependencies.get("fabric-loader") {
+            fabric::resolve(mc_version, Some(loader)).await
+        } else if let Some(version) = self.dependencies.get("forge") {
+            forge::resolve(ForgeKind::Forge, mc_version, Some(version)).await
+        } else if let Some(version) = self.dependencies.get("neoforge") {
+            forge::resolve(ForgeKind::NeoForge, mc_version, Some(version)).await
+        } else if self.dependencies.contains_key("quilt-loader") {
+            Err("quilt packs aren't supported".to_string())
+        } else {
+            Ok(ServerType::Vanilla)
+        }
+    }
+}
+
+/// reads and checks the index of a pack
+pub async fn read_index(archive: &Path) -> Result<PackIndex, String> {
+    let archive = archive.to_path_buf();
+    let index: PackIndex = spawn_blocking(move || {
+        let file = File::open(&archive).map_err(|err| err.to_string())?;
+        let mut archive =
+            ZipArchive::new(file).map_err(|err| format!("not a modrinth pack: {}", err))?;
+        let mut entry = archive
+            .by_name(INDEX_FILE)
+            .map_err(|_| format!("the pack has no {}", INDEX_FILE))?;
+        let mut contents = String::new();
+        entry
+            .read_to_string(&mut contents)
+            .map_err(|err| err.to_string())?;
+        serde_json::from_str(&contents).map_err(|err| format!("invalid {}: {}", INDEX_FILE, err))
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+    if index.format_version != 1 || index.game != "minecraft" {
+        return Err(format!(
+            "unsupported pack format {} for {}",
+            index.format_version, index.game
+        ));
+    }
+    for file in &index.files {
+        if relative_path(&file.path).is_none() {
+            return Err(format!(
+                "the pack has a file outside the server: {}",
+                file.path
+            ));
+        }
+        if file.on_server() && file.allowed_downloads().next().is_none() {
+            return Err(format!(
+                "{} can't be downloaded from an allowed host",
+                file.path
+            ));
+        }
+    }
+    Ok(index)
+}
+
+/// only plain relative paths, a pack can't write outside the server
+fn relative_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    path.components()
+        .all(|x| matches!(x, Component::Normal(_)))
+        .then(|| path.to_path_buf())
+        .filter(|x| x.components().next().is_some())
+}
+
+/// a pack being put onto a server, shown on the import page
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportJob {
+    pub pack: String,
+    pub state: InstallState,
+    pub step: String,
+    pub done: usize,
+    pub total: usize,
+    pub error: Option<String>,
+}
+
+lazy_static! {
+    static ref IMPORT_JOBS: Mutex<HashMap<usize, ImportJob>> = Mutex::new(HashMap::new());
+}
+
+/// the last import onto a server since mcmanage started
+pub fn import_job(server_id: usize) -> Option<ImportJob> {
+    IMPORT_JOBS.lock().unwrap().get(&server_id).cloned()
+}
+
+fn update_job(server_id: usize, f: impl FnOnce(&mut ImportJob)) {
+    if let Some(job) = IMPORT_JOBS.lock().unwrap().get_mut(&server_id) {
+        f(job);
+    }
+}
+
+fn set_step(server_id: usize, step: &str, total: usize) {
+    update_job(server_id, |job| {
+        job.step = step.to_string();
+        job.done = 0;
+        job.total = total;
+    });
+}
+
+/// downloads the loader and the pack's server files, then copies in its overrides
+pub async fn import(server: &Server, index: &PackIndex, archive: &Path) -> Result<(), String> {
+    IMPORT_JOBS.lock().unwrap().insert(
+        server.id,
+        ImportJob {
+            pack: format!("{} {}", index.name, index.version_id),
+            state: InstallState::Running,
+            step: "starting".to_string(),
+            done: 0,
+            total: 0,
+            error: None,
+        },
+    );
+    let result = apply(server, index, archive).await;
+    update_job(server.id, |job| match &result {
+        Ok(()) => {
+            job.state = InstallState::Succeeded;
+            job.step = "done".to_string();
+        }
+        Err(err) => {
+            job.state = InstallState::Failed;
+            job.error = Some(err.clone());
+        }
+    });
+    result
+}
+
+async fn apply(server: &Server, index: &PackIndex, archive: &Path) -> Result<(), String> {
+    let game_dir = PathBuf::from(format!("./servers/{}/game", server.id));
+    set_step(server.id, "downloading the loader", 1);
+    ensure_available(&server.server_type, &server.mc_version_id).await?;
+
+    let files: Vec<&PackFile> = index.files.iter().filter(|x| x.on_server()).collect();
+    set_step(server.id, "downloading files", files.len());
+    for file in &files {
+        let path = game_dir.join(relative_path(&file.path).expect("checked when read"));
+        let mut result = Err(format!("{} has no allowed downloads", file.path));
+        for url in file.allowed_downloads() {
+            result = download(url, &path, Checksum::Sha512(file.hashes.sha512.clone())).await;
+            if result.is_ok() {
+                break;
+            }
+        }
+        result?;
+        update_job(server.id, |job| job.done += 1);
+    }
+
+    set_step(server.id, "applying overrides", 1);
+    let archive = archive.to_path_buf();
+    let destination = game_dir.clone();
+    spawn_blocking(move || unpack_overrides(&archive, &destination))
+        .await
+        .map_err(|err| err.to_string())??;
+
+    set_step(server.id, "recording installed projects", 1);
+    // packs are still usable without their projects in the lockfile, they just can't
+    // be updated one by one
+    if let Err(err) = record_projects(server, &files).await {
+        println!("could not record the projects of the pack: {}", err);
+    }
+    Ok(())
+}
+
+/// copies `overrides/` and then `server-overrides/` over the server's files
+fn unpack_overrides(archive: &Path, destination: &Path) -> Result<(), String> {
+    let file = File::open(archive).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|err| err.to_string())?;
+    let mut total: u64 = 0;
+    for prefix in ["overrides", "server-overrides"] {
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+            let Some(relative) = entry
+                .enclosed_name()
+                .and_then(|x| x.strip_prefix(prefix).ok().map(Path::to_path_buf))
+            else {
+                continue;
+            };
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+            let target = destination.join(relative);
+            if entry.is_dir() {
+                std::fs::create_dir_all(&target).map_err(|err| err.to_string())?;
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+            }
+            let mut out = File::create(&target).map_err(|err| err.to_string())?;
+            if !copy_capped(&mut entry, &mut out, &mut total, MAX_UNZIP_BYTES)
+                .map_err(|err| err.to_string())?
+            {
+                return Err("the overrides unpack to too much".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// adds the pack's mods that modrinth knows to the lockfile
+async fn record_projects(server: &Server, files: &[&PackFile]) -> Result<(), String> {
+    let Some(target) = ModTarget::for_server(&server.server_type) else {
+        return Ok(());
+    };
+    let files: Vec<&PackFile> = files
+        .iter()
+        .filter(|x| Path::new(&x.path).parent() == Some(Path::new(target.dir)))
+        .copied()
+        .collect();
+    if files.is_empty() {
+        return Ok(());
+    }
+    let hashes: Vec<String> = files.iter().map(|x| x.hashes.sha512.clone()).collect();
+    let versions = modrinth::versions_from_hashes(&hashes).await?;
+    let ids: Vec<String> = versions.values().map(|x| x.project_id.clone()).collect();
+    let projects = modrinth::projects(&ids).await?;
+    let _changes = CHANGES.lock().await;
+    let mut lock = ModLock::load(server.id).await?;
+    for file in files {
+        let Some(version) = versions.get(&file.hashes.sha512) else {
+            continue;
+        };
+        let Some(project) = projects.iter().find(|x| x.id == version.project_id) else {
+            continue;
+        };
+        lock.projects.retain(|x| x.project_id != project.id);
+        lock.projects.push(InstalledProject {
+            project_id: project.id.clone(),
+            slug: project.slug.clone(),
+            title: project.title.clone(),
+            version_id: version.id.clone(),
+            version_number: version.version_number.clone(),
+            file_name: Path::new(&file.path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            sha512: file.hashes.sha512.clone(),
+            dependency: false,
+            requires: version.dependency_ids(DependencyType::Required),
+            incompatible: version.dependency_ids(DependencyType::Incompatible),
+            installed: now(),
+        });
+    }
+    lock.write(server.id)
+        .await
+        .map_err(|err| format!("could not write the mod lockfile: {}", err))
+}
+
+/// somewhere to keep a pack until its server exists
+pub fn incoming_path() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    PathBuf::from(format!(
+        "./servers/incoming-{}-{}.mrpack",
+        now(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// fetches a modpack from modrinth, the newest release unless asked for a version
+pub async fn download_pack(
+    project: &str,
+    version_id: Option<&str>,
+    path: &Path,
+) -> Result<(), String> {
+    let project = modrinth::project(project).await?;
+    let version = match version_id {
+        Some(version_id) => modrinth::version(version_id).await?,
+        None => {
+            let versions = modrinth::versions(&project.id, &[], None).await?;
+            modrinth::newest(&versions)
+                .cloned()
+                .ok_or(format!("{} has no versions", project.title))?
+        }
+    };
+    if version.project_id != project.id {
+        return Err(format!(
+            "{} isn't a version of {}",
+            version.id, project.title
+        ));
+    }
+    let file = version
+        .primary_file()
+        .ok_or(format!("{} has no files", project.title))?;
+    download(
+        &file.url,
+        path,
+        Checksum::Sha512(file.hashes.sha512.clone()),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_pack_files_inside_the_server() {
+        assert_eq!(
+            relative_path("mods/sodium.jar"),
+            Some(PathBuf::from("mods/sodium.jar"))
+        );
+        assert_eq!(
+            relative_path("config/a b/c.toml"),
+            Some(PathBuf::from("config/a b/c.toml"))
+        );
+        for path in [
+            "",
+            "/etc/passwd",
+            "../server.jar",
+            "mods/../../escape.jar",
+            "./mods/a.jar",
+        ] {
+            assert_eq!(relative_path(path), None, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn only_downloads_from_allowed_hosts() {
+        assert!(is_allowed_download(
+            "https://cdn.modrinth.com/data/AANobbMI/versions/x/sodium.jar"
+        ));
+        assert!(is_allowed_download(
+            "https://github.com/owner/repo/releases/download/v1/a.jar"
+        ));
+        assert!(is_allowed_download(
+            "https://raw.githubusercontent.com/owner/repo/main/a.jar"
+        ));
+        assert!(is_allowed_download("https://gitlab.com/owner/repo/a.jar"));
+        for url in [
+            "http://cdn.modrinth.com/data/a.jar",
+            "https://cdn.modrinth.com.evil.example/a.jar",
+            "https://evil.example/cdn.modrinth.com/a.jar",
+            "https://cdn.modrinth.com@evil.example/a.jar",
+            "https://127.0.0.1:8080/a.jar",
+            "file:///etc/passwd",
+            "not a url",
+        ] {
+            assert!(!is_allowed_download(url), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix_web::rt::spawn;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::fs;
+use tokio::time::sleep;
+
+use crate::backups::{BackupKind, backup_server};
+use crate::configuration::{ConfigurationManager, Server};
+use crate::mods::modrinth::{self, DependencyType, Version};
+use crate::mods::{CHANGES, InstalledProject, ModLock, ModTarget, install, lock_path};
+use crate::players::now;
+use crate::server_runner::ServerRunnerHandle;
+
+/// how often every server's mods are checked for updates
+const CHECK_EVERY: Duration = Duration::from_secs(6 * 3600);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangelogEntry {
+    pub version_number: String,
+    pub changelog: String,
+}
+
+/// a newer compatible version of an installed project
+#[derive(Debug, Clone, Serialize)]
+pub struct ModUpdate {
+    pub project_id: String,
+    pub title: String,
+    pub from_version: String,
+    pub to_version_id: String,
+    pub to_version: String,
+    /// every version after the installed one, newest first
+    pub changelog: Vec<ChangelogEntry>,
+    /// projects the new version needs that the installed one didn't
+    pub new_requires: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateCheck {
+    /// unix timestamp in seconds
+    pub checked: u64,
+    pub updates: Vec<ModUpdate>,
+    pub error: Option<String>,
+}
+
+lazy_static! {
+    static ref UPDATE_CHECKS: Mutex<HashMap<usize, UpdateCheck>> = Mutex::new(HashMap::new());
+}
+
+/// the last update check of a server since mcmanage started
+pub fn last_check(server_id: usize) -> Option<UpdateCheck> {
+    UPDATE_CHECKS.lock().unwrap().get(&server_id).cloned()
+}
+
+/// where the mod set from before the last bulk update is kept for rolling back
+fn previous_dir(server_id: usize) -> PathBuf {
+    PathBuf::from(format!("./servers/{}/previous_mods", server_id))
+}
+
+pub async fn can_roll_back(server_id: usize) -> bool {
+    fs::try_exists(previous_dir(server_id).join("mods.lock.toml"))
+        .await
+        .unwrap_or(false)
+}
+
+/// the update for an installed project out of its compatible versions, newest first
+fn update_for(project: &InstalledProject, versions: &[Version]) -> Option<ModUpdate> {
+    let newest = modrinth::newest(versions)?;
+    // versions come newest first, an installed one after the newest is older
+    let installed_at = versions.iter().position(|x| x.id == project.version_id);
+    let newest_at = versions
+        .iter()
+        .position(|x| x.id == newest.id)
+        .unwrap_or_default();
+    if installed_at.is_some_and(|x| x <= newest_at) {
+        return None;
+    }
+    let changelog = versions[newest_at..installed_at.unwrap_or(newest_at + 1)]
+        .iter()
+        .map(|x| ChangelogEntry {
+            version_number: x.version_number.clone(),
+            changelog: x.changelog.clone().unwrap_or_default(),
+        })
+        .collect();
+    Some(ModUpdate {
+        project_id: project.project_id.clone(),
+        title: project.title.clone(),
+        from_version: project.version_number.clone(),
+        to_version_id: newest.id.clone(),
+        to_version: newest.version_number.clone(),
+        changelog,
+        new_requires: newest
+            .dependency_ids(DependencyType::Required)
+            .into_iter()
+            .filter(|x| !project.requires.contains(x))
+            .collect(),
+    })
+}
+
+async fn find_updates(server: &Server) -> Result<Vec<ModUpdate>, String> {
+    let Some(target) = ModTarget::for_server(&server.server_type) else {
+        return Ok(vec![]);
+    };
+    let lock = ModLock::load(server.id).await?;
+    let mut updates = vec![];
+    for project in &lock.projects {
+        let versions = modrinth::versions(
+            &project.project_id,
+            target.loaders,
+            Some(&server.mc_version_id),
+        )
+        .await?;
+        updates.extend(update_for(project, &versions));
+    }
+    Ok(updates)
+}
+
+/// looks for newer compatible versions of every installed project and keeps the result
+/// for the mods page
+pub async fn check_updates(server: &Server) -> UpdateCheck {
+    let result = find_updates(server).await;
+    let check = UpdateCheck {
+        checked: now(),
+        error: result.as_ref().err().cloned(),
+        updates: result.unwrap_or_default(),
+    };
+    UPDATE_CHECKS
+        .lock()
+        .unwrap()
+        .insert(server.id, check.clone());
+    check
+}
+
+/// copies the lockfile and every installed file aside, replacing what was kept before
+async fn keep_previous(server: &Server, target: &ModTarget) -> Result<(), String> {
+    let _changes = CHANGES.lock().await;
+    let previous = previous_dir(server.id);
+    if fs::try_exists(&previous).await.unwrap_or(false) {
+        fs::remove_dir_all(&previous)
+            .await
+            .map_err(|err| format!("could not clear the previous mod set: {}", err))?;
+    }
+    fs::create_dir_all(&previous)
+        .await
+        .map_err(|err| err.to_string())?;
+    let lock = ModLock::load(server.id).await?;
+    for project in &lock.projects {
+        fs::copy(
+            target.path(server.id, &project.file_name)?,
+            previous.join(&project.file_name),
+        )
+        .await
+        .map_err(|err| format!("could not keep {}: {}", project.file_name, err))?;
+    }
+    fs::copy(lock_path(server.id), previous.join("mods.lock.toml"))
+        .await
+        .map_err(|err| format!("could not keep the lockfile: {}", err))?;
+    Ok(())
+}
+
+/// backs the server up and moves the projects to the versions found by the last check,
+/// all of them when `project_id` is none. the mod set from before can be rolled back to
+pub async fn apply_updates(
+    runner_handle: &ServerRunnerHandle,
+    server: &Server,
+    project_id: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let target = ModTarget::for_server(&server.server_type)
+        .ok_or("vanilla servers can't load mods or plugins")?;
+    let updates: Vec<ModUpdate> = last_check(server.id)
+        .map(|x| x.updates)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|x| project_id.is_none_or(|id| x.project_id == id))
+        .collect();
+    if updates.is_empty() {
+        return Err("there are no updates to apply, check for updates first".to_string());
+    }
+    let backup = backup_server(runner_handle, server, BackupKind::Safety)
+        .await
+        .map_err(|err| format!("not updating, the backup failed: {}", err))?;
+    println!("backed up server {} as {}", server.id, backup.name);
+    keep_previous(server, &target).await?;
+    let mut applied = vec![];
+    for update in updates {
+        install(server, &update.project_id, Some(&update.to_version_id))
+            .await
+            .map_err(|err| format!("updating {} failed: {}", update.title, err))?;
+        if let Some(check) = UPDATE_CHECKS.lock().unwrap().get_mut(&server.id) {
+            check.updates.retain(|x| x.project_id != update.project_id);
+        }
+        applied.push(format!(
+            "{} {} -> {}",
+            update.title, update.from_version, update.to_version
+        ));
+    }
+    Ok(applied)
+}
+
+/// puts back the mod set from before the last bulk update
+pub async fn roll_back(server: &Server) -> Result<(), String> {
+    let target = ModTarget::for_server(&server.server_type)
+        .ok_or("vanilla servers can't load mods or plugins")?;
+    if !can_roll_back(server.id).await {
+        return Err("there is no previous mod set to roll back to".to_string());
+    }
+    let _changes = CHANGES.lock().await;
+    let previous = previous_dir(server.id);
+    let old: ModLock = toml::from_str(
+        &fs::read_to_string(previous.join("mods.lock.toml"))
+            .await
+            .map_err(|err| err.to_string())?,
+    )
+    .map_err(|err| format!("invalid previous lockfile: {}", err))?;
+    let current = ModLock::load(server.id).await?;
+    // every name is checked before anything is touched, the previous lockfile could have
+    // been edited by hand
+    let removing = current
+        .projects
+        .iter()
+        .map(|x| target.path(server.id, &x.file_name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let restoring = old
+        .projects
+        .iter()
+        .map(|x| target.path(server.id, &x.file_name))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (project, path) in current.projects.iter().zip(removing) {
+        match fs::remove_file(path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("could not remove {}: {}", project.file_name, err)),
+        }
+    }
+    for (project, path) in old.projects.iter().zip(restoring) {
+        fs::copy(previous.join(&project.file_name), path)
+            .await
+            .map_err(|err| format!("could not put back {}: {}", project.file_name, err))?;
+    }
+    old.write(server.id)
+        .await
+        .map_err(|err| format!("could not write the mod lockfile: {}", err))?;
+    let _ = fs::remove_dir_all(&previous).await;
+    UPDATE_CHECKS.lock().unwrap().remove(&server.id);
+    Ok(())
+}
+
+/// checks every server with installed projects for updates now and then
+pub struct ModUpdater;
+
+impl ModUpdater {
+    pub fn begin(config: ConfigurationManager) {
+        spawn(async move {
+            loop {
+                for server in config.get_servers().await {
+                    // an unreadable lockfile still goes through the check so it gets logged
+                    if ModLock::load(server.id)
+                        .await
+                        .is_ok_and(|x| x.projects.is_empty())
+                    {
+                        continue;
+                    }
+                    let check = check_updates(&server).await;
+                    if let Some(err) = check.error {
+                        println!(
+                            "checking server {} for mod updates failed: {}",
+                            server.id, err
+                        );
+                    } else if !check.updates.is_empty() {
+                        println!(
+                            "server {} has {} mod updates",
+                            server.id,
+                            check.updates.len()
+                        );
+                    }
+                }
+                sleep(CHECK_EVERY).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mods::modrinth::Dependency;
+
+    fn version(id: &str, version_type: &str, requires: &[&str]) -> Version {
+        Version {
+            id: id.to_string(),
+            project_id: "sodium".to_string(),
+            name: id.to_string(),
+            version_number: id.to_string(),
+            version_type: version_type.to_string(),
+            date_published: String::new(),
+            changelog: Some(format!("changes in {}", id)),
+            loaders: vec!["fabric".to_string()],
+            game_versions: vec!["1.21".to_string()],
+            dependencies: requires
+                .iter()
+                .map(|x| Dependency {
+                    version_id: None,
+                    project_id: Some(x.to_string()),
+                    dependency_type: DependencyType::Required,
+                })
+                .collect(),
+            files: vec![],
+        }
+    }
+
+    fn installed(version_id: &str) -> InstalledProject {
+        InstalledProject {
+            project_id: "sodium".to_string(),
+            slug: "sodium".to_string(),
+            title: "Sodium".to_string(),
+            version_id: version_id.to_string(),
+            version_number: version_id.to_string(),
+            file_name: format!("sodium-{}.jar", version_id),
+            sha512: String::new(),
+            dependency: false,
+            requires: vec!["fabric-api".to_string()],
+            incompatible: vec![],
+            installed: 0,
+        }
+    }
+
+    /// newest first, the way modrinth lists them
+    fn versions() -> Vec<Version> {
+        vec![
+            version("0.7-beta", "beta", &["fabric-api", "indium"]),
+            version("0.6", "release", &["fabric-api", "indium"]),
+            version("0.5.1", "release", &["fabric-api"]),
+            version("0.5", "release", &["fabric-api"]),
+        ]
+    }
+
+    #[test]
+    fn finds_newer_releases() {
+        let update = update_for(&installed("0.5"), &versions()).unwrap();
+        assert_eq!(update.from_version, "0.5");
+        assert_eq!(update.to_version_id, "0.6");
+        assert_eq!(
+            update
+                .changelog
+                .iter()
+                .map(|x| x.version_number.as_str())
+                .collect::<Vec<_>>(),
+            ["0.6", "0.5.1"]
+        );
+        assert_eq!(update.changelog[0].changelog, "changes in 0.6");
+        assert_eq!(update.new_requires, ["indium"]);
+    }
+
+    #[test]
+    fn leaves_current_and_newer_versions_alone() {
+        assert!(update_for(&installed("0.6"), &versions()).is_none());
+        assert!(update_for(&installed("0.7-beta"), &versions()).is_none());
+        assert!(update_for(&installed("0.5"), &[]).is_none());
+    }
+
+    #[test]
+    fn updates_versions_that_are_no_longer_listed() {
+        let update = update_for(&installed("0.4"), &versions()).unwrap();
+        assert_eq!(update.to_version_id, "0.6");
+        assert_eq!(update.changelog.len(), 1);
+        assert_eq!(update.changelog[0].version_number, "0.6");
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::events::GameEvent;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnlinePlayer {
+    pub username: String,
+    pub uuid: Option<String>,
+    pub ip: Option<String>,
+    /// unix timestamp in seconds
+    pub joined_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerRecord {
+    pub username: String,
+    pub uuid: Option<String>,
+    /// unix timestamp in seconds
+    pub first_seen: u64,
+    /// unix timestamp in seconds
+    pub last_seen: u64,
+    pub total_playtime_secs: u64,
+    pub ips: Vec<String>,
+}
+
+/// persistent session history of every player that has joined a server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerHistory {
+    pub players: Vec<PlayerRecord>,
+}
+
+fn history_path(server_id: usize) -> String {
+    format!("./servers/{}/players.toml", server_id)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock before unix epoch")
+        .as_secs()
+}
+
+impl PlayerHistory {
+    pub async fn load(server_id: usize) -> Result<PlayerHistory, String> {
+        match fs::read_to_string(history_path(server_id)).await {
+            Ok(contents) => toml::from_str(&contents).map_err(|err| {
+                format!(
+                    "invalid player history in {}: {}",
+                    history_path(server_id),
+                    err
+                )
+            }),
+            Err(_) => Ok(PlayerHistory::default()),
+        }
+    }
+    /// written beside the history and moved over it so a crash can't leave half a file
+    async fn write(&self, server_id: usize) -> std::io::Result<()> {
+        let partial = format!("{}.partial", history_path(server_id));
+        let mut file = File::create(&partial).await?;
+        file.write_all(toml::to_string_pretty(&self).unwrap().as_bytes())
+            .await?;
+        file.flush().await?;
+        fs::rename(&partial, history_path(server_id)).await
+    }
+    fn get_or_insert(&mut self, username: &str) -> &mut PlayerRecord {
+        let position = match self.players.iter().position(|x| x.username == username) {
+            Some(position) => position,
+            None => {
+                let now = now();
+                self.players.push(PlayerRecord {
+                    username: username.to_string(),
+                    uuid: None,
+                    first_seen: now,
+                    last_seen: now,
+                    total_playtime_secs: 0,
+                    ips: vec![],
+                });
+                self.players.len() - 1
+            }
+        };
+        &mut self.players[position]
+    }
+}
+
+pub fn is_valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= 16
+        && username
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+/// how long a uuid or ip from the login lines waits for its "joined the game" line
+const PENDING_SECS: u64 = 60;
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    online: Vec<OnlinePlayer>,
+    /// username -> (value, unix timestamp it was seen)
+    pending_uuids: HashMap<String, (String, u64)>,
+    pending_ips: HashMap<String, (String, u64)>,
+    history: PlayerHistory,
+    /// the history on disk didn't parse, so it is left alone instead of overwritten
+    history_unreadable: bool,
+}
+
+impl TrackerState {
+    /// drops uuids and ips of logins that never joined, e.g. kicked or disconnected
+    /// during login
+    fn prune_pending(&mut self, now: u64) {
+        self.pending_uuids
+            .retain(|_, (_, seen)| now.saturating_sub(*seen) < PENDING_SECS);
+        self.pending_ips
+            .retain(|_, (_, seen)| now.saturating_sub(*seen) < PENDING_SECS);
+    }
+}
+
+/// keeps track of who is online on a running server and records their sessions
+#[derive(Debug, Clone)]
+pub struct PlayerTracker {
+    server_id: usize,
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl PlayerTracker {
+    pub async fn new(server_id: usize) -> Self {
+        let state = match PlayerHistory::load(server_id).await {
+            Ok(history) => TrackerState {
+                history,
+                ..Default::default()
+            },
+            Err(err) => {
+                println!("not recording player sessions: {}", err);
+                TrackerState {
+                    history_unreadable: true,
+                    ..Default::default()
+                }
+            }
+        };
+        Self {
+            server_id,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+    pub async fn online_players(&self) -> Vec<OnlinePlayer> {
+        let lock = self.state.lock().await;
+        lock.online.clone()
+    }
+    pub async fn handle_event(&self, event: &GameEvent) {
+        let mut lock = self.state.lock().await;
+        match event.clone() {
+            GameEvent::PlayerUuid { username, uuid } => {
+                let now = now();
+                lock.prune_pending(now);
+                lock.pending_uuids.insert(username, (uuid, now));
+            }
+            GameEvent::PlayerLoggedIn { username, ip } => {
+                let now = now();
+                lock.prune_pending(now);
+                lock.pending_ips.insert(username, (ip, now));
+            }
+            GameEvent::PlayerJoined { username } => {
+                let now = now();
+                lock.prune_pending(now);
+                let uuid = lock.pending_uuids.remove(&username).map(|x| x.0);
+                let ip = lock.pending_ips.remove(&username).map(|x| x.0);
+                let record = lock.history.get_or_insert(&username);
+                record.last_seen = now;
+                if uuid.is_some() {
+                    record.uuid = uuid.clone();
+                }
+                if let Some(ip) = &ip
+                    && !record.ips.contains(ip)
+                {
+                    record.ips.push(ip.clone());
+                }
+                lock.online.retain(|x| x.username != username);
+                lock.online.push(OnlinePlayer {
+                    username,
+                    uuid,
+                    ip,
+                    joined_at: now,
+                });
+                self.persist(&lock).await;
+            }
+            GameEvent::PlayerLeft { username } => {
+                if let Some(position) = lock.online.iter().position(|x| x.username == username) {
+                    let player = lock.online.remove(position);
+                    Self::end_session(&mut lock.history, player);
+                    self.persist(&lock).await;
+                }
+            }
+            _ => {}
+        }
+    }
+    /// closes every open session, used once the server process has exited
+    pub async fn end_all_sessions(&self) {
+        let mut lock = self.state.lock().await;
+        lock.pending_uuids.clear();
+        lock.pending_ips.clear();
+        if lock.online.is_empty() {
+            return;
+        }
+        let online = std::mem::take(&mut lock.online);
+        for player in online {
+            Self::end_session(&mut lock.history, player);
+        }
+        self.persist(&lock).await;
+    }
+    fn end_session(history: &mut PlayerHistory, player: OnlinePlayer) {
+        let now = now();
+        let record = history.get_or_insert(&player.username);
+        record.last_seen = now;
+        record.total_playtime_secs += now.saturating_sub(player.joined_at);
+    }
+    async fn persist(&self, state: &TrackerState) {
+        if state.history_unreadable {
+            return;
+        }
+        if let Err(err) = state.history.write(self.server_id).await {
+            println!("failed to write player history: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_logins_that_never_joined() {
+        let mut state = TrackerState::default();
+        state
+            .pending_uuids
+            .insert("Stale".to_string(), ("uuid".to_string(), 100));
+        state
+            .pending_ips
+            .insert("Stale".to_string(), ("1.2.3.4".to_string(), 100));
+        state
+            .pending_ips
+            .insert("Fresh".to_string(), ("5.6.7.8".to_string(), 150));
+        state.prune_pending(100 + PENDING_SECS);
+        assert!(state.pending_uuids.is_empty());
+        assert_eq!(state.pending_ips.keys().collect::<Vec<_>>(), vec!["Fresh"]);
+    }
+}
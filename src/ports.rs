@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::net::{SocketAddr, TcpListener};
+
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::Server;
+use crate::properties::ServerProperties;
+
+/// range of ports handed out to servers, both ends included
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct PortPool {
+    pub first: u16,
+    pub last: u16,
+}
+
+impl Default for PortPool {
+    fn default() -> Self {
+        Self {
+            first: 25565,
+            last: 25664,
+        }
+    }
+}
+
+impl PortPool {
+    /// the first port in the pool that isn't taken and nothing on the host is listening on
+    pub fn allocate(&self, taken: &HashSet<u16>) -> Option<u16> {
+        (self.first..=self.last).find(|x| !taken.contains(x) && port_available(*x))
+    }
+}
+
+/// every port reserved by a server or the proxy, skipping the given server
+pub fn taken_ports(
+    servers: &[Server],
+    proxy_bind: Option<&str>,
+    skip: Option<usize>,
+) -> HashSet<u16> {
+    let mut taken: HashSet<u16> = servers
+        .iter()
+        .filter(|x| Some(x.id) != skip)
+        .flat_map(|x| [Some(x.port), x.rcon_port])
+        .flatten()
+        .collect();
+    if let Some(address) = proxy_bind.and_then(|x| x.parse::<SocketAddr>().ok()) {
+        taken.insert(address.port());
+    }
+    taken
+}
+
+/// whether a tcp listener could bind the port on every interface right now
+pub fn port_available(port: u16) -> bool {
+    TcpListener::bind(("0.0.0.0", port)).is_ok()
+}
+
+/// points the server's game, query and rcon ports at the ones it was given
+pub async fn write_ports(server: &Server) -> std::io::Result<()> {
+    let mut properties = ServerProperties::load(server.id).await;
+    properties.set("server-port", &server.port.to_string());
+    // query is udp so it can share the game port
+    properties.set("query.port", &server.port.to_string());
+    if let Some(rcon_port) = server.rcon_port {
+        properties.set("rcon.port", &rcon_port.to_string());
+    }
+    properties.write(server.id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a port nothing was listening on a moment ago
+    fn free_port() -> u16 {
+        TcpListener::bind(("0.0.0.0", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    fn server(id: usize, port: u16, rcon_port: Option<u16>) -> Server {
+        let mut server = Server::new(format!("s{}", id), "1.21".to_string(), true);
+        server.id = id;
+        server.port = port;
+        server.rcon_port = rcon_port;
+        server
+    }
+
+    #[test]
+    fn collects_taken_ports() {
+        let servers = [server(0, 25565, Some(25566)), server(1, 25567, None)];
+        assert_eq!(
+            taken_ports(&servers, Some("0.0.0.0:25600"), None),
+            HashSet::from([25565, 25566, 25567, 25600])
+        );
+        assert_eq!(
+            taken_ports(&servers, Some("not an address"), Some(0)),
+            HashSet::from([25567])
+        );
+    }
+
+    #[test]
+    fn runs_out_of_ports() {
+        let port = free_port();
+        let pool = PortPool {
+            first: port,
+            last: port,
+        };
+        assert_eq!(pool.allocate(&HashSet::from([port])), None);
+    }
+
+    #[test]
+    fn skips_ports_in_use() {
+        let listener = TcpListener::bind(("0.0.0.0", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let pool = PortPool {
+            first: port,
+            last: port,
+        };
+        assert_eq!(pool.allocate(&HashSet::new()), None);
+        drop(listener);
+        assert_eq!(pool.allocate(&HashSet::new()), Some(port));
+    }
+
+    #[test]
+    fn reuses_a_deleted_servers_port() {
+        let port = free_port();
+        let pool = PortPool {
+            first: port,
+            last: port,
+        };
+        let mut servers = vec![server(0, port, None), server(1, 1, Some(2))];
+        assert_eq!(pool.allocate(&taken_ports(&servers, None, None)), None);
+        servers.retain(|x| x.id != 0);
+        assert_eq!(
+            pool.allocate(&taken_ports(&servers, None, None)),
+            Some(port)
+        );
+    }
+}
@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tokio::fs;
+
+use crate::worlds::is_valid_world_name;
+
+/// keys mcmanage writes itself before every start, see [crate::ports::write_ports]
+pub const MANAGED_KEYS: [&str; 3] = ["server-port", "query.port", "rcon.port"];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum PropertyKind {
+    Bool,
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Choice {
+        options: &'static [&'static str],
+    },
+    Text {
+        max_length: usize,
+    },
+    /// a folder in the game directory, the worlds page deletes it on a reset
+    WorldName,
+}
+
+impl PropertyKind {
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        match self {
+            PropertyKind::Bool => match value {
+                "true" | "false" => Ok(()),
+                _ => Err("must be true or false".to_string()),
+            },
+            PropertyKind::Integer { min, max } => match value.parse::<i64>() {
+                Ok(number) if number >= *min && number <= *max => Ok(()),
+                _ => Err(format!("must be a whole number from {} to {}", min, max)),
+            },
+            PropertyKind::Choice { options } => {
+                if options.contains(&value) {
+                    Ok(())
+                } else {
+                    Err(format!("must be one of {}", options.join(", ")))
+                }
+            }
+            PropertyKind::WorldName => {
+                if value.is_empty() || is_valid_world_name(value) {
+                    Ok(())
+                } else {
+                    Err(
+                        "must be a plain folder name of letters, digits, spaces, - and _"
+                            .to_string(),
+                    )
+                }
+            }
+            PropertyKind::Text { max_length } => {
+                if value.chars().count() > *max_length {
+                    Err(format!("must be at most {} characters", max_length))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PropertySpec {
+    pub key: &'static str,
+    pub kind: PropertyKind,
+    pub description: &'static str,
+}
+
+const fn spec(key: &'static str, kind: PropertyKind, description: &'static str) -> PropertySpec {
+    PropertySpec {
+        key,
+        kind,
+        description,
+    }
+}
+
+const BOOL: PropertyKind = PropertyKind::Bool;
+const TEXT: PropertyKind = PropertyKind::Text { max_length: 256 };
+const fn int(min: i64, max: i64) -> PropertyKind {
+    PropertyKind::Integer { min, max }
+}
+
+/// the vanilla keys worth offering a proper input for, anything else is edited as text
+pub const KNOWN_PROPERTIES: &[PropertySpec] = &[
+    spec(
+        "motd",
+        PropertyKind::Text { max_length: 59 },
+        "message shown in the server list",
+    ),
+    spec(
+        "difficulty",
+        PropertyKind::Choice {
+            options: &["peaceful", "easy", "normal", "hard"],
+        },
+        "",
+    ),
+    spec(
+        "gamemode",
+        PropertyKind::Choice {
+            options: &["survival", "creative", "adventure", "spectator"],
+        },
+        "gamemode new players start in",
+    ),
+    spec(
+        "force-gamemode",
+        BOOL,
+        "put players back in the default gamemode when they join",
+    ),
+    spec("hardcore", BOOL, "players are banned when they die"),
+    spec("max-players", int(0, i32::MAX as i64), ""),
+    spec("view-distance", int(3, 32), "chunks sent to players"),
+    spec(
+        "simulation-distance",
+        int(3, 32),
+        "chunks around players that are ticked",
+    ),
+    spec("pvp", BOOL, "players can hurt each other"),
+    spec(
+        "online-mode",
+        BOOL,
+        "check players against mojang's session servers",
+    ),
+    spec("white-list", BOOL, "only whitelisted players can join"),
+    spec(
+        "enforce-whitelist",
+        BOOL,
+        "kick players that are removed from the whitelist",
+    ),
+    spec(
+        "allow-flight",
+        BOOL,
+        "don't kick players for flying in survival",
+    ),
+    spec("allow-nether", BOOL, ""),
+    spec(
+        "spawn-protection",
+        int(0, i32::MAX as i64),
+        "radius around spawn only ops can build in",
+    ),
+    spec("enable-command-block", BOOL, ""),
+    spec("generate-structures", BOOL, ""),
+    spec(
+        "level-name",
+        PropertyKind::WorldName,
+        "world folder the server loads",
+    ),
+    spec(
+        "level-seed",
+        TEXT,
+        "seed used when a new world is generated",
+    ),
+    spec(
+        "max-world-size",
+        int(1, 29999984),
+        "world border radius in blocks",
+    ),
+    spec(
+        "player-idle-timeout",
+        int(0, i32::MAX as i64),
+        "minutes before idle players are kicked, 0 to never kick",
+    ),
+    spec("op-permission-level", int(0, 4), ""),
+    spec("function-permission-level", int(1, 4), ""),
+    spec("entity-broadcast-range-percentage", int(10, 1000), ""),
+    spec(
+        "network-compression-threshold",
+        int(-1, i32::MAX as i64),
+        "-1 turns compression off",
+    ),
+    spec(
+        "max-tick-time",
+        int(-1, i64::MAX),
+        "milliseconds before the watchdog stops a stuck server, -1 to disable",
+    ),
+    spec("hide-online-players", BOOL, ""),
+    spec("enable-status", BOOL, "answer server list pings"),
+    spec("enable-query", BOOL, ""),
+    spec("enable-rcon", BOOL, ""),
+    spec("rcon.password", TEXT, ""),
+    spec(
+        "resource-pack",
+        TEXT,
+        "url of a resource pack offered to players",
+    ),
+    spec("require-resource-pack", BOOL, ""),
+    spec(
+        "enforce-secure-profile",
+        BOOL,
+        "require players to have signed chat",
+    ),
+    spec("sync-chunk-writes", BOOL, ""),
+];
+
+pub fn known_property(key: &str) -> Option<&'static PropertySpec> {
+    KNOWN_PROPERTIES.iter().find(|x| x.key == key)
+}
+
+/// checks a single key and value before it is written
+pub fn validate_property(key: &str, value: &str) -> Result<(), String> {
+    if MANAGED_KEYS.contains(&key) {
+        return Err(format!("{} is managed by mcmanage", key));
+    }
+    if key.is_empty() || key.contains(['=', ':', '#', '!', ' ', '\n', '\r']) {
+        return Err(format!("invalid key {}", key));
+    }
+    if value.contains(['\n', '\r']) {
+        return Err(format!("{}: value must be a single line", key));
+    }
+    match known_property(key) {
+        Some(spec) => spec
+            .kind
+            .validate(value)
+            .map_err(|err| format!("{}: {}", key, err)),
+        None => Ok(()),
+    }
+}
+
+/// one row of the editor, a known key the file doesn't have yet shows up with an empty value
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyField {
+    pub key: String,
+    pub value: String,
+    pub kind: PropertyKind,
+    pub description: &'static str,
+    pub managed: bool,
+}
+
+/// undoes the escapes of java's `Properties`, including line continuations
+fn unescape(raw: &str) -> String {
+    let mut value = String::new();
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => value.push('\t'),
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('f') => value.push('\x0c'),
+            Some('u') => {
+                let hex: String = chars.clone().take(4).collect();
+                match u32::from_str_radix(&hex, 16) {
+                    Ok(unit) if hex.len() == 4 => {
+                        chars.nth(3);
+                        // characters outside the bmp are written as two escaped surrogates
+                        let mut units = vec![unit as u16];
+                        if (0xd800..0xdc00).contains(&unit) {
+                            let mut ahead = chars.clone();
+                            if ahead.next() == Some('\\') && ahead.next() == Some('u') {
+                                let low: String = ahead.take(4).collect();
+                                if let Ok(low) = u16::from_str_radix(&low, 16) {
+                                    units.push(low);
+                                    chars.nth(5);
+                                }
+                            }
+                        }
+                        value.extend(char::decode_utf16(units).map(|x| x.unwrap_or('\u{fffd}')));
+                    }
+                    _ => value.push('u'),
+                }
+            }
+            // a continued line, the next one's indentation isn't part of the value
+            Some('\n') => {
+                while chars
+                    .next_if(|x| matches!(x, ' ' | '\t' | '\x0c'))
+                    .is_some()
+                {}
+            }
+            Some(other) => value.push(other),
+            None => {}
+        }
+    }
+    value
+}
+
+/// escapes like java's `Properties.store` to a stream, so anything outside printable ascii
+/// survives however the file gets read
+fn escape(value: &str, is_key: bool) -> String {
+    let mut escaped = String::new();
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            ' ' if is_key || index == 0 => escaped.push_str("\\ "),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\x0c' => escaped.push_str("\\f"),
+            '\\' | '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{:04X}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// whether the line carries on onto the next one, an odd number of trailing backslashes
+fn continues(line: &str) -> bool {
+    line.chars().rev().take_while(|x| *x == '\\').count() % 2 == 1
+}
+
+fn is_comment(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!')
+}
+
+/// a server.properties file kept line by line so comments and unknown keys survive a rewrite
+#[derive(Debug, Clone, Default)]
+pub struct ServerProperties {
+    /// logical lines, a continued entry is kept as one with its line breaks inside
+    lines: Vec<String>,
+}
+
+impl ServerProperties {
+    pub fn path(server_id: usize) -> String {
+        format!("./servers/{}/game/server.properties", server_id)
+    }
+    /// loads the server's properties, empty if the server hasn't generated them yet
+    pub async fn load(server_id: usize) -> ServerProperties {
+        match fs::read(Self::path(server_id)).await {
+            // older servers write latin-1, anything else is escaped so either reads fine
+            Ok(bytes) => Self::parse(
+                &String::from_utf8(bytes)
+                    .unwrap_or_else(|err| err.into_bytes().into_iter().map(char::from).collect()),
+            ),
+            Err(_) => ServerProperties::default(),
+        }
+    }
+    pub fn parse(contents: &str) -> ServerProperties {
+        let mut lines: Vec<String> = vec![];
+        let mut continued = false;
+        for line in contents.lines() {
+            match lines.last_mut() {
+                Some(last) if continued => {
+                    last.push('\n');
+                    last.push_str(line);
+                }
+                _ => lines.push(line.to_string()),
+            }
+            continued = !is_comment(lines.last().unwrap()) && continues(line);
+        }
+        ServerProperties { lines }
+    }
+    /// the unescaped key and value of a line, none for comments and blank lines
+    fn split(line: &str) -> Option<(String, String)> {
+        if is_comment(line) {
+            return None;
+        }
+        let line = line.trim_start_matches([' ', '\t', '\x0c']);
+        let mut key_end = line.len();
+        let mut escaped = false;
+        for (index, c) in line.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if matches!(c, '=' | ':' | ' ' | '\t' | '\x0c') {
+                key_end = index;
+                break;
+            }
+        }
+        let (key, rest) = line.split_at(key_end);
+        let rest = rest.trim_start_matches([' ', '\t', '\x0c']);
+        let rest = rest.strip_prefix(['=', ':']).unwrap_or(rest);
+        let value = rest.trim_start_matches([' ', '\t', '\x0c']);
+        Some((unescape(key), unescape(value)))
+    }
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.lines
+            .iter()
+            .filter_map(|x| Self::split(x))
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+    /// every key and value in file order
+    pub fn entries(&self) -> Vec<(String, String)> {
+        self.lines.iter().filter_map(|x| Self::split(x)).collect()
+    }
+    /// replaces the value in place, or appends the key if it isn't there yet
+    pub fn set(&mut self, key: &str, value: &str) {
+        let line = format!("{}={}", escape(key, true), escape(value, false));
+        match self
+            .lines
+            .iter()
+            .position(|x| Self::split(x).is_some_and(|(k, _)| k == key))
+        {
+            Some(index) => self.lines[index] = line,
+            None => self.lines.push(line),
+        }
+    }
+    pub fn contents(&self) -> String {
+        let mut contents = self.lines.join("\n");
+        contents.push('\n');
+        contents
+    }
+    /// validates every change before applying any of them, returns whether anything changed
+    pub fn apply(&mut self, changes: &HashMap<String, String>) -> Result<bool, String> {
+        let mut changes: Vec<(&String, &String)> = changes
+            .iter()
+            .filter(|(key, value)| match self.get(key) {
+                Some(current) => current != **value,
+                // leaving a known key the file doesn't have empty keeps the game's default
+                None => !value.is_empty(),
+            })
+            .collect();
+        for (key, value) in &changes {
+            validate_property(key, value)?;
+        }
+        changes.sort();
+        for (key, value) in &changes {
+            self.set(key, value);
+        }
+        Ok(!changes.is_empty())
+    }
+    /// the known keys first in schema order, then everything else the file has
+    pub fn fields(&self) -> Vec<PropertyField> {
+        let mut fields: Vec<PropertyField> = KNOWN_PROPERTIES
+            .iter()
+            .map(|spec| PropertyField {
+                key: spec.key.to_string(),
+                value: self.get(spec.key).unwrap_or_default(),
+                kind: spec.kind,
+                description: spec.description,
+                managed: false,
+            })
+            .collect();
+        for (key, value) in self.entries() {
+            if known_property(&key).is_some() {
+                continue;
+            }
+            fields.push(PropertyField {
+                managed: MANAGED_KEYS.contains(&key.as_str()),
+                key,
+                value,
+                kind: TEXT,
+                description: "",
+            });
+        }
+        fields
+    }
+    pub async fn write(&self, server_id: usize) -> std::io::Result<()> {
+        fs::create_dir_all(format!("./servers/{}/game", server_id)).await?;
+        fs::write(Self::path(server_id), self.contents()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_java_escapes() {
+        let properties = ServerProperties::parse(
+            "# comment\nmotd=A \\u00A7aMinecraft\\\\Server\\:\\n\nkey\\ with\\ spaces = value\nlevel-name : world\nempty\nlong=first \\\n    second\n",
+        );
+        assert_eq!(
+            properties.get("motd").as_deref(),
+            Some("A §aMinecraft\\Server:\n")
+        );
+        assert_eq!(properties.get("key with spaces").as_deref(), Some("value"));
+        assert_eq!(properties.get("level-name").as_deref(), Some("world"));
+        assert_eq!(properties.get("empty").as_deref(), Some(""));
+        assert_eq!(properties.get("long").as_deref(), Some("first second"));
+        assert_eq!(properties.get("comment"), None);
+    }
+
+    #[test]
+    fn round_trips_values() {
+        let values = [
+            "plain",
+            "back\\slash",
+            ":leading colon",
+            "=leading equals",
+            "#not a comment",
+            " leading space",
+            "§6Gold §lServer ✨ 🎮",
+            "tabs\tand\nnewlines",
+            "\\u0041 stays literal",
+        ];
+        for value in values {
+            let mut properties = ServerProperties::parse("# kept\nother=1\n");
+            properties.set("motd", value);
+            let reread = ServerProperties::parse(&properties.contents());
+            assert_eq!(reread.get("motd").as_deref(), Some(value));
+            assert_eq!(reread.get("other").as_deref(), Some("1"));
+            assert!(properties.contents().is_ascii());
+        }
+    }
+
+    #[test]
+    fn keeps_lines_it_does_not_change() {
+        let contents = "#Minecraft server properties\nmotd=a\\\n  b\ncustom-key=x\n";
+        let mut properties = ServerProperties::parse(contents);
+        assert_eq!(properties.contents(), contents);
+        properties.set("custom-key", "y");
+        assert_eq!(
+            properties.contents(),
+            "#Minecraft server properties\nmotd=a\\\n  b\ncustom-key=y\n"
+        );
+        assert_eq!(properties.get("motd").as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn level_name_must_be_a_plain_folder() {
+        for value in [".", "..", "../../..", "a/b", "a\\b", ".hidden"] {
+            assert!(validate_property("level-name", value).is_err(), "{}", value);
+        }
+        assert!(validate_property("level-name", "world").is_ok());
+        assert!(validate_property("level-name", "my world_2").is_ok());
+    }
+}
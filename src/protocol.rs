@@ -0,0 +1,232 @@
+use serde_json::json;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// anything bigger than this before login is not a real client
+const MAX_PACKET: usize = 1 << 16;
+
+pub const STATE_STATUS: i32 = 1;
+pub const STATE_LOGIN: i32 = 2;
+/// sent by 1.20.5+ clients following a transfer packet, handled like a login
+pub const STATE_TRANSFER: i32 = 3;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<i32> {
+    let mut value: u32 = 0;
+    for i in 0..5 {
+        let byte = reader.read_u8().await?;
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+pub fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7f == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+}
+
+/// reads a length prefixed packet, returning its body with the packet id still at the front
+pub async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let length = read_varint(reader).await?;
+    if length <= 0 || length as usize > MAX_PACKET {
+        return Err(invalid("bad packet length"));
+    }
+    let mut body = vec![0; length as usize];
+    reader.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+pub fn encode_packet(id: i32, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![];
+    write_varint(&mut body, id);
+    body.extend_from_slice(payload);
+    let mut packet = vec![];
+    write_varint(&mut packet, body.len() as i32);
+    packet.extend(body);
+    packet
+}
+
+pub async fn write_packet<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    id: i32,
+    payload: &[u8],
+) -> io::Result<()> {
+    writer.write_all(&encode_packet(id, payload)).await?;
+    writer.flush().await
+}
+
+pub fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as i32);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// cursor over a packet body
+pub struct PacketReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+    pub fn varint(&mut self) -> Option<i32> {
+        let mut value: u32 = 0;
+        for i in 0..5 {
+            let byte = *self.data.get(self.position)?;
+            self.position += 1;
+            value |= ((byte & 0x7f) as u32) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some(value as i32);
+            }
+        }
+        None
+    }
+    pub fn string(&mut self) -> Option<String> {
+        let length = self.varint()?;
+        if length < 0 {
+            return None;
+        }
+        let end = self.position.checked_add(length as usize)?;
+        let value = String::from_utf8(self.data.get(self.position..end)?.to_vec()).ok()?;
+        self.position = end;
+        Some(value)
+    }
+    pub fn u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(self.position..self.position + 2)?;
+        self.position += 2;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.position.min(self.data.len())..]
+    }
+}
+
+/// the first packet every client sends
+#[derive(Debug, Clone)]
+pub struct Handshake {
+    pub protocol_version: i32,
+    pub server_address: String,
+    pub server_port: u16,
+    pub next_state: i32,
+}
+
+impl Handshake {
+    /// parses the body of a handshake packet, none if it is anything else
+    pub fn parse(body: &[u8]) -> Option<Handshake> {
+        let mut reader = PacketReader::new(body);
+        if reader.varint()? != 0x00 {
+            return None;
+        }
+        Some(Handshake {
+            protocol_version: reader.varint()?,
+            server_address: reader.string()?,
+            server_port: reader.u16()?,
+            next_state: reader.varint()?,
+        })
+    }
+    /// the hostname the client connected with, without forge markers or a trailing dot
+    pub fn hostname(&self) -> String {
+        self.server_address
+            .split('\0')
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('.')
+            .to_lowercase()
+    }
+}
+
+/// answers a status request and the ping after it with the given motd
+pub async fn respond_status<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    protocol_version: i32,
+    version_name: &str,
+    motd: &str,
+) -> io::Result<()> {
+    let request = read_packet(stream).await?;
+    if PacketReader::new(&request).varint() != Some(0x00) {
+        return Err(invalid("expected status request"));
+    }
+    let status = json!({
+        "version": { "name": version_name, "protocol": protocol_version },
+        "players": { "max": 0, "online": 0 },
+        "description": { "text": motd },
+    });
+    let mut payload = vec![];
+    write_string(&mut payload, &status.to_string());
+    write_packet(stream, 0x00, &payload).await?;
+
+    let ping = read_packet(stream).await?;
+    let mut reader = PacketReader::new(&ping);
+    if reader.varint() != Some(0x01) {
+        return Err(invalid("expected ping"));
+    }
+    write_packet(stream, 0x01, reader.remaining()).await
+}
+
+/// kicks a client that is in the login state with a message
+pub async fn disconnect_login<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &str,
+) -> io::Result<()> {
+    let mut payload = vec![];
+    write_string(&mut payload, &json!({ "text": message }).to_string());
+    write_packet(writer, 0x00, &payload).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn varints_round_trip() {
+        for value in [0, 1, 127, 128, 255, 25565, 2097151, i32::MAX, -1] {
+            let mut buf = vec![];
+            write_varint(&mut buf, value);
+            assert_eq!(PacketReader::new(&buf).varint(), Some(value));
+            assert_eq!(read_varint(&mut buf.as_slice()).await.unwrap(), value);
+        }
+        let mut buf = vec![];
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, [0xac, 0x02]);
+    }
+
+    #[tokio::test]
+    async fn parses_handshakes() {
+        let mut payload = vec![];
+        write_varint(&mut payload, 767);
+        write_string(&mut payload, "Play.Example.com.\0FML3\0");
+        payload.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut payload, STATE_LOGIN);
+        let packet = encode_packet(0x00, &payload);
+
+        let body = read_packet(&mut packet.as_slice()).await.unwrap();
+        let handshake = Handshake::parse(&body).unwrap();
+        assert_eq!(handshake.protocol_version, 767);
+        assert_eq!(handshake.server_port, 25565);
+        assert_eq!(handshake.next_state, STATE_LOGIN);
+        assert_eq!(handshake.hostname(), "play.example.com");
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_packets() {
+        assert!(Handshake::parse(&[0x01, 0x00, 0x00]).is_none());
+        assert!(Handshake::parse(&[0x00, 0x80]).is_none());
+        let mut oversized = vec![];
+        write_varint(&mut oversized, MAX_PACKET as i32 + 1);
+        assert!(read_packet(&mut oversized.as_slice()).await.is_err());
+        assert!(read_varint(&mut [0xff; 5].as_slice()).await.is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use actix_web::rt::spawn;
+use serde::{Deserialize, Serialize};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use crate::configuration::{ConfigurationManager, Server};
+use crate::protocol::{
+    Handshake, STATE_LOGIN, STATE_STATUS, STATE_TRANSFER, disconnect_login, read_packet,
+    respond_status, write_varint,
+};
+
+/// how long a client gets to send its handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const PROXY_V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxySettings {
+    /// public address players connect to, must not be any server's own port
+    pub bind_address: String,
+    /// server used when the hostname doesn't match any server's domain
+    #[serde(default)]
+    pub default_server: Option<usize>,
+    /// send a PROXY protocol v2 header to backends so they see the real client address,
+    /// the backends must be configured to expect it
+    #[serde(default)]
+    pub proxy_protocol: bool,
+}
+
+/// builds a PROXY protocol v2 header for a proxied tcp connection
+pub fn proxy_v2_header(source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+    let mut header = PROXY_V2_SIGNATURE.to_vec();
+    // version 2, PROXY command
+    header.push(0x21);
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            // AF_INET over STREAM
+            header.push(0x11);
+            header.extend_from_slice(&12u16.to_be_bytes());
+            header.extend_from_slice(&src.octets());
+            header.extend_from_slice(&dst.octets());
+        }
+        (src, dst) => {
+            let to_v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            // AF_INET6 over STREAM
+            header.push(0x21);
+            header.extend_from_slice(&36u16.to_be_bytes());
+            header.extend_from_slice(&to_v6(src).octets());
+            header.extend_from_slice(&to_v6(dst).octets());
+        }
+    }
+    header.extend_from_slice(&source.port().to_be_bytes());
+    header.extend_from_slice(&destination.port().to_be_bytes());
+    header
+}
+
+/// reads a PROXY protocol v2 header off the start of a connection if there is one,
+/// returning whatever was read that turned out not to be part of one
+pub async fn skip_proxy_header<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut read = vec![];
+    for expected in PROXY_V2_SIGNATURE {
+        let byte = reader.read_u8().await?;
+        read.push(byte);
+        if byte != expected {
+            return Ok(read);
+        }
+    }
+    // version and command, address family, then the length of the addresses
+    let mut rest = [0; 4];
+    reader.read_exact(&mut rest).await?;
+    let mut addresses = vec![0; u16::from_be_bytes([rest[2], rest[3]]) as usize];
+    reader.read_exact(&mut addresses).await?;
+    Ok(vec![])
+}
+
+/// picks the server a hostname belongs to, falling back to the default server
+pub fn route<'a>(
+    servers: &'a [Server],
+    hostname: &str,
+    default_server: Option<usize>,
+) -> Option<&'a Server> {
+    servers
+        .iter()
+        .find(|x| {
+            x.domain
+                .as_ref()
+                .is_some_and(|domain| domain.trim_end_matches('.').eq_ignore_ascii_case(hostname))
+        })
+        .or_else(|| servers.iter().find(|x| Some(x.id) == default_server))
+}
+
+/// tcp proxy that sends players to a server based on the address they connected with
+pub struct Proxy;
+
+impl Proxy {
+    /// starts the proxy if it is configured
+    pub async fn begin(config: ConfigurationManager) {
+        let Some(settings) = config.get_proxy_settings().await else {
+            return;
+        };
+        let listener = match TcpListener::bind(&settings.bind_address).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                println!(
+                    "could not bind proxy address {}, not proxying: {}",
+                    &settings.bind_address, err
+                );
+                return;
+            }
+        };
+        println!("proxy listening on {}", &settings.bind_address);
+        spawn(async move {
+            loop {
+                let Ok((stream, client)) = listener.accept().await else {
+                    continue;
+                };
+                let config = config.clone();
+                spawn(async move {
+                    if let Err(err) = Self::handle(stream, client, config).await {
+                        println!("proxy connection from {} failed: {}", client, err);
+                    }
+                });
+            }
+        });
+    }
+    async fn handle(
+        mut client: TcpStream,
+        client_address: SocketAddr,
+        config: ConfigurationManager,
+    ) -> io::Result<()> {
+        let body = timeout(HANDSHAKE_TIMEOUT, read_packet(&mut client)).await??;
+        let Some(handshake) = Handshake::parse(&body) else {
+            // legacy pings and garbage, nothing sensible to answer with
+            return Ok(());
+        };
+        let Some(settings) = config.get_proxy_settings().await else {
+            return Ok(());
+        };
+        let servers = config.get_servers().await;
+        let hostname = handshake.hostname();
+        let Some(server) = route(&servers, &hostname, settings.default_server) else {
+            return Self::reject(&mut client, &handshake, "unknown server address").await;
+        };
+        if !server.enabled {
+            return Self::reject(&mut client, &handshake, "server is offline").await;
+        }
+
+        let backend_address = SocketAddr::from(([127, 0, 0, 1], server.port));
+        let mut backend = match timeout(CONNECT_TIMEOUT, TcpStream::connect(backend_address)).await
+        {
+            Ok(Ok(backend)) => backend,
+            _ => {
+                return Self::reject(&mut client, &handshake, "server is starting or offline")
+                    .await;
+            }
+        };
+        if settings.proxy_protocol {
+            let local = client.local_addr()?;
+            backend
+                .write_all(&proxy_v2_header(client_address, local))
+                .await?;
+        }
+        // replay the handshake untouched so the backend sees what the client sent
+        let mut length = vec![];
+        write_varint(&mut length, body.len() as i32);
+        backend.write_all(&length).await?;
+        backend.write_all(&body).await?;
+        io::copy_bidirectional(&mut client, &mut backend).await?;
+        Ok(())
+    }
+    async fn reject(
+        client: &mut TcpStream,
+        handshake: &Handshake,
+        message: &str,
+    ) -> io::Result<()> {
+        match handshake.next_state {
+            STATE_STATUS => {
+                timeout(
+                    HANDSHAKE_TIMEOUT,
+                    respond_status(client, handshake.protocol_version, "mcmanage", message),
+                )
+                .await?
+            }
+            STATE_LOGIN | STATE_TRANSFER => disconnect_login(client, message).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_ipv4_headers() {
+        let header = proxy_v2_header(
+            "203.0.113.7:51234".parse().unwrap(),
+            "127.0.0.1:25565".parse().unwrap(),
+        );
+        let mut expected = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+        expected.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        expected.extend_from_slice(&[203, 0, 113, 7, 127, 0, 0, 1]);
+        expected.extend_from_slice(&51234u16.to_be_bytes());
+        expected.extend_from_slice(&25565u16.to_be_bytes());
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn encodes_ipv6_and_mixed_headers() {
+        let header = proxy_v2_header(
+            "[2001:db8::1]:40000".parse().unwrap(),
+            "127.0.0.1:25565".parse().unwrap(),
+        );
+        assert_eq!(header.len(), 16 + 36);
+        assert_eq!(&header[12..16], &[0x21, 0x21, 0, 36]);
+        let source: [u8; 16] = header[16..32].try_into().unwrap();
+        let destination: [u8; 16] = header[32..48].try_into().unwrap();
+        assert_eq!(
+            source,
+            "2001:db8::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+        assert_eq!(
+            destination,
+            "::ffff:127.0.0.1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+        assert_eq!(&header[48..50], &40000u16.to_be_bytes());
+        assert_eq!(&header[50..52], &25565u16.to_be_bytes());
+    }
+
+    #[test]
+    fn routes_by_domain() {
+        let server = |id: usize, domain: Option<&str>| {
+            let mut server = Server::new(format!("s{}", id), "1.21".to_string(), true);
+            server.id = id;
+            server.domain = domain.map(str::to_string);
+            server
+        };
+        let servers = vec![
+            server(0, Some("survival.example.com")),
+            server(1, Some("Creative.Example.com.")),
+            server(2, None),
+        ];
+        let routed = |hostname: &str, default_server: Option<usize>| {
+            route(&servers, hostname, default_server).map(|x| x.id)
+        };
+        assert_eq!(routed("survival.example.com", None), Some(0));
+        assert_eq!(routed("creative.example.com", None), Some(1));
+        assert_eq!(routed("unknown.example.com", Some(2)), Some(2));
+        assert_eq!(routed("unknown.example.com", None), None);
+    }
+}
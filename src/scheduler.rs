@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use actix_web::rt::spawn;
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::backups::{BackupKind, backup_server};
+use crate::configuration::ConfigurationManager;
+use crate::players::now;
+use crate::server_runner::ServerRunnerHandle;
+
+const HISTORY_PATH: &str = "task_history.toml";
+/// runs kept in the task history
+const MAX_HISTORY: usize = 500;
+/// how often the scheduler checks for due tasks
+const TICK: Duration = Duration::from_secs(5);
+/// seconds before a restart at which players get warned
+const WARNINGS: [u64; 9] = [900, 600, 300, 60, 30, 10, 3, 2, 1];
+
+lazy_static! {
+    /// held for every read-modify-write of the history, runs finish concurrently
+    static ref HISTORY_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// standard five field cron expression, minute hour day-of-month month day-of-week
+    Cron {
+        expression: String,
+    },
+    Interval {
+        minutes: u64,
+    },
+}
+
+impl Schedule {
+    /// the cron crate wants a leading seconds field
+    fn parse_cron(expression: &str) -> Result<cron::Schedule, String> {
+        let expression = match expression.split_whitespace().count() {
+            5 => format!("0 {}", expression),
+            _ => expression.to_string(),
+        };
+        cron::Schedule::from_str(&expression).map_err(|x| x.to_string())
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Schedule::Cron { expression } => Self::parse_cron(expression).map(|_| ()),
+            Schedule::Interval { minutes: 0 } => Err("interval must be at least a minute".into()),
+            Schedule::Interval { .. } => Ok(()),
+        }
+    }
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Schedule::Cron { expression } => {
+                Self::parse_cron(expression).ok()?.after(&after).next()
+            }
+            Schedule::Interval { minutes } => {
+                Some(after + chrono::Duration::minutes(*minutes as i64))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TaskAction {
+    /// restart the server, warning players in chat during the countdown
+    Restart {
+        countdown_secs: u64,
+    },
+    Command {
+        command: String,
+    },
+    Broadcast {
+        message: String,
+    },
+    Start,
+    Stop,
+    /// a backup kept according to the server's retention
+    Backup,
+}
+
+impl TaskAction {
+    pub fn describe(&self) -> String {
+        match self {
+            TaskAction::Restart { countdown_secs } => {
+                format!("restart with a {}s countdown", countdown_secs)
+            }
+            TaskAction::Command { command } => format!("run `{}`", command),
+            TaskAction::Broadcast { message } => format!("broadcast \"{}\"", message),
+            TaskAction::Start => "start".to_string(),
+            TaskAction::Stop => "stop".to_string(),
+            TaskAction::Backup => "back up".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScheduledTask {
+    pub id: usize,
+    pub server_id: usize,
+    pub name: String,
+    pub schedule: Schedule,
+    pub action: TaskAction,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaskRun {
+    pub task_id: usize,
+    pub server_id: usize,
+    pub task_name: String,
+    /// unix timestamp in seconds
+    pub started_at: u64,
+    /// unix timestamp in seconds
+    pub finished_at: u64,
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TaskHistory {
+    pub runs: Vec<TaskRun>,
+}
+
+impl TaskHistory {
+    pub async fn load() -> Result<TaskHistory, String> {
+        match fs::read_to_string(HISTORY_PATH).await {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| format!("invalid task history in {}: {}", HISTORY_PATH, err)),
+            Err(_) => Ok(TaskHistory::default()),
+        }
+    }
+    /// writes next to the history and moves it over, so readers never see half of it
+    async fn write(&self) -> std::io::Result<()> {
+        let partial = format!("{}.partial", HISTORY_PATH);
+        let mut file = File::create(&partial).await?;
+        file.write_all(toml::to_string_pretty(&self).unwrap().as_bytes())
+            .await?;
+        file.flush().await?;
+        fs::rename(&partial, HISTORY_PATH).await
+    }
+    async fn record(run: TaskRun) {
+        let _lock = HISTORY_LOCK.lock().await;
+        let mut history = match Self::load().await {
+            Ok(history) => history,
+            Err(err) => {
+                println!("not recording the run of {}: {}", run.task_name, err);
+                return;
+            }
+        };
+        history.runs.push(run);
+        if history.runs.len() > MAX_HISTORY {
+            let excess = history.runs.len() - MAX_HISTORY;
+            history.runs.drain(..excess);
+        }
+        if let Err(err) = history.write().await {
+            println!("failed to write task history: {}", err);
+        }
+    }
+    pub fn for_server(&self, server_id: usize) -> Vec<TaskRun> {
+        self.runs
+            .iter()
+            .rev()
+            .filter(|x| x.server_id == server_id)
+            .cloned()
+            .collect()
+    }
+}
+
+fn format_countdown(secs: u64) -> String {
+    match secs {
+        60.. if secs.is_multiple_of(60) => format!("{} minute(s)", secs / 60),
+        _ => format!("{} second(s)", secs),
+    }
+}
+
+/// runs the configured tasks when they come due
+pub struct Scheduler {
+    config: ConfigurationManager,
+    runner_handle: ServerRunnerHandle,
+    /// next run per task id, along with the schedule it was computed from
+    upcoming: HashMap<usize, (Schedule, DateTime<Local>)>,
+}
+
+impl Scheduler {
+    pub fn new(config: ConfigurationManager, runner_handle: ServerRunnerHandle) -> Self {
+        Self {
+            config,
+            runner_handle,
+            upcoming: HashMap::new(),
+        }
+    }
+    pub fn begin(config: ConfigurationManager, runner_handle: ServerRunnerHandle) {
+        spawn(Self::new(config, runner_handle).run());
+    }
+    pub async fn run(mut self) {
+        loop {
+            sleep(TICK).await;
+            let tasks = self.config.get_tasks().await;
+            let current = Local::now();
+            self.upcoming
+                .retain(|id, _| tasks.iter().any(|x| x.id == *id && x.enabled));
+            for task in tasks.into_iter().filter(|x| x.enabled) {
+                let next = match self.upcoming.get(&task.id) {
+                    Some((schedule, next)) if *schedule == task.schedule => *next,
+                    _ => {
+                        let Some(next) = task.schedule.next_after(current) else {
+                            continue;
+                        };
+                        self.upcoming.insert(task.id, (task.schedule.clone(), next));
+                        next
+                    }
+                };
+                if next > current {
+                    continue;
+                }
+                match task.schedule.next_after(current) {
+                    Some(next) => {
+                        self.upcoming.insert(task.id, (task.schedule.clone(), next));
+                    }
+                    None => {
+                        self.upcoming.remove(&task.id);
+                    }
+                }
+                let config = self.config.clone();
+                let runner_handle = self.runner_handle.clone();
+                spawn(async move {
+                    let started_at = now();
+                    let result = Self::execute(&config, &runner_handle, &task).await;
+                    TaskHistory::record(TaskRun {
+                        task_id: task.id,
+                        server_id: task.server_id,
+                        task_name: task.name.clone(),
+                        started_at,
+                        finished_at: now(),
+                        success: result.is_ok(),
+                        message: result.unwrap_or_else(|x| x),
+                    })
+                    .await;
+                });
+            }
+        }
+    }
+    /// carries out a task, returning a short description of what happened
+    pub async fn execute(
+        config: &ConfigurationManager,
+        runner_handle: &ServerRunnerHandle,
+        task: &ScheduledTask,
+    ) -> Result<String, String> {
+        let Some(server) = config.get_server(task.server_id).await else {
+            return Err("server no longer exists".to_string());
+        };
+        match &task.action {
+            TaskAction::Restart { countdown_secs } => {
+                if !server.enabled {
+                    return Err("server is disabled".to_string());
+                }
+                let mut remaining = *countdown_secs;
+                for warning in WARNINGS.into_iter().filter(|x| *x <= *countdown_secs) {
+                    sleep(Duration::from_secs(remaining - warning)).await;
+                    remaining = warning;
+                    runner_handle.issue_command(
+                        server.id,
+                        format!("say server restarting in {}", format_countdown(warning)),
+                    );
+                }
+                sleep(Duration::from_secs(remaining)).await;
+                runner_handle.stop_gracefully(server.id).await;
+                runner_handle.start_server(server);
+                Ok("restarted".to_string())
+            }
+            TaskAction::Command { command } => {
+                if !server.enabled {
+                    return Err("server is disabled".to_string());
+                }
+                runner_handle.issue_command(server.id, command.clone());
+                Ok(format!("ran `{}`", command))
+            }
+            TaskAction::Broadcast { message } => {
+                if !server.enabled {
+                    return Err("server is disabled".to_string());
+                }
+                runner_handle.issue_command(server.id, format!("say {}", message));
+                Ok("broadcast sent".to_string())
+            }
+            TaskAction::Start => {
+                // only one server may run at a time, same as enabling it from the dash
+                for status in runner_handle.get_statuses().await {
+                    runner_handle.stop_gracefully(status.id).await;
+                }
+                config.disable_all().await;
+                config.set_server_enabled(server.id, true).await;
+                config.start_all(runner_handle.clone()).await;
+                Ok("started".to_string())
+            }
+            TaskAction::Stop => {
+                runner_handle.stop_gracefully(server.id).await;
+                config.set_server_enabled(server.id, false).await;
+                Ok("stopped".to_string())
+            }
+            TaskAction::Backup => {
+                let backup = backup_server(runner_handle, &server, BackupKind::Scheduled).await?;
+                Ok(format!("wrote {}", backup.name))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 3, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn validates_schedules() {
+        let cron = |expression: &str| Schedule::Cron {
+            expression: expression.to_string(),
+        };
+        assert!(cron("0 4 * * *").validate().is_ok());
+        assert!(cron("*/15 * * * 1-5").validate().is_ok());
+        assert!(cron("0 4 * *").validate().is_err());
+        assert!(cron("61 * * * *").validate().is_err());
+        assert!(Schedule::Interval { minutes: 0 }.validate().is_err());
+        assert!(Schedule::Interval { minutes: 30 }.validate().is_ok());
+    }
+
+    #[test]
+    fn cron_runs_at_the_next_match() {
+        let daily = Schedule::Cron {
+            expression: "0 4 * * *".to_string(),
+        };
+        assert_eq!(daily.next_after(at(10, 3, 59)), Some(at(10, 4, 0)));
+        assert_eq!(daily.next_after(at(10, 4, 0)), Some(at(11, 4, 0)));
+        let quarterly = Schedule::Cron {
+            expression: "*/15 * * * *".to_string(),
+        };
+        assert_eq!(quarterly.next_after(at(10, 12, 7)), Some(at(10, 12, 15)));
+    }
+
+    #[test]
+    fn interval_runs_minutes_later() {
+        let schedule = Schedule::Interval { minutes: 90 };
+        assert_eq!(schedule.next_after(at(10, 12, 0)), Some(at(10, 13, 30)));
+    }
+
+    #[test]
+    fn formats_countdowns() {
+        assert_eq!(format_countdown(600), "10 minute(s)");
+        assert_eq!(format_countdown(90), "90 second(s)");
+        assert_eq!(format_countdown(30), "30 second(s)");
+    }
+}
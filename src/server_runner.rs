@@ -1,23 +1,47 @@
 use std::collections::{HashMap, VecDeque};
 use std::process::Stdio;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use actix_web::rt::spawn;
+use serde::Serialize;
 use tokio::fs::{self, create_dir_all};
-use tokio::io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
-use tokio::process::{Child, Command};
-use tokio::sync::{RwLock, mpsc, oneshot};
-use tokio::time::timeout;
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::process::Command;
+use tokio::sync::{RwLock, broadcast, mpsc, oneshot};
+use tokio::time::{sleep, timeout};
 
 use crate::configuration::Server;
+use crate::events::{GameEvent, ServerEvent};
+use crate::loaders::prepare;
+use crate::metrics::{ConsoleStats, MetricsHistory, ProcessSample, ProcessSampler};
+use crate::players::{OnlinePlayer, PlayerTracker};
+use crate::ports::{port_available, write_ports};
+use crate::upgrades::take_force_upgrade;
 
 #[derive(Debug, Clone)]
 pub struct ServerRunnerHandle {
     cmd_tx: mpsc::UnboundedSender<RunnerCommand>,
+    events: broadcast::Sender<ServerEvent>,
 }
 
+/// events buffered per subscriber before slow ones start missing them
+const EVENT_CAPACITY: usize = 1024;
+/// how long a start waits for a server's ports to be released
+const PORT_WAIT_SECS: u64 = 5;
+/// how long a graceful stop waits for the server to save and exit before killing it
+const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
 impl ServerRunnerHandle {
+    /// receive every game event parsed from any server's output from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.events.subscribe()
+    }
+    /// sends an event that didn't come from a server's output to every subscriber
+    pub fn publish(&self, event: ServerEvent) {
+        let _ = self.events.send(event);
+    }
     pub fn start_server(&self, server: Server) {
         self.cmd_tx
             .send(RunnerCommand::StartServer { server })
@@ -36,6 +60,18 @@ impl ServerRunnerHandle {
             .send(RunnerCommand::StopServer { id: server })
             .unwrap();
     }
+    /// saves the world and stops the server through its console, killing it only if it
+    /// doesn't exit in time. returns once the process is gone
+    pub async fn stop_gracefully(&self, server: usize) {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(RunnerCommand::StopGracefully {
+                id: server,
+                response_handle: res_tx,
+            })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
     pub fn issue_command(&self, server: usize, command: String) {
         println!("issuing command {}", &command);
         self.cmd_tx
@@ -58,12 +94,61 @@ impl ServerRunnerHandle {
             .unwrap();
         res_rx.await.unwrap()
     }
+    /// returns the sampled resource usage, or none if the server isn't running
+    pub async fn get_metrics(&self, server: usize) -> Option<MetricsHistory> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(RunnerCommand::GetMetrics {
+                id: server,
+                response_handle: res_tx,
+            })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    /// returns a snapshot of every server the runner has started
+    pub async fn get_statuses(&self) -> Vec<ServerStatus> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(RunnerCommand::GetStatuses {
+                response_handle: res_tx,
+            })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn is_running(&self, server: usize) -> bool {
+        self.get_statuses()
+            .await
+            .iter()
+            .any(|x| x.id == server && x.running)
+    }
+    /// flags a running server as needing a restart to pick up changed settings,
+    /// the flag goes away with the process
+    pub fn require_restart(&self, server: usize) {
+        self.cmd_tx
+            .send(RunnerCommand::RequireRestart { id: server })
+            .unwrap();
+    }
+    /// returns the players currently online, or none if the server isn't running
+    pub async fn get_online_players(&self, server: usize) -> Option<Vec<OnlinePlayer>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(RunnerCommand::GetOnlinePlayers {
+                id: server,
+                response_handle: res_tx,
+            })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
 }
 
 pub enum RunnerCommand {
     StartServer {
         server: Server,
     },
+    /// a start that already waited for the server's ports, gives up if they're still taken
+    RetryStart {
+        server: Server,
+    },
     StartAll {
         servers: Vec<Server>,
     },
@@ -71,6 +156,10 @@ pub enum RunnerCommand {
         id: usize,
     },
     StopAll,
+    StopGracefully {
+        id: usize,
+        response_handle: oneshot::Sender<()>,
+    },
     IssueCommand {
         id: usize,
         command: String,
@@ -79,17 +168,84 @@ pub enum RunnerCommand {
         id: usize,
         response_handle: oneshot::Sender<Option<String>>,
     },
+    GetMetrics {
+        id: usize,
+        response_handle: oneshot::Sender<Option<MetricsHistory>>,
+    },
+    GetOnlinePlayers {
+        id: usize,
+        response_handle: oneshot::Sender<Option<Vec<OnlinePlayer>>>,
+    },
+    GetStatuses {
+        response_handle: oneshot::Sender<Vec<ServerStatus>>,
+    },
+    RequireRestart {
+        id: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatus {
+    pub id: usize,
+    pub running: bool,
+    pub uptime_secs: u64,
+    /// times the server was started again after its first start
+    pub restarts: u64,
+    pub online_players: usize,
+    pub usage: Option<ProcessSample>,
+    pub console: ConsoleStats,
+    /// settings were changed since the server started
+    pub restart_required: bool,
 }
 
 pub struct ServerRunner {
     cmd_reciever: mpsc::UnboundedReceiver<RunnerCommand>,
+    /// weak so the runner still shuts down once every handle is dropped
+    cmd_tx: mpsc::WeakUnboundedSender<RunnerCommand>,
     active_servers: HashMap<usize, ServerProcess>,
+    start_counts: HashMap<usize, u64>,
+    events: broadcast::Sender<ServerEvent>,
 }
 
 pub struct ServerProcess {
     child_process: tokio::process::Child,
     text_log: Arc<RwLock<VecDeque<String>>>,
     stdin: tokio::io::BufWriter<tokio::process::ChildStdin>,
+    players: PlayerTracker,
+    metrics: Arc<RwLock<MetricsHistory>>,
+    console: Arc<RwLock<ConsoleStats>>,
+    started_at: Instant,
+    stop_requested: Arc<AtomicBool>,
+    restart_required: bool,
+}
+
+impl ServerProcess {
+    async fn stop(&mut self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        self.child_process
+            .kill()
+            .await
+            .expect("failed to kill server");
+        // close sessions now so the old reader can't race a new tracker's history
+        self.players.end_all_sessions().await;
+    }
+    async fn shut_down(mut self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        let asked = async {
+            self.stdin.write_all(b"save-all\nstop\n").await?;
+            self.stdin.flush().await
+        }
+        .await;
+        let exited = asked.is_ok()
+            && timeout(STOP_TIMEOUT, self.child_process.wait())
+                .await
+                .is_ok_and(|x| x.is_ok());
+        if !exited {
+            println!("server didn't stop in time, killing it");
+            let _ = self.child_process.kill().await;
+        }
+        self.players.end_all_sessions().await;
+    }
 }
 
 async fn append_to_log(text_log: Arc<RwLock<VecDeque<String>>>, line: String) {
@@ -103,48 +259,97 @@ async fn append_to_log(text_log: Arc<RwLock<VecDeque<String>>>, line: String) {
 impl ServerRunner {
     pub fn new() -> (Self, ServerRunnerHandle) {
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
         let new = Self {
             cmd_reciever: cmd_rx,
+            cmd_tx: cmd_tx.downgrade(),
             active_servers: HashMap::new(),
+            start_counts: HashMap::new(),
+            events: events.clone(),
         };
-        (new, ServerRunnerHandle { cmd_tx })
+        (new, ServerRunnerHandle { cmd_tx, events })
     }
-    async fn start_server(&mut self, server: Server) {
+    fn ports_available(server: &Server) -> bool {
+        [Some(server.port), server.rcon_port]
+            .iter()
+            .flatten()
+            .all(|x| port_available(*x))
+    }
+    /// gives a process that was just stopped a few seconds to let go of the server's ports,
+    /// then tries the start again. waits outside the runner so other servers aren't held up
+    fn retry_when_ports_free(&self, server: Server) {
+        let Some(cmd_tx) = self.cmd_tx.upgrade() else {
+            return;
+        };
+        spawn(async move {
+            for _ in 0..PORT_WAIT_SECS {
+                sleep(Duration::from_secs(1)).await;
+                if Self::ports_available(&server) {
+                    break;
+                }
+            }
+            let _ = cmd_tx.send(RunnerCommand::RetryStart { server });
+        });
+    }
+    async fn start_server(&mut self, server: Server, retried: bool) {
         if let Some(mut existing) = self.active_servers.remove(&server.id) {
-            existing
-                .child_process
-                .kill()
-                .await
-                .expect("failed to kill server");
+            existing.stop().await;
         }
-        create_dir_all(&format!("./servers/{}/game", server.id))
+        create_dir_all(format!("./servers/{}/game", server.id))
             .await
             .expect("could not create server directory");
         if server.eula {
             fs::write(
-                &format!("./servers/{}/game/eula.txt", server.id),
+                format!("./servers/{}/game/eula.txt", server.id),
                 "eula=true",
             )
             .await
             .expect("failed to write to eula");
         }
+        if !Self::ports_available(&server) {
+            if !retried {
+                self.retry_when_ports_free(server);
+                return;
+            }
+            println!(
+                "port {} for server {} is in use by something else, not starting it",
+                server.port, server.id
+            );
+            return;
+        }
+        write_ports(&server)
+            .await
+            .expect("failed to write server.properties");
+        if let Err(err) = prepare(&server).await {
+            println!("server {} can't start: {}", server.id, err);
+            return;
+        }
         let mut command = Command::new("java");
-        command.current_dir(&format!("./servers/{}/game", server.id));
+        command.current_dir(format!("./servers/{}/game", server.id));
         command
-            .arg("-jar")
-            .arg(&format!(
-                "../../../server_versions/{}/{}.jar",
-                &server.mc_version_id, &server.mc_version_id
-            ))
+            .args(server.server_type.launch_args(&server.mc_version_id))
             .arg("nogui")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped());
+        if take_force_upgrade(server.id).await {
+            println!("starting server {} with --forceUpgrade", server.id);
+            command.arg("--forceUpgrade");
+        }
         let mut status = command
             .kill_on_drop(true)
             .spawn()
             .expect("could not spawn server");
-        let text_log = Arc::new(RwLock::new(VecDeque::new()));        
-        
+        let text_log = Arc::new(RwLock::new(VecDeque::new()));
+        let players = PlayerTracker::new(server.id).await;
+        let metrics = Arc::new(RwLock::new(MetricsHistory::default()));
+        let console = Arc::new(RwLock::new(ConsoleStats::default()));
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        *self.start_counts.entry(server.id).or_default() += 1;
+        if let Some(pid) = status.id() {
+            let sampler = ProcessSampler::new(pid, format!("./servers/{}/game", server.id).into());
+            spawn(sampler.run(metrics.clone()));
+        }
+
         let stdout = BufReader::new(status.stdout.take().unwrap());
         let stdin = BufWriter::new(status.stdin.take().unwrap());
 
@@ -153,14 +358,33 @@ impl ServerRunner {
             ServerProcess {
                 child_process: status,
                 text_log: text_log.clone(),
-                stdin: stdin,
+                stdin,
+                players: players.clone(),
+                metrics,
+                console: console.clone(),
+                started_at: Instant::now(),
+                stop_requested: stop_requested.clone(),
+                restart_required: false,
             },
         );
+        let events = self.events.clone();
+        let server_id = server.id;
         spawn(async move {
             let mut reader = stdout.lines();
+            let mut stopping = false;
             while let Ok(Some(line)) = reader.next_line().await {
+                if let Some(event) = GameEvent::parse(&line) {
+                    stopping |= event == GameEvent::ServerStopping;
+                    players.handle_event(&event).await;
+                    console.write().await.handle_event(&event);
+                    // nobody listening isn't an error
+                    let _ = events.send(ServerEvent::new(server_id, event));
+                }
                 append_to_log(text_log.clone(), line).await;
             }
+            players.end_all_sessions().await;
+            let crashed = !stopping && !stop_requested.load(Ordering::SeqCst);
+            let _ = events.send(ServerEvent::new(server_id, GameEvent::Exited { crashed }));
             println!("reader dead");
         });
     }
@@ -168,41 +392,67 @@ impl ServerRunner {
         'meow: while let Some(cmd) = self.cmd_reciever.recv().await {
             match cmd {
                 RunnerCommand::StartServer { server } => {
-                    self.start_server(server).await;
+                    self.start_server(server, false).await;
+                }
+                RunnerCommand::RetryStart { server } => {
+                    self.start_server(server, true).await;
                 }
                 RunnerCommand::StartAll { servers } => {
                     for server in servers {
-                        self.start_server(server).await;
+                        self.start_server(server, false).await;
                     }
                 }
                 RunnerCommand::StopAll => {
-                    for (_, server) in &mut self.active_servers {
-                        server
-                            .child_process
-                            .kill()
-                            .await
-                            .expect("failed to kill server");
+                    for server in self.active_servers.values_mut() {
+                        server.stop().await;
                     }
                 }
                 RunnerCommand::StopServer { id } => {
                     if let Some(server) = &mut self.active_servers.remove(&id) {
-                        server
-                            .child_process
-                            .kill()
-                            .await
-                            .expect("failed to kill server");
+                        server.stop().await;
                     }
                 }
+                RunnerCommand::StopGracefully {
+                    id,
+                    response_handle,
+                } => match self.active_servers.remove(&id) {
+                    // waiting for the exit here would hold up every other server
+                    Some(server) => {
+                        spawn(async move {
+                            server.shut_down().await;
+                            let _ = response_handle.send(());
+                        });
+                    }
+                    None => {
+                        let _ = response_handle.send(());
+                    }
+                },
                 RunnerCommand::IssueCommand { id, command } => {
                     println!("command recieved: {}", &command);
-                    if let Some(server) = self.active_servers.get_mut(&id) {
+                    let Some(server) = self.active_servers.get_mut(&id) else {
+                        continue 'meow;
+                    };
+                    let written = async {
                         server
                             .stdin
                             .write_all(format!("{}\n", command).as_bytes())
-                            .await
-                            .expect("could not issue command");
-                        server.stdin.flush().await.expect("failed to flush stdin");
-                        append_to_log(server.text_log.clone(), command).await;
+                            .await?;
+                        server.stdin.flush().await
+                    }
+                    .await;
+                    match written {
+                        Ok(()) => append_to_log(server.text_log.clone(), command).await,
+                        Err(err) => {
+                            // stdin only breaks once the process is gone
+                            println!(
+                                "could not issue command to server {}, dropping it: {}",
+                                id, err
+                            );
+                            if let Some(mut server) = self.active_servers.remove(&id) {
+                                let _ = server.child_process.kill().await;
+                                server.players.end_all_sessions().await;
+                            }
+                        }
                     }
                 }
                 RunnerCommand::GetOutput {
@@ -217,6 +467,47 @@ impl ServerRunner {
                     }
                     let _ = response_handle.send(None);
                 }
+                RunnerCommand::GetMetrics {
+                    id,
+                    response_handle,
+                } => {
+                    let metrics = match self.active_servers.get(&id) {
+                        Some(server) => Some(server.metrics.read().await.clone()),
+                        None => None,
+                    };
+                    let _ = response_handle.send(metrics);
+                }
+                RunnerCommand::GetStatuses { response_handle } => {
+                    let mut statuses = vec![];
+                    for (id, server) in self.active_servers.iter_mut() {
+                        statuses.push(ServerStatus {
+                            id: *id,
+                            running: matches!(server.child_process.try_wait(), Ok(None)),
+                            uptime_secs: server.started_at.elapsed().as_secs(),
+                            restarts: self.start_counts.get(id).unwrap_or(&1).saturating_sub(1),
+                            online_players: server.players.online_players().await.len(),
+                            usage: server.metrics.read().await.latest().cloned(),
+                            console: server.console.read().await.clone(),
+                            restart_required: server.restart_required,
+                        });
+                    }
+                    let _ = response_handle.send(statuses);
+                }
+                RunnerCommand::RequireRestart { id } => {
+                    if let Some(server) = self.active_servers.get_mut(&id) {
+                        server.restart_required = true;
+                    }
+                }
+                RunnerCommand::GetOnlinePlayers {
+                    id,
+                    response_handle,
+                } => {
+                    let players = match self.active_servers.get(&id) {
+                        Some(server) => Some(server.players.online_players().await),
+                        None => None,
+                    };
+                    let _ = response_handle.send(players);
+                }
             }
         }
 
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::rt::spawn;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Notify, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+
+use crate::configuration::{ConfigurationManager, Server};
+use crate::protocol::{
+    Handshake, STATE_LOGIN, STATE_STATUS, STATE_TRANSFER, disconnect_login, read_packet,
+    respond_status,
+};
+use crate::proxy::skip_proxy_header;
+use crate::server_runner::ServerRunnerHandle;
+
+/// how often players are counted
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// how long a pinging client gets to finish talking to the sleeping listener
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+const SLEEPING_MOTD: &str = "sleeping, join to wake";
+const WAKING_MESSAGE: &str = "server is waking up, reconnect in a few seconds";
+
+/// which servers are currently asleep, shared with the web ui
+#[derive(Debug, Clone, Default)]
+pub struct SleepHandle {
+    sleeping: Arc<RwLock<HashSet<usize>>>,
+}
+
+impl SleepHandle {
+    pub async fn is_sleeping(&self, server_id: usize) -> bool {
+        self.sleeping.read().await.contains(&server_id)
+    }
+    pub async fn sleeping(&self) -> HashSet<usize> {
+        self.sleeping.read().await.clone()
+    }
+}
+
+enum SleepState {
+    Awake { idle_since: Option<Instant> },
+    Sleeping { listener: JoinHandle<()> },
+}
+
+/// stops servers nobody is playing on and wakes them when someone tries to join
+pub struct Sleeper {
+    config: ConfigurationManager,
+    runner_handle: ServerRunnerHandle,
+    handle: SleepHandle,
+    states: HashMap<usize, SleepState>,
+}
+
+impl Sleeper {
+    pub fn begin(config: ConfigurationManager, runner_handle: ServerRunnerHandle) -> SleepHandle {
+        let handle = SleepHandle::default();
+        let sleeper = Self {
+            config,
+            runner_handle,
+            handle: handle.clone(),
+            states: HashMap::new(),
+        };
+        spawn(sleeper.run());
+        handle
+    }
+    async fn run(mut self) {
+        loop {
+            sleep(CHECK_INTERVAL).await;
+            self.check().await;
+        }
+    }
+    async fn check(&mut self) {
+        let servers = self.config.get_servers().await;
+        let statuses = self.runner_handle.get_statuses().await;
+        for server in servers {
+            let running = statuses.iter().find(|x| x.id == server.id && x.running);
+            let state = self
+                .states
+                .remove(&server.id)
+                .unwrap_or(SleepState::Awake { idle_since: None });
+            let state = match state {
+                SleepState::Sleeping { listener } => {
+                    if listener.is_finished() {
+                        // somebody joined and the listener already started the server
+                        SleepState::Awake { idle_since: None }
+                    } else if !server.enabled || running.is_some() {
+                        // disabled or started some other way while asleep
+                        listener.abort();
+                        self.handle.sleeping.write().await.remove(&server.id);
+                        SleepState::Awake { idle_since: None }
+                    } else {
+                        SleepState::Sleeping { listener }
+                    }
+                }
+                SleepState::Awake { idle_since } => match (server.sleep_after_minutes, running) {
+                    (Some(minutes), Some(status)) if server.enabled => {
+                        if status.online_players > 0 {
+                            SleepState::Awake { idle_since: None }
+                        } else {
+                            let idle_since = idle_since.unwrap_or_else(Instant::now);
+                            if idle_since.elapsed() >= Duration::from_secs(minutes * 60) {
+                                self.fall_asleep(&server).await
+                            } else {
+                                SleepState::Awake {
+                                    idle_since: Some(idle_since),
+                                }
+                            }
+                        }
+                    }
+                    _ => SleepState::Awake { idle_since: None },
+                },
+            };
+            self.states.insert(server.id, state);
+        }
+    }
+    async fn fall_asleep(&mut self, server: &Server) -> SleepState {
+        println!("server {} is idle, putting it to sleep", server.id);
+        self.handle.sleeping.write().await.insert(server.id);
+        let listener = spawn(listen_while_asleep(
+            server.clone(),
+            self.config.clone(),
+            self.runner_handle.clone(),
+            self.handle.clone(),
+        ));
+        SleepState::Sleeping { listener }
+    }
+}
+
+/// stops the server, then answers pings on its port until a player tries to log in and
+/// starts it again
+async fn listen_while_asleep(
+    server: Server,
+    config: ConfigurationManager,
+    runner_handle: ServerRunnerHandle,
+    handle: SleepHandle,
+) {
+    // in here rather than in the sleeper's loop, the save can take a while
+    runner_handle.stop_gracefully(server.id).await;
+    let woken = Arc::new(Notify::new());
+    // the java process may take a moment to release the port
+    let listener = loop {
+        match TcpListener::bind(("0.0.0.0", server.port)).await {
+            Ok(listener) => break listener,
+            Err(_) => sleep(Duration::from_secs(1)).await,
+        }
+    };
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(_) => continue,
+            },
+            _ = woken.notified() => break,
+        };
+        let woken = woken.clone();
+        let version = server.mc_version_id.clone();
+        spawn(async move {
+            if let Ok(Ok(true)) =
+                timeout(CLIENT_TIMEOUT, handle_sleeping_client(stream, &version)).await
+            {
+                woken.notify_one();
+            }
+        });
+    }
+    // let go of the port before java wants it
+    drop(listener);
+    handle.sleeping.write().await.remove(&server.id);
+    if let Some(server) = config.get_server(server.id).await
+        && server.enabled
+    {
+        println!("waking server {}", server.id);
+        runner_handle.start_server(server);
+    }
+}
+
+/// returns true when the client wanted to log in
+async fn handle_sleeping_client(mut stream: TcpStream, version: &str) -> std::io::Result<bool> {
+    // the proxy puts a PROXY header in front when it's set to, it doesn't know the
+    // server is asleep
+    let read = skip_proxy_header(&mut stream).await?;
+    let body = read_packet(&mut read.as_slice().chain(&mut stream)).await?;
+    let Some(handshake) = Handshake::parse(&body) else {
+        return Ok(false);
+    };
+    match handshake.next_state {
+        STATE_STATUS => {
+            respond_status(
+                &mut stream,
+                handshake.protocol_version,
+                version,
+                SLEEPING_MOTD,
+            )
+            .await?;
+            Ok(false)
+        }
+        STATE_LOGIN | STATE_TRANSFER => {
+            disconnect_login(&mut stream, WAKING_MESSAGE).await?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{encode_packet, write_string, write_varint};
+    use crate::proxy::proxy_v2_header;
+    use tokio::io::AsyncWriteExt;
+
+    fn login_handshake() -> Vec<u8> {
+        let mut payload = vec![];
+        write_varint(&mut payload, 767);
+        write_string(&mut payload, "play.example.com");
+        payload.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut payload, STATE_LOGIN);
+        encode_packet(0x00, &payload)
+    }
+
+    /// connects to a sleeping listener, sends what it's given and returns whether the
+    /// server would wake along with the packet the client got back
+    async fn wake_with(sent: Vec<u8>) -> (bool, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(&sent).await.unwrap();
+            read_packet(&mut stream).await.unwrap()
+        });
+        let (stream, _) = listener.accept().await.unwrap();
+        let woke = timeout(CLIENT_TIMEOUT, handle_sleeping_client(stream, "1.21"))
+            .await
+            .unwrap()
+            .unwrap();
+        (woke, client.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn wakes_for_proxied_logins() {
+        let mut sent = proxy_v2_header(
+            "203.0.113.7:51234".parse().unwrap(),
+            "127.0.0.1:25565".parse().unwrap(),
+        );
+        sent.extend(login_handshake());
+        let (woke, response) = wake_with(sent).await;
+        assert!(woke);
+        assert_eq!(response[0], 0x00);
+        assert!(String::from_utf8_lossy(&response).contains(WAKING_MESSAGE));
+
+        let (woke, _) = wake_with(login_handshake()).await;
+        assert!(woke);
+    }
+}
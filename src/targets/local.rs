@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::targets::{RemoteFile, is_valid_remote_name};
+
+/// a directory on this machine, meant for a mount of another disk or a network share
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocalTarget {
+    pub path: String,
+}
+
+impl LocalTarget {
+    fn dir(&self, dir: &str) -> PathBuf {
+        Path::new(&self.path).join(dir)
+    }
+    pub async fn upload(&self, dir: &str, file: &Path, name: &str) -> Result<(), String> {
+        let dir = self.dir(dir);
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+        let destination = dir.join(name);
+        let partial = dir.join(format!("{}.partial", name));
+        let copied = fs::copy(file, &partial).await;
+        let expected = fs::metadata(file).await.map(|x| x.len());
+        match (copied, expected) {
+            (Ok(copied), Ok(expected)) if copied == expected => {}
+            (Err(err), _) | (_, Err(err)) => {
+                let _ = fs::remove_file(&partial).await;
+                return Err(format!("failed to copy: {}", err));
+            }
+            (Ok(copied), Ok(expected)) => {
+                let _ = fs::remove_file(&partial).await;
+                return Err(format!("copied {} of {} bytes", copied, expected));
+            }
+        }
+        fs::rename(&partial, &destination)
+            .await
+            .map_err(|err| format!("failed to move copy into place: {}", err))
+    }
+    pub async fn list(&self, dir: &str) -> Result<Vec<RemoteFile>, String> {
+        let mut files = vec![];
+        let Ok(mut entries) = fs::read_dir(self.dir(dir)).await else {
+            return Ok(files);
+        };
+        while let Some(entry) = entries.next_entry().await.map_err(|err| err.to_string())? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let metadata = entry.metadata().await.map_err(|err| err.to_string())?;
+            if metadata.is_file() && !name.ends_with(".partial") {
+                files.push(RemoteFile {
+                    name,
+                    size_bytes: metadata.len(),
+                });
+            }
+        }
+        Ok(files)
+    }
+    pub async fn delete(&self, dir: &str, name: &str) -> Result<(), String> {
+        if !is_valid_remote_name(name) {
+            return Err("invalid file name".to_string());
+        }
+        fs::remove_file(self.dir(dir).join(name))
+            .await
+            .map_err(|err| format!("failed to delete {}: {}", name, err))
+    }
+}
@@ -0,0 +1,246 @@
+use std::path::{Path, PathBuf};
+
+use actix_web::rt::spawn;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::backups::{export_backup, record_upload};
+use crate::configuration::ConfigurationManager;
+use crate::events::{GameEvent, ServerEvent};
+use crate::server_runner::ServerRunnerHandle;
+
+pub mod local;
+pub mod s3;
+pub mod sftp;
+
+use local::LocalTarget;
+use s3::S3Target;
+use sftp::SftpTarget;
+
+/// where a target keeps things, each kind checks an upload arrived whole before
+/// calling it done
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TargetKind {
+    Local(LocalTarget),
+    S3(S3Target),
+    Sftp(SftpTarget),
+}
+
+/// a file already on a target
+#[derive(Debug, Serialize, Clone)]
+pub struct RemoteFile {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+impl TargetKind {
+    /// stores `file` as `dir/name`
+    pub async fn upload(&self, dir: &str, file: &Path, name: &str) -> Result<(), String> {
+        match self {
+            TargetKind::Local(target) => target.upload(dir, file, name).await,
+            TargetKind::S3(target) => target.upload(dir, file, name).await,
+            TargetKind::Sftp(target) => target.upload(dir, file, name).await,
+        }
+    }
+    pub async fn list(&self, dir: &str) -> Result<Vec<RemoteFile>, String> {
+        match self {
+            TargetKind::Local(target) => target.list(dir).await,
+            TargetKind::S3(target) => target.list(dir).await,
+            TargetKind::Sftp(target) => target.list(dir).await,
+        }
+    }
+    pub async fn delete(&self, dir: &str, name: &str) -> Result<(), String> {
+        match self {
+            TargetKind::Local(target) => target.delete(dir, name).await,
+            TargetKind::S3(target) => target.delete(dir, name).await,
+            TargetKind::Sftp(target) => target.delete(dir, name).await,
+        }
+    }
+    pub fn describe(&self) -> String {
+        match self {
+            TargetKind::Local(target) => format!("directory {}", target.path),
+            TargetKind::S3(target) => format!("s3 {}/{}", target.endpoint, target.bucket),
+            TargetKind::Sftp(target) => format!(
+                "sftp {}@{}:{}{}",
+                target.username, target.host, target.port, target.path
+            ),
+        }
+    }
+}
+
+/// somewhere off the machine backups are copied to after they're written
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupTarget {
+    pub id: usize,
+    pub name: String,
+    pub kind: TargetKind,
+    /// only take backups of this server, or of every server when unset
+    pub server_id: Option<usize>,
+    /// newest uploads of each server kept on the target, 0 keeps them all
+    pub keep_last: usize,
+    pub enabled: bool,
+}
+
+impl BackupTarget {
+    pub fn wants(&self, server_id: usize) -> bool {
+        self.enabled && self.server_id.is_none_or(|x| x == server_id)
+    }
+}
+
+/// names are only ever ones we made, refusing anything else keeps them from
+/// reaching outside of the target
+pub fn is_valid_remote_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || matches!(x, '-' | '_' | '.'))
+}
+
+/// uploads a backup archive under the server's directory and drops the oldest
+/// uploads past the target's retention
+pub async fn upload_backup(
+    target: &BackupTarget,
+    server_id: usize,
+    archive: &Path,
+) -> Result<(), String> {
+    let name = archive
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !is_valid_remote_name(&name) {
+        return Err(format!("can't upload a file called {}", name));
+    }
+    let dir = server_id.to_string();
+    target.kind.upload(&dir, archive, &name).await?;
+    if target.keep_last == 0 {
+        return Ok(());
+    }
+    let mut uploads: Vec<String> = target
+        .kind
+        .list(&dir)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .filter(|x| x.ends_with(".zip"))
+        .collect();
+    // names start with the time they were taken
+    uploads.sort();
+    let excess = uploads.len().saturating_sub(target.keep_last);
+    for name in uploads.into_iter().take(excess) {
+        println!(
+            "removing {} of server {} from {}",
+            name, server_id, target.name
+        );
+        target.kind.delete(&dir, &name).await?;
+    }
+    Ok(())
+}
+
+/// uploads, lists and deletes a small file to check the target works
+pub async fn test_target(target: &BackupTarget) -> Result<(), String> {
+    let name = "mcmanage-test.txt";
+    let file = PathBuf::from("./backups").join(name);
+    fs::create_dir_all("./backups")
+        .await
+        .map_err(|err| err.to_string())?;
+    fs::write(&file, "mcmanage backup target test\n")
+        .await
+        .map_err(|err| err.to_string())?;
+    let result = async {
+        target.kind.upload("test", &file, name).await?;
+        if !target
+            .kind
+            .list("test")
+            .await?
+            .iter()
+            .any(|x| x.name == name)
+        {
+            return Err("the test file was uploaded but isn't listed".to_string());
+        }
+        target.kind.delete("test", name).await
+    }
+    .await;
+    let _ = fs::remove_file(&file).await;
+    result
+}
+
+/// copies every finished backup to the targets that want it
+pub struct Uploader;
+
+impl Uploader {
+    pub fn begin(config: ConfigurationManager, runner_handle: ServerRunnerHandle) {
+        let mut events = runner_handle.subscribe();
+        spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        println!("uploader fell behind, skipped {} events", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let GameEvent::BackupFinished { backup, .. } = event.event else {
+                    continue;
+                };
+                let targets: Vec<BackupTarget> = config
+                    .get_backup_targets()
+                    .await
+                    .into_iter()
+                    .filter(|x| x.wants(event.server_id))
+                    .collect();
+                if targets.is_empty() {
+                    continue;
+                }
+                spawn(upload_everywhere(
+                    runner_handle.clone(),
+                    event.server_id,
+                    backup,
+                    targets,
+                ));
+            }
+        });
+    }
+}
+
+async fn upload_everywhere(
+    runner_handle: ServerRunnerHandle,
+    server_id: usize,
+    backup: String,
+    targets: Vec<BackupTarget>,
+) {
+    let failed = |error: String| {
+        println!("{}", error);
+        runner_handle.publish(ServerEvent::new(
+            server_id,
+            GameEvent::BackupFailed { error },
+        ));
+    };
+    // snapshots are packed into a zip first, those go once everything has them
+    let (archive, temporary) = match export_backup(server_id, &backup).await {
+        Ok(exported) => exported,
+        Err(err) => {
+            failed(format!("failed to prepare {} for upload: {}", backup, err));
+            return;
+        }
+    };
+    for target in targets {
+        println!(
+            "uploading {} of server {} to {}",
+            backup, server_id, target.name
+        );
+        match upload_backup(&target, server_id, &archive).await {
+            Ok(_) => record_upload(server_id, &backup, target.id).await,
+            Err(err) => failed(format!(
+                "upload of {} to {} failed: {}",
+                backup, target.name, err
+            )),
+        }
+    }
+    if temporary {
+        let _ = fs::remove_file(&archive).await;
+    }
+}
@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::targets::{RemoteFile, is_valid_remote_name};
+
+fn default_port() -> u16 {
+    22
+}
+
+/// a directory on another machine reached with the system's sftp client. it logs in
+/// with a key since there's nobody to type a password. without a pinned host key it
+/// trusts whatever key the host shows the first time and refuses a different one after
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SftpTarget {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: String,
+    /// private key to log in with, otherwise the agent and the default keys are tried
+    #[serde(default)]
+    pub identity_file: Option<String>,
+    /// remote directory the backups go in
+    pub path: String,
+    /// the host's public key like `ssh-ed25519 AAAA...`, as ssh-keyscan prints it. when
+    /// set only this key is accepted, even on the first connection
+    #[serde(default)]
+    pub host_key: Option<String>,
+}
+
+/// sftp batch files quote with double quotes and have no way to escape them
+pub fn is_valid_sftp_path(path: &str) -> bool {
+    !path.is_empty() && !path.contains(['"', '\n', '\r'])
+}
+
+/// a host or username for the sftp command line, nothing it could take for an option
+/// or that would split into another argument
+pub fn is_valid_sftp_login(value: &str) -> bool {
+    !value.is_empty()
+        && !value.starts_with('-')
+        && !value.chars().any(|x| x.is_whitespace() || x.is_control())
+}
+
+/// a key type and its base64, nothing that could add another known_hosts entry
+pub fn is_valid_host_key(key: &str) -> bool {
+    let fields: Vec<&str> = key.split_whitespace().collect();
+    !key.contains(['\n', '\r'])
+        && matches!(fields.len(), 2 | 3)
+        && !fields[0].starts_with('@')
+        && fields[1]
+            .bytes()
+            .all(|x| x.is_ascii_alphanumeric() || matches!(x, b'+' | b'/' | b'='))
+}
+
+/// the size and name out of an `ls -l` line
+fn parse_listing(line: &str) -> Option<RemoteFile> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 9 || !fields[0].starts_with('-') {
+        return None;
+    }
+    let name = fields[8..].join(" ");
+    let name = name.rsplit('/').next()?.to_string();
+    Some(RemoteFile {
+        name,
+        size_bytes: fields[4].parse().ok()?,
+    })
+}
+
+impl SftpTarget {
+    fn dir(&self, dir: &str) -> String {
+        format!("{}/{}", self.path.trim_end_matches('/'), dir)
+    }
+    /// the pinned key as a line of a known_hosts file
+    fn known_hosts_line(&self, key: &str) -> String {
+        match self.port {
+            22 => format!("{} {}\n", self.host, key.trim()),
+            port => format!("[{}]:{} {}\n", self.host, port, key.trim()),
+        }
+    }
+    /// runs batch commands, a command starting with - may fail without stopping the rest
+    async fn run(&self, commands: &str) -> Result<String, String> {
+        let Some(key) = &self.host_key else {
+            return self.run_with(commands, None).await;
+        };
+        if !is_valid_host_key(key) {
+            return Err("invalid host key".to_string());
+        }
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let known_hosts = std::env::temp_dir().join(format!(
+            "mcmanage-known-hosts-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&known_hosts, self.known_hosts_line(key))
+            .await
+            .map_err(|err| format!("failed to write known hosts: {}", err))?;
+        let result = self.run_with(commands, Some(&known_hosts)).await;
+        let _ = fs::remove_file(&known_hosts).await;
+        result
+    }
+    async fn run_with(
+        &self,
+        commands: &str,
+        known_hosts: Option<&PathBuf>,
+    ) -> Result<String, String> {
+        let mut command = Command::new("sftp");
+        command
+            .arg("-b")
+            .arg("-")
+            .arg("-P")
+            .arg(self.port.to_string())
+            .args(["-o", "BatchMode=yes"])
+            .args(["-o", "ConnectTimeout=15"]);
+        match known_hosts {
+            Some(known_hosts) => {
+                command
+                    .args(["-o", "StrictHostKeyChecking=yes"])
+                    .arg("-o")
+                    .arg(format!("UserKnownHostsFile={}", known_hosts.display()));
+            }
+            // the user's known_hosts remembers the first key and fails on a changed one
+            None => {
+                command.args(["-o", "StrictHostKeyChecking=accept-new"]);
+            }
+        }
+        if let Some(identity_file) = &self.identity_file {
+            command.arg("-i").arg(identity_file);
+        }
+        let mut child = command
+            .arg("--")
+            .arg(format!("{}@{}", self.username, self.host))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| format!("failed to run sftp: {}", err))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        stdin
+            .write_all(commands.as_bytes())
+            .await
+            .map_err(|err| format!("failed to talk to sftp: {}", err))?;
+        drop(stdin);
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|err| format!("sftp failed: {}", err))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(stderr
+                .lines()
+                .rfind(|x| !x.trim().is_empty())
+                .unwrap_or("sftp failed")
+                .to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+    /// puts the file next to its final name and moves it over once the size checks out
+    pub async fn upload(&self, dir: &str, file: &Path, name: &str) -> Result<(), String> {
+        if !is_valid_sftp_path(&self.path) {
+            return Err("invalid remote path".to_string());
+        }
+        let local = file
+            .canonicalize()
+            .map_err(|err| err.to_string())?
+            .to_string_lossy()
+            .to_string();
+        if !is_valid_sftp_path(&local) {
+            return Err("can't upload a file with quotes in its path".to_string());
+        }
+        let size = file.metadata().map_err(|err| err.to_string())?.len();
+        let dir = self.dir(dir);
+        let remote = format!("{}/{}", dir, name);
+        let output = self
+            .run(&format!(
+                "-mkdir \"{path}\"\n-mkdir \"{dir}\"\nput \"{local}\" \"{remote}.partial\"\nls -l \"{remote}.partial\"\n",
+                path = self.path,
+            ))
+            .await?;
+        let stored = output
+            .lines()
+            .filter(|x| !x.starts_with("sftp>"))
+            .find_map(parse_listing)
+            .map(|x| x.size_bytes);
+        if stored != Some(size) {
+            let _ = self.run(&format!("-rm \"{}.partial\"\n", remote)).await;
+            return Err(format!(
+                "stored file is {:?} bytes instead of {}",
+                stored, size
+            ));
+        }
+        self.run(&format!(
+            "-rm \"{remote}\"\nrename \"{remote}.partial\" \"{remote}\"\n"
+        ))
+        .await?;
+        Ok(())
+    }
+    pub async fn list(&self, dir: &str) -> Result<Vec<RemoteFile>, String> {
+        if !is_valid_sftp_path(&self.path) {
+            return Err("invalid remote path".to_string());
+        }
+        // a directory that isn't there yet just has nothing in it
+        let output = self.run(&format!("-ls -l \"{}\"\n", self.dir(dir))).await?;
+        Ok(output
+            .lines()
+            .filter(|x| !x.starts_with("sftp>"))
+            .filter_map(parse_listing)
+            .filter(|x| !x.name.ends_with(".partial"))
+            .collect())
+    }
+    pub async fn delete(&self, dir: &str, name: &str) -> Result<(), String> {
+        if !is_valid_remote_name(name) || !is_valid_sftp_path(&self.path) {
+            return Err("invalid file name".to_string());
+        }
+        self.run(&format!("rm \"{}/{}\"\n", self.dir(dir), name))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_host_keys() {
+        assert!(is_valid_host_key(
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl"
+        ));
+        assert!(is_valid_host_key("ssh-rsa AAAAB3Nza+/== comment"));
+        assert!(!is_valid_host_key("ssh-rsa"));
+        assert!(!is_valid_host_key("ssh-rsa AAAA\n* ssh-rsa BBBB"));
+        assert!(!is_valid_host_key("@revoked * ssh-rsa AAAA"));
+        assert!(!is_valid_host_key("ssh-rsa AAAA,evil"));
+    }
+
+    #[test]
+    fn checks_hosts_and_usernames() {
+        assert!(is_valid_sftp_login("backup.example.com"));
+        assert!(is_valid_sftp_login("mc-backups"));
+        assert!(!is_valid_sftp_login("-oProxyCommand=sh"));
+        assert!(!is_valid_sftp_login("host name"));
+        assert!(!is_valid_sftp_login("host\tname"));
+        assert!(!is_valid_sftp_login("host\0"));
+    }
+
+    #[test]
+    fn pins_the_host_and_port() {
+        let mut target = SftpTarget {
+            host: "backup.example.com".to_string(),
+            port: 22,
+            username: "mc".to_string(),
+            identity_file: None,
+            path: "/backups".to_string(),
+            host_key: None,
+        };
+        assert_eq!(
+            target.known_hosts_line("ssh-ed25519 AAAA "),
+            "backup.example.com ssh-ed25519 AAAA\n"
+        );
+        target.port = 2222;
+        assert_eq!(
+            target.known_hosts_line("ssh-ed25519 AAAA"),
+            "[backup.example.com]:2222 ssh-ed25519 AAAA\n"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::backups::{BackupKind, backup_server};
+use crate::configuration::{ConfigurationManager, Server, ServerType};
+use crate::downloader::Downloader;
+use crate::loaders::{ensure_available, fabric, forge, paper, purpur};
+use crate::players::now;
+use crate::server_runner::ServerRunnerHandle;
+use crate::versions::{PackagesList, VersionCatalog};
+
+/// one move of a server from a minecraft version to another
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VersionChange {
+    pub from: String,
+    pub to: String,
+    /// unix timestamp in seconds
+    pub at: u64,
+    /// the backup taken right before, none when the server had no files yet
+    pub backup: Option<String>,
+}
+
+/// left in the server's directory to start it with `--forceUpgrade` the next time
+fn force_upgrade_marker(server_id: usize) -> PathBuf {
+    PathBuf::from(format!("./servers/{}/force_upgrade", server_id))
+}
+
+/// whether the server should convert its worlds on this start, only true once
+pub async fn take_force_upgrade(server_id: usize) -> bool {
+    fs::remove_file(force_upgrade_marker(server_id))
+        .await
+        .is_ok()
+}
+
+/// whether `to` came out after `from`, none when either isn't in the manifest
+pub fn is_newer(packages: &PackagesList, from: &str, to: &str) -> Option<bool> {
+    let released = |id: &str| {
+        packages
+            .get_version(id)
+            .and_then(|x| DateTime::parse_from_rfc3339(&x.release_time).ok())
+    };
+    Some(released(to)? > released(from)?)
+}
+
+/// the same kind of server for the new version, keeping the fabric loader when it
+/// supports the version and picking the newest build or recommended loader otherwise
+async fn server_type_for(server_type: &ServerType, mc_version: &str) -> Result<ServerType, String> {
+    match server_type {
+        ServerType::Vanilla => Ok(ServerType::Vanilla),
+        ServerType::Fabric { loader_version, .. } => {
+            match fabric::resolve(mc_version, Some(loader_version)).await {
+                Ok(server_type) => Ok(server_type),
+                Err(_) => fabric::resolve(mc_version, None).await,
+            }
+        }
+        ServerType::Paper { .. } => paper::resolve(mc_version, None).await,
+        ServerType::Purpur { .. } => purpur::resolve(mc_version, None).await,
+        ServerType::Forge { .. } | ServerType::NeoForge { .. } => {
+            let (kind, _) = server_type.forge().expect("matched forge");
+            forge::resolve(kind, mc_version, None).await
+        }
+    }
+}
+
+/// moves a stopped server to another minecraft version after backing it up. worlds
+/// can't be opened by older versions, so going back needs `allow_downgrade`
+pub async fn upgrade_server(
+    config: &ConfigurationManager,
+    runner_handle: &ServerRunnerHandle,
+    server: &Server,
+    mc_version: &str,
+    force_upgrade: bool,
+    allow_downgrade: bool,
+) -> Result<Server, String> {
+    if server.mc_version_id == mc_version {
+        return Err(format!("the server is already on {}", mc_version));
+    }
+    if runner_handle.is_running(server.id).await {
+        return Err("stop the server first".to_string());
+    }
+    let packages = VersionCatalog::packages().await?;
+    let selected = packages
+        .get_version(mc_version)
+        .ok_or(format!("unknown minecraft version {}", mc_version))?;
+    match is_newer(&packages, &server.mc_version_id, mc_version) {
+        Some(true) => {}
+        Some(false) if allow_downgrade => {}
+        Some(false) => {
+            return Err(format!(
+                "{} is older than {}, worlds can't be downgraded",
+                mc_version, server.mc_version_id
+            ));
+        }
+        None => {
+            return Err(format!(
+                "can't tell whether {} is newer than {}",
+                mc_version, server.mc_version_id
+            ));
+        }
+    }
+    let info = VersionCatalog::version_info(&selected).await?;
+    let server_type = server_type_for(&server.server_type, mc_version).await?;
+    // downloaded before the backup so an upgrade that can't happen doesn't leave one
+    Downloader::ensure_available(info).await?;
+    ensure_available(&server_type, mc_version).await?;
+
+    let has_files = fs::try_exists(format!("./servers/{}/game", server.id))
+        .await
+        .unwrap_or(false);
+    let backup = if has_files {
+        let backup = backup_server(runner_handle, server, BackupKind::Safety)
+            .await
+            .map_err(|err| format!("not upgrading, the backup failed: {}", err))?;
+        Some(backup.name)
+    } else {
+        None
+    };
+
+    let change = VersionChange {
+        from: server.mc_version_id.clone(),
+        to: mc_version.to_string(),
+        at: now(),
+        backup,
+    };
+    let upgraded = config
+        .set_server_version(server.id, mc_version.to_string(), server_type, change)
+        .await
+        .ok_or("server not found".to_string())?;
+    // only once the version changed, a marker left behind would convert the worlds on
+    // the next start of the old version
+    if force_upgrade {
+        fs::write(force_upgrade_marker(server.id), "")
+            .await
+            .map_err(|err| {
+                format!(
+                    "moved to {} but could not mark the server for --forceUpgrade: {}",
+                    mc_version, err
+                )
+            })?;
+    }
+    Ok(upgraded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages() -> PackagesList {
+        let version = |id: &str, release_type: &str, released: &str| {
+            serde_json::json!({
+                "id": id,
+                "type": release_type,
+                "url": "",
+                "time": released,
+                "releaseTime": released,
+            })
+        };
+        serde_json::from_value(serde_json::json!({
+            "latest": { "release": "1.21", "snapshot": "1.21" },
+            "versions": [
+                version("1.21", "release", "2024-06-13T08:24:03+00:00"),
+                version("24w14a", "snapshot", "2024-04-03T12:48:39+00:00"),
+                version("1.20.4", "release", "2023-12-07T12:56:20+00:00"),
+                version("1.9", "release", "2016-02-29T13:49:54+00:00"),
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn orders_versions_by_release() {
+        let packages = packages();
+        assert_eq!(is_newer(&packages, "1.20.4", "1.21"), Some(true));
+        assert_eq!(is_newer(&packages, "1.21", "1.20.4"), Some(false));
+        // "1.9" sorts after "1.20.4" as text but came out years before it
+        assert_eq!(is_newer(&packages, "1.9", "1.20.4"), Some(true));
+        assert_eq!(is_newer(&packages, "1.20.4", "24w14a"), Some(true));
+        assert_eq!(is_newer(&packages, "1.21", "1.21"), Some(false));
+        assert_eq!(is_newer(&packages, "1.20.4", "2.0"), None);
+        assert_eq!(is_newer(&packages, "1.0-unknown", "1.21"), None);
+    }
+}
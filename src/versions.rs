@@ -1,7 +1,20 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
 use serde::{Deserialize, Serialize};
-use tokio::{fs::File, io::AsyncWriteExt};
-use tokio_stream::StreamExt;
-pub const PACKAGES_ENDPOINT: &str = "https://launchermeta.mojang.com/mc/game/version_manifest.json";
+use sha1::{Digest, Sha1};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::players::now;
+
+pub const PACKAGES_ENDPOINT: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+const CACHE_DIR: &str = "./cache/versions";
+/// how long the cached manifest is used before asking mojang whether it changed
+const MANIFEST_TTL: Duration = Duration::from_secs(3600);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Latest {
@@ -27,17 +40,23 @@ pub struct McVersion {
     pub time: String,
     #[serde(rename = "releaseTime")]
     pub release_time: String,
+    /// of the version's info document, only in the v2 manifest
+    #[serde(default)]
+    pub sha1: Option<String>,
 }
 
+/// release date of 1.2.5, the first version mojang has a server download for
+const FIRST_SERVER_RELEASE: &str = "2012-03-29";
+
 impl McVersion {
-    pub async fn get_version_info(&self) -> VersionInfo {
-        let res = reqwest::get(&self.url)
-            .await
-            .expect("failed to communicate with mojang")
-            .json::<VersionInfo>()
-            .await
-            .expect("invalid version info format from mojang");
-        res
+    /// whether there's a server jar for it. alphas, betas and everything before 1.2.5
+    /// only had a client, the odd version after that without one is found when its
+    /// info is fetched
+    pub fn may_have_server(&self) -> bool {
+        !matches!(
+            self.release_type,
+            ReleaseType::OldAlpha | ReleaseType::OldBeta
+        ) && self.release_time.as_str() >= FIRST_SERVER_RELEASE
     }
 }
 
@@ -56,15 +75,6 @@ impl PackagesList {
         self.get_version(&self.latest.release)
             .expect("unable to get latest")
     }
-    pub async fn new() -> Self {
-        let res = reqwest::get(PACKAGES_ENDPOINT)
-            .await
-            .expect("failed to communicate with mojang")
-            .json::<PackagesList>()
-            .await
-            .expect("invalid packages list format from mojang");
-        res
-    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,36 +84,248 @@ pub struct DownloadInfo {
     pub url: String,
 }
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
-impl DownloadInfo {
-    pub async fn download(&self, path: &str) -> Result<()> {
-        let mut file = File::create(path).await?;
-        println!("Downloading {}...", &self.url);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionDownloads {
+    pub client: Option<DownloadInfo>,
+    pub client_mappings: Option<DownloadInfo>,
+    /// the oldest versions only have a client
+    pub server: Option<DownloadInfo>,
+    pub server_mappings: Option<DownloadInfo>,
+}
 
-        let mut stream = reqwest::get(&self.url).await?.bytes_stream();
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub downloads: VersionDownloads,
+    pub id: String,
+}
 
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result?;
-            file.write_all(&chunk).await?;
-        }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedManifest {
+    /// unix timestamp in seconds of when mojang last confirmed it
+    checked: u64,
+    etag: Option<String>,
+    manifest: PackagesList,
+    /// versions found to have no server download, left out of the list
+    #[serde(default)]
+    no_server: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedInfo {
+    sha1: Option<String>,
+    info: VersionInfo,
+}
+
+lazy_static! {
+    /// also keeps two requests from fetching the manifest at the same time
+    static ref MANIFEST: Mutex<Option<CachedManifest>> = Mutex::new(None);
+}
+
+fn manifest_path() -> String {
+    format!("{}/manifest.json", CACHE_DIR)
+}
+
+fn info_path(version_id: &str) -> String {
+    format!("{}/{}.json", CACHE_DIR, version_id)
+}
 
-        file.flush().await?;
+async fn read_cache<T: for<'a> Deserialize<'a>>(path: &str) -> Option<T> {
+    let contents = fs::read(path).await.ok()?;
+    serde_json::from_slice(&contents).ok()
+}
 
-        println!("Downloaded {}", &self.url);
-        Ok(())
+async fn write_cache<T: Serialize>(path: &str, value: &T) {
+    let result = async {
+        fs::create_dir_all(CACHE_DIR).await?;
+        fs::write(path, serde_json::to_vec(value).unwrap()).await
+    }
+    .await;
+    if let Err(err) = result {
+        println!("could not write {}: {}", path, err);
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VersionDownloads {
-    pub client: DownloadInfo,
-    pub client_mappings: DownloadInfo,
-    pub server: DownloadInfo,
-    pub server_mappings: DownloadInfo,
+/// fetches the manifest unless mojang says the cached one with that etag is still
+/// current, which is none
+async fn fetch_manifest(
+    etag: Option<&str>,
+) -> Result<Option<(PackagesList, Option<String>)>, String> {
+    let mut request = reqwest::Client::new().get(PACKAGES_ENDPOINT);
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|x| x.error_for_status())
+        .map_err(|err| format!("failed to communicate with mojang: {}", err))?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|x| x.to_str().ok())
+        .map(str::to_string);
+    let manifest = response
+        .json::<PackagesList>()
+        .await
+        .map_err(|err| format!("invalid packages list format from mojang: {}", err))?;
+    Ok(Some((manifest, etag)))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VersionInfo {
-    pub downloads: VersionDownloads,
-    pub id: String,
+/// mojang's list of versions and their details, cached on disk so pages don't wait on
+/// mojang and keep working while it can't be reached
+pub struct VersionCatalog;
+
+impl VersionCatalog {
+    /// the versions that can run as a server, from the cache while it's fresh
+    pub async fn packages() -> Result<PackagesList, String> {
+        Self::load(false).await
+    }
+    /// asks mojang for changes even if the cache is fresh
+    pub async fn refresh() -> Result<PackagesList, String> {
+        Self::load(true).await
+    }
+    async fn load(force: bool) -> Result<PackagesList, String> {
+        let mut cached = MANIFEST.lock().await;
+        if cached.is_none() {
+            *cached = read_cache(&manifest_path()).await;
+        }
+        let fresh = cached
+            .as_ref()
+            .is_some_and(|x| x.checked + MANIFEST_TTL.as_secs() > now());
+        if force || !fresh {
+            let etag = cached.as_ref().and_then(|x| x.etag.as_deref());
+            match fetch_manifest(etag).await {
+                Ok(Some((manifest, etag))) => {
+                    let no_server = cached.take().map(|x| x.no_server).unwrap_or_default();
+                    *cached = Some(CachedManifest {
+                        checked: now(),
+                        etag,
+                        manifest,
+                        no_server,
+                    });
+                }
+                Ok(None) => {
+                    if let Some(cached) = cached.as_mut() {
+                        cached.checked = now();
+                    }
+                }
+                Err(err) if cached.is_some() => {
+                    println!("using the cached version manifest: {}", err);
+                }
+                Err(err) => return Err(err),
+            }
+            if let Some(cached) = cached.as_ref() {
+                write_cache(&manifest_path(), cached).await;
+            }
+        }
+        let cached = cached.as_ref().expect("loaded or returned above");
+        let mut manifest = cached.manifest.clone();
+        manifest
+            .versions
+            .retain(|x| x.may_have_server() && !cached.no_server.contains(&x.id));
+        Ok(manifest)
+    }
+    /// the details of a version with a server download, cached for as long as the
+    /// manifest lists the same document for it
+    pub async fn version_info(version: &McVersion) -> Result<VersionInfo, String> {
+        let cached: Option<CachedInfo> = read_cache(&info_path(&version.id)).await;
+        let info = match cached {
+            Some(cached) if version.sha1.is_none() || cached.sha1 == version.sha1 => cached.info,
+            cached => match Self::fetch_info(version).await {
+                Ok(info) => {
+                    let entry = CachedInfo {
+                        sha1: version.sha1.clone(),
+                        info: info.clone(),
+                    };
+                    write_cache(&info_path(&version.id), &entry).await;
+                    info
+                }
+                Err(err) => match cached {
+                    Some(cached) => {
+                        println!("using the cached info of {}: {}", version.id, err);
+                        cached.info
+                    }
+                    None => return Err(err),
+                },
+            },
+        };
+        if info.downloads.server.is_none() {
+            let mut manifest = MANIFEST.lock().await;
+            if let Some(manifest) = manifest.as_mut()
+                && !manifest.no_server.contains(&version.id)
+            {
+                manifest.no_server.push(version.id.clone());
+                write_cache(&manifest_path(), manifest).await;
+            }
+            return Err(format!("{} has no server download", version.id));
+        }
+        Ok(info)
+    }
+    async fn fetch_info(version: &McVersion) -> Result<VersionInfo, String> {
+        let body = reqwest::get(&version.url)
+            .await
+            .and_then(|x| x.error_for_status())
+            .map_err(|err| format!("failed to communicate with mojang: {}", err))?
+            .bytes()
+            .await
+            .map_err(|err| format!("failed to communicate with mojang: {}", err))?;
+        if let Some(expected) = &version.sha1 {
+            let actual = hex::encode(Sha1::digest(&body));
+            if &actual != expected {
+                return Err(format!(
+                    "the info of {} has sha1 {} instead of {}",
+                    version.id, actual, expected
+                ));
+            }
+        }
+        serde_json::from_slice(&body)
+            .map_err(|err| format!("invalid version info format from mojang: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(id: &str, release_type: ReleaseType, release_time: &str) -> McVersion {
+        McVersion {
+            id: id.to_string(),
+            release_type,
+            url: String::new(),
+            time: String::new(),
+            release_time: release_time.to_string(),
+            sha1: None,
+        }
+    }
+
+    #[test]
+    fn leaves_out_client_only_versions() {
+        assert!(
+            version("1.21.1", ReleaseType::Release, "2024-08-08T12:24:45+00:00").may_have_server()
+        );
+        assert!(
+            version("1.2.5", ReleaseType::Release, "2012-03-29T22:00:00+00:00").may_have_server()
+        );
+        assert!(
+            version(
+                "24w14potato",
+                ReleaseType::Snapshot,
+                "2024-04-01T12:00:00+00:00"
+            )
+            .may_have_server()
+        );
+        assert!(
+            !version("1.0", ReleaseType::Release, "2011-11-17T22:00:00+00:00").may_have_server()
+        );
+        assert!(
+            !version("b1.7.3", ReleaseType::OldBeta, "2011-07-07T22:00:00+00:00").may_have_server()
+        );
+        assert!(
+            !version("a1.2.6", ReleaseType::OldAlpha, "2010-12-02T22:00:00+00:00")
+                .may_have_server()
+        );
+    }
 }
@@ -0,0 +1,507 @@
+use std::time::Duration;
+
+use actix_web::rt::spawn;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::sleep;
+
+use crate::configuration::{ConfigurationManager, Server};
+use crate::events::{GameEvent, ServerEvent};
+use crate::players::now;
+use crate::server_runner::ServerRunnerHandle;
+
+const DELIVERIES_PATH: &str = "webhook_deliveries.toml";
+/// deliveries kept in the log
+const MAX_DELIVERIES: usize = 500;
+/// attempts per delivery, waiting twice as long after each failure
+const MAX_ATTEMPTS: u32 = 5;
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(10);
+pub const SIGNATURE_HEADER: &str = "X-Mcmanage-Signature";
+
+lazy_static! {
+    /// held for every read-modify-write of the log, deliveries finish concurrently
+    static ref DELIVERIES_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// the event as json along with the server it happened on
+    Generic,
+    Discord,
+    Slack,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Started,
+    Stopping,
+    Crashed,
+    PlayerJoined,
+    PlayerLeft,
+    Chat,
+    Death,
+    Advancement,
+    BackupFinished,
+    BackupFailed,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 10] = [
+        WebhookEvent::Started,
+        WebhookEvent::Stopping,
+        WebhookEvent::Crashed,
+        WebhookEvent::PlayerJoined,
+        WebhookEvent::PlayerLeft,
+        WebhookEvent::Chat,
+        WebhookEvent::Death,
+        WebhookEvent::Advancement,
+        WebhookEvent::BackupFinished,
+        WebhookEvent::BackupFailed,
+    ];
+    pub fn from_game_event(event: &GameEvent) -> Option<WebhookEvent> {
+        match event {
+            GameEvent::ServerStarted { .. } => Some(WebhookEvent::Started),
+            GameEvent::ServerStopping => Some(WebhookEvent::Stopping),
+            GameEvent::Exited { crashed: true } => Some(WebhookEvent::Crashed),
+            GameEvent::PlayerJoined { .. } => Some(WebhookEvent::PlayerJoined),
+            GameEvent::PlayerLeft { .. } => Some(WebhookEvent::PlayerLeft),
+            GameEvent::Chat { .. } => Some(WebhookEvent::Chat),
+            GameEvent::Death { .. } => Some(WebhookEvent::Death),
+            GameEvent::Advancement { .. } => Some(WebhookEvent::Advancement),
+            GameEvent::BackupFinished { .. } => Some(WebhookEvent::BackupFinished),
+            GameEvent::BackupFailed { .. } => Some(WebhookEvent::BackupFailed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Webhook {
+    pub id: usize,
+    pub name: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    /// only fire for this server, or for every server when unset
+    pub server_id: Option<usize>,
+    pub events: Vec<WebhookEvent>,
+    /// when set the body is signed with hmac-sha256 in the signature header
+    pub secret: Option<String>,
+    pub enabled: bool,
+}
+
+impl Webhook {
+    pub fn wants(&self, server_id: usize, event: WebhookEvent) -> bool {
+        self.enabled
+            && self.server_id.is_none_or(|x| x == server_id)
+            && self.events.contains(&event)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Delivery {
+    pub webhook_id: usize,
+    pub webhook_name: String,
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+    pub event: String,
+    pub attempts: u32,
+    pub success: bool,
+    /// the last status code or error
+    pub response: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeliveryLog {
+    pub deliveries: Vec<Delivery>,
+}
+
+impl DeliveryLog {
+    pub async fn load() -> Result<DeliveryLog, String> {
+        match fs::read_to_string(DELIVERIES_PATH).await {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| format!("invalid webhook log in {}: {}", DELIVERIES_PATH, err)),
+            Err(_) => Ok(DeliveryLog::default()),
+        }
+    }
+    /// writes next to the log and moves it over, so readers never see half of it
+    async fn write(&self) -> std::io::Result<()> {
+        let partial = format!("{}.partial", DELIVERIES_PATH);
+        let mut file = File::create(&partial).await?;
+        file.write_all(toml::to_string_pretty(&self).unwrap().as_bytes())
+            .await?;
+        file.flush().await?;
+        fs::rename(&partial, DELIVERIES_PATH).await
+    }
+    async fn record(delivery: Delivery) {
+        let _lock = DELIVERIES_LOCK.lock().await;
+        let mut log = match Self::load().await {
+            Ok(log) => log,
+            Err(err) => {
+                println!(
+                    "not recording a delivery to {}: {}",
+                    delivery.webhook_name, err
+                );
+                return;
+            }
+        };
+        log.deliveries.push(delivery);
+        if log.deliveries.len() > MAX_DELIVERIES {
+            let excess = log.deliveries.len() - MAX_DELIVERIES;
+            log.deliveries.drain(..excess);
+        }
+        if let Err(err) = log.write().await {
+            println!("failed to write webhook log: {}", err);
+        }
+    }
+}
+
+/// a human readable line describing the event, used by the chat formats
+pub fn describe(server: &str, event: &GameEvent) -> String {
+    let text = match event {
+        GameEvent::ServerStarted { startup_secs } => match startup_secs {
+            Some(secs) => format!("server started in {:.1}s", secs),
+            None => "server started".to_string(),
+        },
+        GameEvent::ServerStopping => "server stopping".to_string(),
+        GameEvent::Exited { crashed: true } => "server crashed".to_string(),
+        GameEvent::Exited { crashed: false } => "server stopped".to_string(),
+        GameEvent::PlayerJoined { username } => format!("{} joined the game", username),
+        GameEvent::PlayerLeft { username } => format!("{} left the game", username),
+        GameEvent::Chat { username, message } => format!("<{}> {}", username, message),
+        GameEvent::Death { message, .. } => message.clone(),
+        GameEvent::Advancement {
+            username,
+            advancement,
+        } => format!("{} made the advancement [{}]", username, advancement),
+        GameEvent::BackupFinished { backup, size_bytes } => {
+            format!("backup {} finished ({} MB)", backup, size_bytes / 1_000_000)
+        }
+        GameEvent::BackupFailed { error } => format!("backup failed: {}", error),
+        other => format!("{:?}", other),
+    };
+    format!("[{}] {}", server, text)
+}
+
+pub fn payload(format: WebhookFormat, server: &Server, event: &ServerEvent) -> serde_json::Value {
+    let text = describe(&server.name, &event.event);
+    match format {
+        WebhookFormat::Generic => json!({
+            "server_id": server.id,
+            "server_name": server.name,
+            "timestamp": event.timestamp,
+            "message": text,
+            "event": event.event,
+        }),
+        // chat is relayed as is, so nobody gets to ping @everyone or a role through it
+        WebhookFormat::Discord => json!({
+            "content": text,
+            "allowed_mentions": { "parse": [] },
+        }),
+        WebhookFormat::Slack => json!({ "text": text }),
+    }
+}
+
+/// hex encoded hmac-sha256 of the body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// what came of posting a payload
+#[derive(Debug, Clone, PartialEq)]
+struct Outcome {
+    attempts: u32,
+    success: bool,
+    response: String,
+}
+
+/// posts a payload, retrying with backoff, and records the outcome in the delivery log
+pub async fn deliver(webhook: Webhook, event_name: String, body: serde_json::Value) -> bool {
+    let outcome = post_with_retries(&webhook, &body, FIRST_RETRY).await;
+    DeliveryLog::record(Delivery {
+        webhook_id: webhook.id,
+        webhook_name: webhook.name,
+        timestamp: now(),
+        event: event_name,
+        attempts: outcome.attempts,
+        success: outcome.success,
+        response: outcome.response,
+    })
+    .await;
+    outcome.success
+}
+
+async fn post_with_retries(
+    webhook: &Webhook,
+    body: &serde_json::Value,
+    first_retry: Duration,
+) -> Outcome {
+    let body = serde_json::to_vec(body).unwrap();
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .expect("failed to build http client");
+    let mut delay = first_retry;
+    let mut attempts = 0;
+    let mut response = String::new();
+    let mut success = false;
+    while attempts < MAX_ATTEMPTS {
+        attempts += 1;
+        let mut request = client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .body(body.clone());
+        if let Some(secret) = &webhook.secret {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+        }
+        match request.send().await {
+            Ok(res) => {
+                response = res.status().to_string();
+                if res.status().is_success() {
+                    success = true;
+                    break;
+                }
+                // the receiver rejected the payload itself, trying again won't help
+                if res.status().is_client_error() && res.status().as_u16() != 429 {
+                    break;
+                }
+            }
+            Err(err) => response = err.to_string(),
+        }
+        if attempts < MAX_ATTEMPTS {
+            sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    Outcome {
+        attempts,
+        success,
+        response,
+    }
+}
+
+/// forwards game events to the configured webhooks
+pub struct WebhookDispatcher;
+
+impl WebhookDispatcher {
+    pub fn begin(config: ConfigurationManager, runner_handle: ServerRunnerHandle) {
+        let mut events = runner_handle.subscribe();
+        spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        println!("webhooks fell behind, skipped {} events", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let Some(kind) = WebhookEvent::from_game_event(&event.event) else {
+                    continue;
+                };
+                let webhooks = config.get_webhooks().await;
+                if !webhooks.iter().any(|x| x.wants(event.server_id, kind)) {
+                    continue;
+                }
+                let Some(server) = config.get_server(event.server_id).await else {
+                    continue;
+                };
+                for webhook in webhooks
+                    .into_iter()
+                    .filter(|x| x.wants(event.server_id, kind))
+                {
+                    let body = payload(webhook.format, &server, &event);
+                    let event_name = serde_json::to_value(kind)
+                        .ok()
+                        .and_then(|x| x.as_str().map(String::from))
+                        .unwrap_or_default();
+                    spawn(deliver(webhook, event_name, body));
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+
+    /// a request as the stand-in receiver saw it
+    struct Received {
+        at: Instant,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    /// answers with each status in turn on a local port, returns its url and what it got
+    fn stand_in(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(vec![]));
+        let log = received.clone();
+        thread::spawn(move || {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        headers.push((name.to_lowercase(), value.to_string()));
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map(|(_, x)| x.parse().unwrap())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                log.lock().unwrap().push(Received {
+                    at: Instant::now(),
+                    headers,
+                    body,
+                });
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn webhook(url: String, secret: Option<&str>) -> Webhook {
+        Webhook {
+            id: 0,
+            name: "test".to_string(),
+            url,
+            format: WebhookFormat::Generic,
+            server_id: None,
+            events: WebhookEvent::ALL.to_vec(),
+            secret: secret.map(str::to_string),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn discord_payloads_mention_nobody() {
+        let server = Server::new("s0".to_string(), "1.21".to_string(), true);
+        let event = ServerEvent {
+            server_id: 0,
+            timestamp: 0,
+            event: GameEvent::Chat {
+                username: "Steve".to_string(),
+                message: "@everyone <@&123> hi".to_string(),
+            },
+        };
+        let body = payload(WebhookFormat::Discord, &server, &event);
+        assert_eq!(body["content"], "[s0] <Steve> @everyone <@&123> hi");
+        assert_eq!(body["allowed_mentions"], json!({ "parse": [] }));
+        assert!(
+            payload(WebhookFormat::Slack, &server, &event)
+                .get("allowed_mentions")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn delivers_a_signed_body() {
+        let (url, received) = stand_in(vec![204]);
+        let body = json!({ "content": "[s0] Steve joined the game" });
+        let outcome =
+            post_with_retries(&webhook(url, Some("hunter2")), &body, Duration::ZERO).await;
+        assert!(outcome.success);
+        assert_eq!(outcome.attempts, 1);
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&request.body).unwrap(),
+            body
+        );
+        let signature = request
+            .headers
+            .iter()
+            .find(|(name, _)| name == &SIGNATURE_HEADER.to_lowercase())
+            .map(|(_, x)| x.clone())
+            .unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"hunter2").unwrap();
+        mac.update(&request.body);
+        assert_eq!(
+            signature,
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+        );
+    }
+
+    #[tokio::test]
+    async fn unsigned_without_a_secret() {
+        let (url, received) = stand_in(vec![200]);
+        post_with_retries(&webhook(url, None), &json!({}), Duration::ZERO).await;
+        let received = received.lock().unwrap();
+        assert!(
+            received[0]
+                .headers
+                .iter()
+                .all(|(name, _)| name != &SIGNATURE_HEADER.to_lowercase())
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_with_backoff() {
+        let (url, received) = stand_in(vec![503, 500, 200]);
+        let first_retry = Duration::from_millis(100);
+        let outcome = post_with_retries(&webhook(url, None), &json!({}), first_retry).await;
+        assert!(outcome.success);
+        assert_eq!(outcome.attempts, 3);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        assert!(received[1].at - received[0].at >= first_retry);
+        assert!(received[2].at - received[1].at >= first_retry * 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let (url, received) = stand_in(vec![502; MAX_ATTEMPTS as usize]);
+        let outcome = post_with_retries(&webhook(url, None), &json!({}), Duration::ZERO).await;
+        assert!(!outcome.success);
+        assert_eq!(outcome.attempts, MAX_ATTEMPTS);
+        assert_eq!(received.lock().unwrap().len(), MAX_ATTEMPTS as usize);
+    }
+
+    #[tokio::test]
+    async fn doesnt_retry_rejected_payloads() {
+        let (url, received) = stand_in(vec![400, 200]);
+        let outcome = post_with_retries(&webhook(url, None), &json!({}), Duration::ZERO).await;
+        assert!(!outcome.success);
+        assert_eq!(outcome.attempts, 1);
+        assert_eq!(outcome.response, "400 Bad Request");
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,252 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+
+use crate::{
+    access::{AccessAction, AccessChange, AccessList, AccessLists},
+    properties::ServerProperties,
+    webui::{auth::Info, state::WebState},
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "access.html",
+            include_str!("../../webui/templates/access.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize)]
+pub struct AccessForm {
+    pub list: AccessList,
+    pub action: AccessAction,
+    pub target: String,
+    /// empty for the server's default
+    #[serde(default)]
+    pub level: String,
+    #[serde(default)]
+    pub reason: String,
+}
+
+impl AccessForm {
+    pub fn into_change(self) -> Result<AccessChange, String> {
+        let level = match self.level.trim() {
+            "" => None,
+            level => Some(level.parse().map_err(|_| "invalid op level")?),
+        };
+        Ok(AccessChange {
+            list: self.list,
+            action: self.action,
+            target: self.target,
+            level,
+            reason: Some(self.reason),
+        })
+    }
+}
+
+/// sends the change to the console if the server is running, otherwise edits its files
+async fn apply_change(
+    state: &WebState,
+    server_id: usize,
+    change: &AccessChange,
+) -> Result<(), String> {
+    if state.runner_handle.is_running(server_id).await {
+        let default_op_level = ServerProperties::load(server_id)
+            .await
+            .get("op-permission-level")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(4);
+        let command = change.command(default_op_level)?;
+        state.runner_handle.issue_command(server_id, command);
+        Ok(())
+    } else {
+        let lookup_url = state.config.get_profile_lookup_url().await;
+        AccessLists::apply_offline(server_id, change, &lookup_url).await
+    }
+}
+
+#[get("/access/{id}")]
+async fn access_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("access login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let mut context = Context::new();
+    context.insert("server", &server);
+    match AccessLists::load(server.id).await {
+        Ok(lists) => context.insert("lists", &lists),
+        Err(err) => {
+            context.insert("lists", &AccessLists::default());
+            context.insert("lists_error", &err);
+        }
+    }
+    let body = TEMPLATES
+        .render("access.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/access/{id}")]
+async fn change_access(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<AccessForm>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("access login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let change = match form.into_change() {
+        Ok(change) => change,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    if let Err(err) = apply_change(&state, server.id, &change).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/access/{}", server.id)))
+        .body("success")
+}
+
+#[get("/api/servers/{id}/access")]
+async fn get_access_api(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+    let Some(_user) = user else {
+        return HttpResponse::Unauthorized().body("not logged in");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    match AccessLists::load(server.id).await {
+        Ok(lists) => HttpResponse::Ok().json(lists),
+        Err(err) => HttpResponse::InternalServerError().body(err),
+    }
+}
+
+/// a running server applies the change on its own, so it may take a moment to show up
+#[post("/api/servers/{id}/access")]
+async fn change_access_api(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Json(change): web::Json<AccessChange>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+    let Some(_user) = user else {
+        return HttpResponse::Unauthorized().body("not logged in");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    if let Err(err) = apply_change(&state, server.id, &change).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    match AccessLists::load(server.id).await {
+        Ok(lists) => HttpResponse::Ok().json(lists),
+        Err(err) => HttpResponse::InternalServerError().body(err),
+    }
+}
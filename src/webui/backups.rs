@@ -0,0 +1,602 @@
+use actix_files::NamedFile;
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{
+        StatusCode,
+        header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    },
+    post,
+    rt::spawn,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+
+use crate::{
+    backups::{
+        BackupCompression, BackupFormat, BackupKind, BackupList, BackupSettings, backup_server,
+        browse_backup, clone_backup, delete_backup, export_backup, is_backing_up, read_backup_file,
+        restore_backup, store_stats, verify_backup,
+    },
+    webui::{
+        auth::Info,
+        state::{Checked, WebState},
+    },
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "backups.html",
+            include_str!("../../webui/templates/backups.html"),
+        )
+        .expect("Failed to add raw template");
+        tera.add_raw_template(
+            "backup_files.html",
+            include_str!("../../webui/templates/backup_files.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize)]
+pub struct BackupSettingsForm {
+    pub enabled: Option<Checked>,
+    pub every_hours: u64,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+    pub compression: BackupCompression,
+    pub format: BackupFormat,
+}
+
+#[derive(Deserialize)]
+pub struct BackupName {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct CloneBackup {
+    pub name: String,
+    pub new_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ArchivePath {
+    /// directory or file inside the archive, empty for the top
+    #[serde(default)]
+    pub path: String,
+}
+
+#[get("/backups/{id}")]
+async fn backups_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let mut context = Context::new();
+    match BackupList::load(server.id).await {
+        Ok(list) => {
+            let mut backups = list.backups;
+            backups.reverse();
+            context.insert("backups", &backups);
+        }
+        Err(err) => {
+            context.insert("backups", &Vec::<()>::new());
+            context.insert("backups_error", &err);
+        }
+    }
+    context.insert("server", &server);
+    context.insert("settings", &server.backups.clone().unwrap_or_default());
+    context.insert("compressions", &BackupCompression::ALL);
+    context.insert("formats", &BackupFormat::ALL);
+    context.insert("store", &store_stats(server.id).await);
+    context.insert("in_progress", &is_backing_up(server.id));
+    context.insert("targets", &state.config.get_backup_targets().await);
+    let body = TEMPLATES
+        .render("backups.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/backups/{id}/settings")]
+async fn set_backup_settings(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<BackupSettingsForm>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    if form.every_hours == 0 {
+        return HttpResponse::BadRequest().body("backups must be at least an hour apart");
+    }
+    if form.keep_daily == 0 && form.keep_weekly == 0 {
+        return HttpResponse::BadRequest().body("keep at least one daily or weekly backup");
+    }
+    let enabled: bool = form.enabled.unwrap_or(Checked::Off).into();
+    let settings = enabled.then_some(BackupSettings {
+        every_hours: form.every_hours,
+        keep_daily: form.keep_daily,
+        keep_weekly: form.keep_weekly,
+        compression: form.compression,
+        format: form.format,
+    });
+    state.config.set_server_backups(server.id, settings).await;
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/backups/{}", server.id)))
+        .body("success")
+}
+
+/// starts a manual backup in the background, the page shows it as running until it's done
+#[post("/backups/{id}/now")]
+async fn backup_now(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if is_backing_up(server.id) {
+        return HttpResponse::build(StatusCode::CONFLICT)
+            .body("a backup of this server is already running");
+    }
+
+    let runner_handle = state.runner_handle.clone();
+    let server_id = server.id;
+    spawn(async move {
+        if let Err(err) = backup_server(&runner_handle, &server, BackupKind::Manual).await {
+            println!("backup of server {} failed: {}", server.id, err);
+        }
+    });
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/backups/{}", server_id)))
+        .body("success")
+}
+
+#[post("/backups/{id}/delete")]
+async fn remove_backup(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<BackupName>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    if let Err(err) = delete_backup(server.id, &form.name).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/backups/{}", server.id)))
+        .body("success")
+}
+
+#[get("/backups/{id}/browse/{name}")]
+async fn browse_backup_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, String)>,
+    query: web::Query<ArchivePath>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, name) = path.into_inner();
+    let Some(server) = state.config.get_server(server_id).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let dir = query.path.trim_matches('/');
+    let entries = match browse_backup(server.id, &name, dir).await {
+        Ok(entries) => entries,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let parent = dir.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("name", &name);
+    context.insert("dir", &dir);
+    context.insert("parent", &parent);
+    context.insert("entries", &entries);
+    let body = TEMPLATES
+        .render("backup_files.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[get("/backups/{id}/file/{name}")]
+async fn download_backup_file(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, String)>,
+    query: web::Query<ArchivePath>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, name) = path.into_inner();
+    let Some(server) = state.config.get_server(server_id).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let contents = match read_backup_file(server.id, &name, &query.path).await {
+        Ok(contents) => contents,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let file_name = query
+        .path
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    HttpResponse::Ok()
+        .content_type(ContentType::octet_stream())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(file_name)],
+        })
+        .body(contents)
+}
+
+#[get("/backups/{id}/download/{name}")]
+async fn download_backup(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, String)>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, name) = path.into_inner();
+    let Some(server) = state.config.get_server(server_id).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let (archive, temporary) = match export_backup(server.id, &name).await {
+        Ok(exported) => exported,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let file = NamedFile::open_async(&archive).await;
+    if temporary {
+        // the open file keeps the data around until it has been sent
+        let _ = tokio::fs::remove_file(&archive).await;
+    }
+    let file_name = archive
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    match file {
+        Ok(file) => file
+            .set_content_disposition(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(file_name)],
+            })
+            .into_response(&req),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+/// checks the archive in the background, the result shows up in the list
+#[post("/backups/{id}/verify")]
+async fn verify_backup_now(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<BackupName>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if is_backing_up(server.id) {
+        return HttpResponse::build(StatusCode::CONFLICT)
+            .body("a backup of this server is already running");
+    }
+
+    let runner_handle = state.runner_handle.clone();
+    spawn(async move {
+        if let Err(err) = verify_backup(&runner_handle, server.id, &form.name).await {
+            println!("failed to verify {}: {}", form.name, err);
+        }
+    });
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/backups/{}", server.id)))
+        .body("success")
+}
+
+#[post("/backups/{id}/restore")]
+async fn restore_backup_now(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<BackupName>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    if let Err(err) = restore_backup(&state.runner_handle, &server, &form.name).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/backups/{}", server.id)))
+        .body("success")
+}
+
+#[post("/backups/{id}/clone")]
+async fn clone_backup_now(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<CloneBackup>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("backups login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let new_name = form.new_name.trim();
+    if new_name.is_empty() {
+        return HttpResponse::BadRequest().body("the new server needs a name");
+    }
+    let clone = match clone_backup(&state.config, &server, &form.name, new_name.to_string()).await {
+        Ok(clone) => clone,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/server/{}", clone.id)))
+        .body("success")
+}
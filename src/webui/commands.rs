@@ -8,7 +8,11 @@ use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use tera::{Context, Tera};
 
-use crate::webui::{auth::Info, state::WebState};
+use crate::{
+    metrics::Chart,
+    players::PlayerHistory,
+    webui::{auth::Info, state::WebState},
+};
 
 lazy_static! {
     pub static ref TEMPLATES: Tera = {
@@ -29,7 +33,7 @@ async fn command_dashboard(
     path: web::Path<usize>,
 ) -> impl Responder {
     let user = if let Some(cookie) = req.cookie("auth") {
-        if let Ok(user) = serde_json::from_str::<Info>(&cookie.value()) {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
             match state
                 .config
                 .validate_password(user.username.clone(), user.password.clone())
@@ -60,10 +64,42 @@ async fn command_dashboard(
         .get_output(server.id)
         .await
         .unwrap_or("".to_string());
+    let online = state
+        .runner_handle
+        .get_online_players(server.id)
+        .await
+        .unwrap_or_default();
+    let restart_required = state
+        .runner_handle
+        .get_statuses()
+        .await
+        .iter()
+        .any(|x| x.id == server.id && x.restart_required);
+    let metrics = state
+        .runner_handle
+        .get_metrics(server.id)
+        .await
+        .unwrap_or_default();
 
     let mut context = Context::new();
     context.insert("server", &server);
     context.insert("output", &output);
+    context.insert("sleeping", &state.sleeper.is_sleeping(server.id).await);
+    context.insert("restart_required", &restart_required);
+    context.insert("online", &online);
+    match PlayerHistory::load(server.id).await {
+        Ok(history) => context.insert("history", &history.players),
+        Err(err) => {
+            context.insert("history", &Vec::<()>::new());
+            context.insert("history_error", &err);
+        }
+    }
+    context.insert("usage", &metrics.latest());
+    context.insert("recent_charts", &Chart::for_samples(metrics.recent.iter()));
+    context.insert(
+        "history_charts",
+        &Chart::for_samples(metrics.history.iter()),
+    );
     let body = TEMPLATES
         .render("commands.html", &context)
         .expect("failed to render");
@@ -86,7 +122,7 @@ async fn command_endpoint(
     web::Form(form): web::Form<Command>,
 ) -> impl Responder {
     let user = if let Some(cookie) = req.cookie("auth") {
-        if let Ok(user) = serde_json::from_str::<Info>(&cookie.value()) {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
             match state
                 .config
                 .validate_password(user.username.clone(), user.password.clone())
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use actix_web::{
     HttpRequest, HttpResponse, Responder, get,
     http::{StatusCode, header::ContentType},
@@ -44,8 +46,20 @@ async fn dash(req: HttpRequest, state: Data<WebState>) -> impl Responder {
             .body("");
     };
 
+    let servers = state.config.get_servers().await;
+    let mut usage = HashMap::new();
+    for server in &servers {
+        if let Some(metrics) = state.runner_handle.get_metrics(server.id).await
+            && let Some(latest) = metrics.latest()
+        {
+            usage.insert(server.id.to_string(), latest.clone());
+        }
+    }
+
     let mut context = Context::new();
-    context.insert("servers", &state.config.get_servers().await);
+    context.insert("servers", &servers);
+    context.insert("usage", &usage);
+    context.insert("sleeping", &state.sleeper.sleeping().await);
     let body = TEMPLATES
         .render("dash.html", &context)
         .expect("failed to render");
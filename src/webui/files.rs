@@ -0,0 +1,453 @@
+use actix_files::NamedFile;
+use actix_multipart::{Field, Multipart};
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{
+        StatusCode,
+        header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    },
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_stream::StreamExt;
+
+use crate::{
+    files::{
+        MAX_EDIT_BYTES, MAX_UPLOAD_BYTES, clean_path, create_dir, delete_path, is_dir, list_dir,
+        parent_path, read_text_file, rename_path, resolve, unzip_path, upload_paths,
+        write_text_file, zip_path,
+    },
+    targets::s3::uri_encode,
+    webui::{auth::Info, state::WebState},
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "files.html",
+            include_str!("../../webui/templates/files.html"),
+        )
+        .expect("Failed to add raw template");
+        tera.add_raw_template("file.html", include_str!("../../webui/templates/file.html"))
+            .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize)]
+pub struct FilePath {
+    /// relative to the server's directory, empty for the top
+    #[serde(default)]
+    pub path: String,
+}
+
+#[derive(Deserialize)]
+pub struct FileAction {
+    pub path: String,
+    /// the new name for mkdir and rename
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    /// makes `name` inside the directory at `path`
+    Mkdir,
+    Rename,
+    Delete,
+    Zip,
+    Unzip,
+}
+
+/// the page for a directory or file, where the browser goes after a change
+fn back_to(server_id: usize, path: &str) -> String {
+    format!("/files/{}?path={}", server_id, uri_encode(path, true))
+}
+
+/// a small form field as text
+async fn read_text_field(field: &mut Field) -> Result<String, String> {
+    let mut value = vec![];
+    while let Some(chunk) = field.next().await {
+        value.extend_from_slice(&chunk.map_err(|err| err.to_string())?);
+        if value.len() as u64 > MAX_EDIT_BYTES {
+            return Err("field is too big".to_string());
+        }
+    }
+    String::from_utf8(value).map_err(|_| "field isn't utf-8".to_string())
+}
+
+#[get("/files/{id}")]
+async fn files_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    query: web::Query<FilePath>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("files login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let Ok(relative) = clean_path(&query.path) else {
+        return HttpResponse::BadRequest().body("invalid path");
+    };
+    let relative = relative.to_string_lossy().replace('\\', "/");
+
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("path", &relative);
+    context.insert("parent", &parent_path(&relative));
+    let body = if is_dir(server.id, &relative).await {
+        let entries = match list_dir(server.id, &relative).await {
+            Ok(entries) => entries,
+            Err(err) => return HttpResponse::BadRequest().body(err),
+        };
+        context.insert("entries", &entries);
+        context.insert("max_upload_bytes", &MAX_UPLOAD_BYTES);
+        TEMPLATES.render("files.html", &context)
+    } else {
+        let contents = match read_text_file(server.id, &relative).await {
+            Ok(contents) => contents,
+            Err(err) => return HttpResponse::BadRequest().body(err),
+        };
+        context.insert("contents", &contents);
+        TEMPLATES.render("file.html", &context)
+    }
+    .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[get("/files/{id}/download")]
+async fn download_file(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    query: web::Query<FilePath>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("files login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let file = match resolve(server.id, &query.path).await {
+        Ok(file) => file,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    if !fs::metadata(&file).await.is_ok_and(|x| x.is_file()) {
+        return HttpResponse::BadRequest().body("not a file, zip directories to download them");
+    }
+    let name = file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    match NamedFile::open_async(&file).await {
+        Ok(file) => file
+            .set_content_disposition(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(name)],
+            })
+            .into_response(&req),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+/// takes `path` and `contents` as multipart since a form post is limited to 16KiB
+#[post("/files/{id}/save")]
+async fn save_file(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("files login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let mut file_path = None;
+    let mut contents = None;
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+        };
+        let value = match field.name() {
+            Some("path") | Some("contents") => match read_text_field(&mut field).await {
+                Ok(value) => value,
+                Err(err) => return HttpResponse::BadRequest().body(err),
+            },
+            _ => {
+                while field.next().await.is_some() {}
+                continue;
+            }
+        };
+        if field.name() == Some("path") {
+            file_path = Some(value);
+        } else {
+            // browsers send textareas with crlf line endings
+            contents = Some(value.replace("\r\n", "\n"));
+        }
+    }
+    let (Some(file_path), Some(contents)) = (file_path, contents) else {
+        return HttpResponse::BadRequest().body("path and contents are required");
+    };
+    if let Err(err) = write_text_file(server.id, &file_path, &contents).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", back_to(server.id, &file_path)))
+        .body("success")
+}
+
+/// takes the directory as `dir` before any number of `file` fields
+#[post("/files/{id}/upload")]
+async fn upload_files(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("files login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let mut dir = None;
+    let mut received = 0;
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+        };
+        match field.name() {
+            Some("dir") => match read_text_field(&mut field).await {
+                Ok(value) => dir = Some(value),
+                Err(err) => return HttpResponse::BadRequest().body(err),
+            },
+            Some("file") => {
+                let Some(dir) = &dir else {
+                    return HttpResponse::BadRequest().body("dir has to come before the files");
+                };
+                let name = field
+                    .content_disposition()
+                    .and_then(|x| x.get_filename())
+                    .unwrap_or_default()
+                    .to_string();
+                // a file input left empty still sends a field without a name
+                if name.is_empty() {
+                    while field.next().await.is_some() {}
+                    continue;
+                }
+                if let Err(err) = receive_file(server.id, dir, &name, &mut field).await {
+                    return HttpResponse::BadRequest().body(err);
+                }
+                received += 1;
+            }
+            _ => while field.next().await.is_some() {},
+        }
+    }
+    if received == 0 {
+        return HttpResponse::BadRequest().body("no files uploaded");
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header((
+            "Location",
+            back_to(server.id, dir.as_deref().unwrap_or_default()),
+        ))
+        .body("success")
+}
+
+/// streams one upload next to its final name and moves it over once it's all there
+async fn receive_file(
+    server_id: usize,
+    dir: &str,
+    name: &str,
+    field: &mut Field,
+) -> Result<(), String> {
+    let (uploading, destination) = upload_paths(server_id, dir, name).await?;
+    let result = async {
+        let mut file = fs::File::create(&uploading)
+            .await
+            .map_err(|err| err.to_string())?;
+        let mut size: u64 = 0;
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|err| err.to_string())?;
+            size += chunk.len() as u64;
+            if size > MAX_UPLOAD_BYTES {
+                return Err(format!("{} is too big", name));
+            }
+            file.write_all(&chunk)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        file.flush().await.map_err(|err| err.to_string())?;
+        fs::rename(&uploading, &destination)
+            .await
+            .map_err(|err| format!("failed to move {} into place: {}", name, err))
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&uploading).await;
+    }
+    result
+}
+
+#[post("/files/{id}/{change}")]
+async fn change_file(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, FileChange)>,
+    web::Form(form): web::Form<FileAction>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("files login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, change) = path.into_inner();
+    let Some(server) = state.config.get_server(server_id).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let name = form.name.trim();
+    let result = match change {
+        FileChange::Mkdir => create_dir(server.id, &form.path, name).await,
+        FileChange::Rename => rename_path(server.id, &form.path, name).await,
+        FileChange::Delete => delete_path(server.id, &form.path).await,
+        FileChange::Zip => zip_path(server.id, &form.path).await,
+        FileChange::Unzip => unzip_path(server.id, &form.path).await,
+    };
+    if let Err(err) = result {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    let dir = match change {
+        FileChange::Mkdir => form.path.clone(),
+        _ => parent_path(&form.path),
+    };
+    HttpResponse::SeeOther()
+        .insert_header(("Location", back_to(server.id, &dir)))
+        .body("success")
+}
@@ -0,0 +1,317 @@
+use std::path::{Path, PathBuf};
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_stream::StreamExt;
+
+use crate::{
+    configuration::Server,
+    files::MAX_UPLOAD_BYTES,
+    loaders::forge::InstallState,
+    mods::{
+        modrinth,
+        mrpack::{download_pack, import_job, incoming_path},
+    },
+    webui::{
+        auth::Info,
+        state::{Checked, WebState},
+    },
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "import.html",
+            include_str!("../../webui/templates/import.html"),
+        )
+        .expect("Failed to add raw template");
+        tera.add_raw_template(
+            "import_job.html",
+            include_str!("../../webui/templates/import_job.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize)]
+pub struct PackSearch {
+    /// searches modrinth for modpacks when given, even if empty
+    query: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ModrinthPack {
+    servername: String,
+    project: String,
+    /// empty picks the newest release
+    #[serde(default)]
+    version: String,
+    eula: Option<Checked>,
+}
+
+#[get("/import")]
+async fn import_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    web::Query(query): web::Query<PackSearch>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("import page login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let mut context = Context::new();
+    context.insert("query", &query.query);
+    if let Some(search) = &query.query {
+        match modrinth::search(search, "modpack", &[], None).await {
+            Ok(hits) => context.insert("results", &hits),
+            Err(err) => context.insert("error", &err),
+        }
+    }
+    let body = TEMPLATES
+        .render("import.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+/// turns the result of creating a server from a pack into a response, the pack is
+/// deleted when no server took it
+async fn created(result: Result<Option<Server>, String>, archive: &Path) -> HttpResponse {
+    let server = match result {
+        Ok(Some(server)) => server,
+        Ok(None) => {
+            let _ = fs::remove_file(archive).await;
+            return HttpResponse::build(StatusCode::CONFLICT)
+                .body("no free port left in the port pool");
+        }
+        Err(err) => {
+            let _ = fs::remove_file(archive).await;
+            return HttpResponse::BadRequest().body(err);
+        }
+    };
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/import/{}", server.id)))
+        .body("success")
+}
+
+async fn read_text_field(field: &mut Field) -> Result<String, String> {
+    let mut value = vec![];
+    while let Some(chunk) = field.next().await {
+        value.extend_from_slice(&chunk.map_err(|err| err.to_string())?);
+    }
+    String::from_utf8(value).map_err(|_| "field isn't utf-8".to_string())
+}
+
+async fn receive_pack(field: &mut Field, archive: &PathBuf) -> Result<(), String> {
+    let mut file = fs::File::create(archive)
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut size: u64 = 0;
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|err| err.to_string())?;
+        size += chunk.len() as u64;
+        if size > MAX_UPLOAD_BYTES {
+            return Err("the pack is too big".to_string());
+        }
+        file.write_all(&chunk)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    file.flush().await.map_err(|err| err.to_string())
+}
+
+/// takes `servername`, `eula` and the `pack` file
+#[post("/import/upload")]
+async fn upload_pack(
+    req: HttpRequest,
+    state: Data<WebState>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("pack upload login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let archive = incoming_path();
+    let mut name = String::new();
+    let mut eula = false;
+    let mut received = false;
+    let result = async {
+        fs::create_dir_all("./servers")
+            .await
+            .map_err(|err| err.to_string())?;
+        while let Some(field) = payload.next().await {
+            let mut field = field.map_err(|err| err.to_string())?;
+            match field.name() {
+                Some("servername") => name = read_text_field(&mut field).await?.trim().to_string(),
+                Some("eula") => eula = read_text_field(&mut field).await? == "on",
+                Some("pack") => {
+                    receive_pack(&mut field, &archive).await?;
+                    received = true;
+                }
+                _ => while field.next().await.is_some() {},
+            }
+        }
+        Ok::<_, String>(())
+    }
+    .await;
+    if let Err(err) = result {
+        let _ = fs::remove_file(&archive).await;
+        return HttpResponse::BadRequest().body(err);
+    }
+    if !received {
+        return HttpResponse::BadRequest().body("no pack uploaded");
+    }
+    if name.is_empty() {
+        let _ = fs::remove_file(&archive).await;
+        return HttpResponse::BadRequest().body("the server needs a name");
+    }
+
+    let result = state.create_pack_server(name, eula, archive.clone()).await;
+    created(result, &archive).await
+}
+
+#[post("/import/modrinth")]
+async fn import_modrinth_pack(
+    req: HttpRequest,
+    state: Data<WebState>,
+    web::Form(form): web::Form<ModrinthPack>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("pack import login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let archive = incoming_path();
+    let version = Some(form.version.trim()).filter(|x| !x.is_empty());
+    if let Err(err) = download_pack(form.project.trim(), version, &archive).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    let eula = form.eula.unwrap_or(Checked::Off).into();
+    let result = state
+        .create_pack_server(form.servername, eula, archive.clone())
+        .await;
+    created(result, &archive).await
+}
+
+#[get("/import/{id}")]
+async fn import_progress(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("import progress login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let job = import_job(server.id);
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("job", &job);
+    context.insert(
+        "running",
+        &job.is_some_and(|x| x.state == InstallState::Running),
+    );
+    let body = TEMPLATES
+        .render("import_job.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
@@ -0,0 +1,133 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use tera::{Context, Tera};
+
+use crate::{
+    loaders::{
+        ensure_available,
+        forge::{InstallState, install_job, is_installing},
+    },
+    webui::{auth::Info, state::WebState},
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "install.html",
+            include_str!("../../webui/templates/install.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[get("/install/{id}")]
+async fn install_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("install page login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let job = install_job(server.id);
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("job", &job);
+    context.insert(
+        "running",
+        &job.is_some_and(|x| x.state == InstallState::Running),
+    );
+    let body = TEMPLATES
+        .render("install.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/install/{id}")]
+async fn rerun_install(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("install login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if server.server_type.forge().is_none() {
+        return HttpResponse::BadRequest()
+            .body("only forge and neoforge servers have an installer");
+    }
+    if is_installing(server.id) {
+        return HttpResponse::build(StatusCode::CONFLICT).body("the installer is already running");
+    }
+    // the installer rewrites the libraries the server is running from
+    if state.runner_handle.is_running(server.id).await {
+        return HttpResponse::build(StatusCode::CONFLICT).body("stop the server first");
+    }
+    if let Err(err) = ensure_available(&server.server_type, &server.mc_version_id).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+    state.install_in_background(server.clone());
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/install/{}", server.id)))
+        .body("success")
+}
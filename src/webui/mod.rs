@@ -1,7 +1,22 @@
+pub mod access;
 pub mod auth;
+pub mod backups;
 pub mod commands;
 pub mod dash;
+pub mod files;
+pub mod import;
+pub mod install;
+pub mod mods;
 pub mod new;
+pub mod players;
+pub mod prometheus;
+pub mod properties;
 pub mod routes;
+pub mod settings;
 pub mod state;
+pub mod targets;
+pub mod tasks;
 pub mod toggle_enabled;
+pub mod upgrade;
+pub mod webhooks;
+pub mod worlds;
@@ -0,0 +1,384 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+
+use crate::{
+    mods::{
+        self, ModLock, ModTarget, modrinth,
+        updates::{apply_updates, can_roll_back, check_updates, last_check, roll_back},
+    },
+    webui::{auth::Info, state::WebState},
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template("mods.html", include_str!("../../webui/templates/mods.html"))
+            .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize)]
+pub struct ModsQuery {
+    /// searches modrinth when given, even if empty
+    query: Option<String>,
+    /// lists the versions of a project that fit the server
+    project: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct InstallMod {
+    project: String,
+    /// empty picks the newest compatible one
+    #[serde(default)]
+    version: String,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveMod {
+    project: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateMods {
+    /// empty updates everything the last check found
+    #[serde(default)]
+    project: String,
+}
+
+#[get("/mods/{id}")]
+async fn mods_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Query(query): web::Query<ModsQuery>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("mods page login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let target = ModTarget::for_server(&server.server_type);
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("target", &target);
+    match ModLock::load(server.id).await {
+        Ok(lock) => context.insert("installed", &lock.projects),
+        Err(err) => {
+            context.insert("installed", &Vec::<()>::new());
+            context.insert("installed_error", &err);
+        }
+    }
+    context.insert("query", &query.query);
+    context.insert("update_check", &last_check(server.id));
+    context.insert("can_roll_back", &can_roll_back(server.id).await);
+    if let Some(target) = &target {
+        if let Some(search) = &query.query {
+            match modrinth::search(
+                search,
+                target.project_type,
+                target.loaders,
+                Some(&server.mc_version_id),
+            )
+            .await
+            {
+                Ok(hits) => context.insert("results", &hits),
+                Err(err) => context.insert("error", &err),
+            }
+        }
+        if let Some(project) = &query.project {
+            let found = async {
+                let project = modrinth::project(project).await?;
+                let versions =
+                    modrinth::versions(&project.id, target.loaders, Some(&server.mc_version_id))
+                        .await?;
+                Ok::<_, String>((project, versions))
+            }
+            .await;
+            match found {
+                Ok((project, versions)) => {
+                    context.insert("project", &project);
+                    context.insert("versions", &versions);
+                }
+                Err(err) => context.insert("error", &err),
+            }
+        }
+    }
+    let body = TEMPLATES
+        .render("mods.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/mods/{id}/install")]
+async fn install_mod(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<InstallMod>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("mod install login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let version = Some(form.version.trim()).filter(|x| !x.is_empty());
+    match mods::install(&server, form.project.trim(), version).await {
+        Ok(installed) => {
+            for project in installed {
+                println!(
+                    "installed {} {} on server {}",
+                    project.title, project.version_number, server.id
+                );
+            }
+        }
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    }
+    state.runner_handle.require_restart(server.id);
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/mods/{}", server.id)))
+        .body("success")
+}
+
+#[post("/mods/{id}/remove")]
+async fn remove_mod(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<RemoveMod>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("mod removal login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    match mods::remove(&server, &form.project).await {
+        Ok(removed) => {
+            for title in removed {
+                println!("removed {} from server {}", title, server.id);
+            }
+        }
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    }
+    state.runner_handle.require_restart(server.id);
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/mods/{}", server.id)))
+        .body("success")
+}
+
+#[post("/mods/{id}/check")]
+async fn check_mod_updates(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("mod update check login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if let Some(err) = check_updates(&server).await.error {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/mods/{}", server.id)))
+        .body("success")
+}
+
+#[post("/mods/{id}/update")]
+async fn update_mods(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<UpdateMods>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("mod update login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let project = Some(form.project.trim()).filter(|x| !x.is_empty());
+    match apply_updates(&state.runner_handle, &server, project).await {
+        Ok(applied) => {
+            for update in applied {
+                println!("updated {} on server {}", update, server.id);
+            }
+        }
+        Err(err) => {
+            // whatever was updated before the failure still needs a restart
+            state.runner_handle.require_restart(server.id);
+            return HttpResponse::BadRequest().body(err);
+        }
+    }
+    state.runner_handle.require_restart(server.id);
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/mods/{}", server.id)))
+        .body("success")
+}
+
+#[post("/mods/{id}/rollback")]
+async fn rollback_mods(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("mod rollback login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if let Err(err) = roll_back(&server).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+    state.runner_handle.require_restart(server.id);
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/mods/{}", server.id)))
+        .body("success")
+}
@@ -5,10 +5,15 @@ use actix_web::{
     web::{self, Data},
 };
 use lazy_static::lazy_static;
+use serde::Deserialize;
 use tera::{Context, Tera};
 
 use crate::{
-    versions::PackagesList,
+    loaders::{
+        fabric,
+        forge::{self, ForgeKind},
+    },
+    versions::VersionCatalog,
     webui::{
         auth::Info,
         state::{NewServer, WebState},
@@ -27,8 +32,19 @@ lazy_static! {
     };
 }
 
+/// lists the forge or neoforge versions for a game version on the page
+#[derive(Deserialize)]
+pub struct ForgeQuery {
+    forge: Option<ForgeKind>,
+    version: Option<String>,
+}
+
 #[get("/new")]
-async fn new_server(req: HttpRequest, state: Data<WebState>) -> impl Responder {
+async fn new_server(
+    req: HttpRequest,
+    state: Data<WebState>,
+    web::Query(query): web::Query<ForgeQuery>,
+) -> impl Responder {
     let user = if let Some(cookie) = req.cookie("auth") {
         if let Ok(user) = serde_json::from_str::<Info>(&cookie.value()) {
             match state
@@ -54,12 +70,27 @@ async fn new_server(req: HttpRequest, state: Data<WebState>) -> impl Responder {
             .body("");
     };
 
-    let packages = PackagesList::new().await;
+    let packages = match VersionCatalog::packages().await {
+        Ok(packages) => packages,
+        Err(err) => return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).body(err),
+    };
     let latest = packages.get_latest_release();
 
     let mut context = Context::new();
     context.insert("latest", &latest.id);
     context.insert("versions", &packages.versions);
+    // the page still works for vanilla servers when fabric can't be reached
+    context.insert("fabric_loaders", &fabric::cached_loader_versions().await);
+    context.insert("forge_kind", "");
+    context.insert("forge_mc", &latest.id);
+    if let (Some(kind), Some(version)) = (query.forge, query.version) {
+        context.insert("forge_kind", kind.name());
+        context.insert("forge_mc", &version);
+        match forge::versions(kind, &version).await {
+            Ok(versions) => context.insert("forge_versions", &versions),
+            Err(err) => context.insert("forge_error", &err),
+        }
+    }
     let body = TEMPLATES
         .render("new_server.html", &context)
         .expect("failed to render");
@@ -100,9 +131,59 @@ async fn create_new_server(
             .body("");
     };
 
-    state.create_new_server(form).await;
+    let server = match state.create_new_server(form).await {
+        Ok(Some(server)) => server,
+        Ok(None) => {
+            return HttpResponse::build(StatusCode::CONFLICT)
+                .body("no free port left in the port pool");
+        }
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    // forge servers start once their installer is done, which the install page shows
+    let location = if server.server_type.forge().is_some() {
+        format!("/install/{}", server.id)
+    } else {
+        "/dash".to_string()
+    };
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", location))
+        .body("success")
+}
+
+/// asks mojang for new versions instead of waiting for the cached list to expire
+#[post("/versions/refresh")]
+async fn refresh_versions(req: HttpRequest, state: Data<WebState>) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("version refresh login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+
+    if let Err(err) = VersionCatalog::refresh().await {
+        return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).body(err);
+    }
 
     HttpResponse::SeeOther()
-        .insert_header(("Location", "/dash"))
+        .insert_header(("Location", "/new"))
         .body("success")
 }
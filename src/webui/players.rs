@@ -0,0 +1,85 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder,
+    http::StatusCode,
+    post,
+    web::{self, Data},
+};
+use serde::Deserialize;
+
+use crate::{
+    players::is_valid_username,
+    webui::{auth::Info, state::WebState},
+};
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerAction {
+    Kick,
+    Ban,
+    Op,
+    Deop,
+}
+
+impl PlayerAction {
+    pub fn command(&self, username: &str) -> String {
+        match self {
+            PlayerAction::Kick => format!("kick {}", username),
+            PlayerAction::Ban => format!("ban {}", username),
+            PlayerAction::Op => format!("op {}", username),
+            PlayerAction::Deop => format!("deop {}", username),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PlayerActionForm {
+    pub username: String,
+    pub action: PlayerAction,
+}
+
+#[post("/player/{id}")]
+async fn player_action(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<PlayerActionForm>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("player action login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if !is_valid_username(&form.username) {
+        return HttpResponse::BadRequest().body("invalid username");
+    }
+
+    state
+        .runner_handle
+        .issue_command(server.id, form.action.command(&form.username));
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/server/{}", server.id)))
+        .body("success")
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header},
+    web::Data,
+};
+
+use crate::{
+    backups::{BACKUP_DURATIONS, BackupList},
+    configuration::Server,
+    metrics::{DOWNLOAD_DURATIONS, DurationStats},
+    server_runner::ServerStatus,
+    webui::state::WebState,
+};
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// accumulates prometheus text exposition format output
+struct Exposition {
+    body: String,
+}
+
+impl Exposition {
+    fn new() -> Self {
+        Self {
+            body: String::new(),
+        }
+    }
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.body, "# HELP {} {}", name, help);
+        let _ = writeln!(self.body, "# TYPE {} {}", name, kind);
+    }
+    fn server_sample(&mut self, name: &str, server: &Server, value: f64) {
+        let _ = writeln!(
+            self.body,
+            "{}{{server_id=\"{}\",server_name=\"{}\"}} {}",
+            name,
+            server.id,
+            escape_label(&server.name),
+            value
+        );
+    }
+    fn sample(&mut self, name: &str, value: f64) {
+        let _ = writeln!(self.body, "{} {}", name, value);
+    }
+    /// writes one gauge per server, skipping servers the getter has no value for
+    fn server_gauge(
+        &mut self,
+        name: &str,
+        help: &str,
+        servers: &[(Server, Option<ServerStatus>)],
+        getter: impl Fn(&Server, Option<&ServerStatus>) -> Option<f64>,
+    ) {
+        self.header(name, "gauge", help);
+        for (server, status) in servers {
+            if let Some(value) = getter(server, status.as_ref()) {
+                self.server_sample(name, server, value);
+            }
+        }
+    }
+    fn summary(&mut self, name: &str, help: &str, stats: DurationStats) {
+        self.header(name, "summary", help);
+        self.sample(&format!("{}_sum", name), stats.sum_secs);
+        self.sample(&format!("{}_count", name), stats.count as f64);
+    }
+}
+
+fn authorized(req: &HttpRequest, token: &str) -> bool {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "));
+    let query = req
+        .query_string()
+        .split('&')
+        .find_map(|x| x.strip_prefix("token="));
+    bearer == Some(token) || query == Some(token)
+}
+
+#[get("/metrics")]
+async fn prometheus_metrics(req: HttpRequest, state: Data<WebState>) -> impl Responder {
+    if let Some(token) = state.config.get_metrics_token().await
+        && !authorized(&req, &token)
+    {
+        return HttpResponse::build(StatusCode::UNAUTHORIZED).body("invalid metrics token");
+    }
+
+    let statuses = state.runner_handle.get_statuses().await;
+    let servers: Vec<(Server, Option<ServerStatus>)> = state
+        .config
+        .get_servers()
+        .await
+        .into_iter()
+        .map(|server| {
+            let status = statuses.iter().find(|x| x.id == server.id).cloned();
+            (server, status)
+        })
+        .collect();
+
+    let mut out = Exposition::new();
+    out.server_gauge(
+        "mcmanage_server_enabled",
+        "Whether the server is enabled in the configuration.",
+        &servers,
+        |server, _| Some(server.enabled as u8 as f64),
+    );
+    out.server_gauge(
+        "mcmanage_server_up",
+        "Whether the server process is running.",
+        &servers,
+        |_, status| Some(status.is_some_and(|x| x.running) as u8 as f64),
+    );
+    out.server_gauge(
+        "mcmanage_server_uptime_seconds",
+        "Seconds since the server process was started.",
+        &servers,
+        |_, status| status.filter(|x| x.running).map(|x| x.uptime_secs as f64),
+    );
+    out.header(
+        "mcmanage_server_restarts_total",
+        "counter",
+        "Times the server was started again since mcmanage launched.",
+    );
+    for (server, status) in &servers {
+        let restarts = status.as_ref().map(|x| x.restarts).unwrap_or(0);
+        out.server_sample("mcmanage_server_restarts_total", server, restarts as f64);
+    }
+    out.server_gauge(
+        "mcmanage_server_cpu_percent",
+        "Cpu usage of the server process in percent of one core.",
+        &servers,
+        |_, status| status.and_then(|x| x.usage.as_ref()).map(|x| x.cpu_percent),
+    );
+    out.server_gauge(
+        "mcmanage_server_memory_bytes",
+        "Resident memory of the server process.",
+        &servers,
+        |_, status| {
+            status
+                .and_then(|x| x.usage.as_ref())
+                .map(|x| x.rss_mb * 1024.0 * 1024.0)
+        },
+    );
+    out.server_gauge(
+        "mcmanage_server_threads",
+        "Threads of the server process.",
+        &servers,
+        |_, status| {
+            status
+                .and_then(|x| x.usage.as_ref())
+                .map(|x| x.threads as f64)
+        },
+    );
+    out.server_gauge(
+        "mcmanage_server_disk_bytes",
+        "Disk space used by the server's game directory.",
+        &servers,
+        |_, status| {
+            status
+                .and_then(|x| x.usage.as_ref())
+                .map(|x| x.disk_mb * 1024.0 * 1024.0)
+        },
+    );
+    out.server_gauge(
+        "mcmanage_server_online_players",
+        "Players currently online.",
+        &servers,
+        |_, status| status.map(|x| x.online_players as f64),
+    );
+    out.server_gauge(
+        "mcmanage_server_tps",
+        "Ticks per second over the last minute where the server reports it.",
+        &servers,
+        |_, status| status.and_then(|x| x.console.tps),
+    );
+    out.header(
+        "mcmanage_server_overloaded_warnings_total",
+        "counter",
+        "Can't keep up warnings logged by the current server process.",
+    );
+    for (server, status) in &servers {
+        if let Some(status) = status {
+            out.server_sample(
+                "mcmanage_server_overloaded_warnings_total",
+                server,
+                status.console.overloaded_warnings as f64,
+            );
+        }
+    }
+    let downloads = *DOWNLOAD_DURATIONS.lock().unwrap();
+    out.summary(
+        "mcmanage_download_duration_seconds",
+        "Time spent downloading server jars.",
+        downloads,
+    );
+    let backups = *BACKUP_DURATIONS.lock().unwrap();
+    out.summary(
+        "mcmanage_backup_duration_seconds",
+        "Time spent writing backup archives.",
+        backups,
+    );
+    let mut last_backups = HashMap::new();
+    for (server, _) in &servers {
+        let Ok(list) = BackupList::load(server.id).await else {
+            continue;
+        };
+        if let Some(last) = list.backups.iter().map(|x| x.created).max() {
+            last_backups.insert(server.id, last);
+        }
+    }
+    out.server_gauge(
+        "mcmanage_last_backup_timestamp_seconds",
+        "Unix time of the server's newest backup.",
+        &servers,
+        |server, _| last_backups.get(&server.id).map(|x| *x as f64),
+    );
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type("text/plain; version=0.0.4")
+        .body(out.body)
+}
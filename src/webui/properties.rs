@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tera::{Context, Tera};
+
+use crate::{
+    properties::{PropertyField, ServerProperties},
+    webui::{auth::Info, state::WebState},
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "properties.html",
+            include_str!("../../webui/templates/properties.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Serialize)]
+pub struct PropertiesResponse {
+    pub properties: Vec<PropertyField>,
+    pub restart_required: bool,
+}
+
+async fn restart_required(state: &WebState, server_id: usize) -> bool {
+    state
+        .runner_handle
+        .get_statuses()
+        .await
+        .iter()
+        .any(|x| x.id == server_id && x.restart_required)
+}
+
+/// validates and writes the changes, flagging the server for a restart if it is running
+async fn save(
+    state: &WebState,
+    server_id: usize,
+    changes: &HashMap<String, String>,
+) -> Result<(), String> {
+    let mut properties = ServerProperties::load(server_id).await;
+    if !properties.apply(changes)? {
+        return Ok(());
+    }
+    properties
+        .write(server_id)
+        .await
+        .map_err(|err| format!("failed to write server.properties: {}", err))?;
+    state.runner_handle.require_restart(server_id);
+    Ok(())
+}
+
+#[get("/properties/{id}")]
+async fn properties_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("properties login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let properties = ServerProperties::load(server.id).await;
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("fields", &properties.fields());
+    context.insert(
+        "restart_required",
+        &restart_required(&state, server.id).await,
+    );
+    let body = TEMPLATES
+        .render("properties.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/properties/{id}")]
+async fn save_properties(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<HashMap<String, String>>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("properties login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    if let Err(err) = save(&state, server.id, &form).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/properties/{}", server.id)))
+        .body("success")
+}
+
+#[get("/api/servers/{id}/properties")]
+async fn get_properties_api(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+    let Some(_user) = user else {
+        return HttpResponse::Unauthorized().body("not logged in");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let properties = ServerProperties::load(server.id).await;
+    HttpResponse::Ok().json(PropertiesResponse {
+        properties: properties.fields(),
+        restart_required: restart_required(&state, server.id).await,
+    })
+}
+
+/// takes a json object of keys to new values, keys that are left out are untouched
+#[post("/api/servers/{id}/properties")]
+async fn set_properties_api(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Json(changes): web::Json<HashMap<String, String>>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+    let Some(_user) = user else {
+        return HttpResponse::Unauthorized().body("not logged in");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    if let Err(err) = save(&state, server.id, &changes).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    let properties = ServerProperties::load(server.id).await;
+    HttpResponse::Ok().json(PropertiesResponse {
+        properties: properties.fields(),
+        restart_required: restart_required(&state, server.id).await,
+    })
+}
@@ -1,12 +1,31 @@
 use actix_web::{HttpRequest, HttpResponse, Responder, get, web::Data};
 
 use crate::webui::{
+    access::{access_page, change_access, change_access_api, get_access_api},
     auth::{Info, login, login_page, signup, signup_page},
+    backups::{
+        backup_now, backups_page, browse_backup_page, clone_backup_now, download_backup,
+        download_backup_file, remove_backup, restore_backup_now, set_backup_settings,
+        verify_backup_now,
+    },
     commands::{command_dashboard, command_endpoint},
     dash::dash,
-    new::{create_new_server, new_server},
+    files::{change_file, download_file, files_page, save_file, upload_files},
+    import::{import_modrinth_pack, import_page, import_progress, upload_pack},
+    install::{install_page, rerun_install},
+    mods::{check_mod_updates, install_mod, mods_page, remove_mod, rollback_mods, update_mods},
+    new::{create_new_server, new_server, refresh_versions},
+    players::player_action,
+    prometheus::prometheus_metrics,
+    properties::{get_properties_api, properties_page, save_properties, set_properties_api},
+    settings::{set_domain, set_sleep, update_build},
     state::WebState,
+    targets::{change_target, create_target, targets_page},
+    tasks::{change_task, create_task, edit_task, tasks_page},
     toggle_enabled::{set_disabled, set_enabled},
+    upgrade::{upgrade_page, upgrade_version},
+    webhooks::{change_webhook, create_webhook, webhooks_page},
+    worlds::{change_world, download_world, reset_current_world, upload_world, worlds_page},
 };
 
 #[get("/")]
@@ -46,9 +65,68 @@ pub fn get_api_routes() -> actix_web::Scope {
         .service(dash)
         .service(new_server)
         .service(create_new_server)
+        .service(refresh_versions)
         .service(home_redirector)
         .service(set_disabled)
         .service(set_enabled)
         .service(command_dashboard)
         .service(command_endpoint)
+        .service(player_action)
+        .service(prometheus_metrics)
+        .service(tasks_page)
+        .service(create_task)
+        .service(edit_task)
+        .service(change_task)
+        .service(webhooks_page)
+        .service(create_webhook)
+        .service(change_webhook)
+        .service(targets_page)
+        .service(create_target)
+        .service(change_target)
+        .service(set_sleep)
+        .service(set_domain)
+        .service(update_build)
+        .service(properties_page)
+        .service(save_properties)
+        .service(get_properties_api)
+        .service(set_properties_api)
+        .service(access_page)
+        .service(change_access)
+        .service(get_access_api)
+        .service(change_access_api)
+        .service(worlds_page)
+        .service(download_world)
+        .service(upload_world)
+        .service(reset_current_world)
+        .service(backups_page)
+        .service(set_backup_settings)
+        .service(backup_now)
+        .service(remove_backup)
+        .service(browse_backup_page)
+        .service(download_backup_file)
+        .service(download_backup)
+        .service(verify_backup_now)
+        .service(restore_backup_now)
+        .service(clone_backup_now)
+        .service(files_page)
+        .service(download_file)
+        .service(save_file)
+        .service(upload_files)
+        .service(change_file)
+        .service(install_page)
+        .service(rerun_install)
+        .service(import_page)
+        .service(upload_pack)
+        .service(import_modrinth_pack)
+        .service(import_progress)
+        .service(mods_page)
+        .service(install_mod)
+        .service(remove_mod)
+        .service(check_mod_updates)
+        .service(update_mods)
+        .service(rollback_mods)
+        .service(upgrade_page)
+        .service(upgrade_version)
+        // after the fixed world routes so it doesn't swallow them
+        .service(change_world)
 }
@@ -0,0 +1,181 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder,
+    http::StatusCode,
+    post,
+    web::{self, Data},
+};
+use serde::Deserialize;
+
+use crate::{
+    loaders::update_to_latest_build,
+    webui::{auth::Info, state::WebState},
+};
+
+#[derive(Deserialize)]
+pub struct SleepSettings {
+    /// minutes without players before sleeping, empty to never sleep
+    pub minutes: String,
+}
+
+#[post("/sleep/{id}")]
+async fn set_sleep(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<SleepSettings>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("sleep settings login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let minutes = match form.minutes.trim() {
+        "" => None,
+        minutes => match minutes.parse::<u64>() {
+            Ok(minutes) if minutes > 0 => Some(minutes),
+            _ => return HttpResponse::BadRequest().body("minutes must be a positive number"),
+        },
+    };
+
+    state.config.set_server_sleep(server.id, minutes).await;
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/server/{}", server.id)))
+        .body("success")
+}
+
+#[derive(Deserialize)]
+pub struct DomainSettings {
+    /// hostname players use to reach this server through the proxy, empty to clear
+    pub domain: String,
+}
+
+#[post("/domain/{id}")]
+async fn set_domain(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<DomainSettings>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("domain settings login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let domain = form.domain.trim().trim_end_matches('.').to_lowercase();
+    let domain = if domain.is_empty() {
+        None
+    } else if domain
+        .chars()
+        .all(|x| x.is_ascii_alphanumeric() || x == '.' || x == '-')
+    {
+        Some(domain)
+    } else {
+        return HttpResponse::BadRequest().body("invalid domain");
+    };
+    if let Some(domain) = &domain
+        && state
+            .config
+            .get_servers()
+            .await
+            .iter()
+            .any(|x| x.id != server.id && x.domain.as_ref() == Some(domain))
+    {
+        return HttpResponse::BadRequest().body("domain already used by another server");
+    }
+
+    state.config.set_server_domain(server.id, domain).await;
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/server/{}", server.id)))
+        .body("success")
+}
+
+#[post("/build/{id}")]
+async fn update_build(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("build update login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    match update_to_latest_build(&state.config, &state.runner_handle, &server).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Ok().body("already on the newest build"),
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/server/{}", server.id)))
+        .body("success")
+}
@@ -1,15 +1,26 @@
+use std::path::PathBuf;
+
 use serde::Deserialize;
+use tokio::fs;
 
 use crate::{
-    configuration::{ConfigurationManager, Server},
+    configuration::{ConfigurationManager, Server, ServerType},
+    loaders::{
+        ensure_available, fabric,
+        forge::{self, ForgeKind, run_installer},
+        paper, purpur,
+    },
+    mods::mrpack::{import, read_index},
     server_runner::ServerRunnerHandle,
-    versions::PackagesList,
+    sleeper::SleepHandle,
+    versions::VersionCatalog,
 };
 
 #[derive(Debug, Clone)]
 pub struct WebState {
     pub config: ConfigurationManager,
     pub runner_handle: ServerRunnerHandle,
+    pub sleeper: SleepHandle,
 }
 
 #[derive(Deserialize)]
@@ -19,10 +30,33 @@ pub enum Checked {
     Off,
 }
 
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum NewServerType {
+    #[default]
+    Vanilla,
+    Fabric,
+    Paper,
+    Purpur,
+    Forge,
+    NeoForge,
+}
+
 #[derive(Deserialize)]
 pub struct NewServer {
     pub servername: String,
     pub version: String,
+    #[serde(default)]
+    pub server_type: NewServerType,
+    /// empty picks the newest stable one
+    #[serde(default)]
+    pub loader_version: String,
+    /// paper or purpur build, empty picks the newest one
+    #[serde(default)]
+    pub build: String,
+    /// forge or neoforge version, empty picks the recommended one
+    #[serde(default)]
+    pub forge_version: String,
     pub eula: Option<Checked>,
 }
 
@@ -36,22 +70,106 @@ impl From<Checked> for bool {
 }
 
 impl WebState {
-    pub async fn create_new_server(&self, new_server: NewServer) -> Server {
+    /// none if there is no free port left for the server, an error if the server type
+    /// can't be set up for the version
+    pub async fn create_new_server(&self, new_server: NewServer) -> Result<Option<Server>, String> {
         let eula: bool = new_server.eula.unwrap_or(Checked::Off).into();
         print!("creating new server with eula {}", eula);
-        self.runner_handle.stop_all();
-        self.config.disable_all().await;
-        let packages = PackagesList::new().await;
+        let packages = VersionCatalog::packages().await?;
         let selected = packages
             .get_version(&new_server.version)
-            .expect("invalid server version provided");
-        let info = selected.get_version_info().await;
-        let server = self
+            .ok_or(format!("unknown minecraft version {}", new_server.version))?;
+        let info = VersionCatalog::version_info(&selected).await?;
+        let loader_version = Some(new_server.loader_version.trim()).filter(|x| !x.is_empty());
+        let forge_version = Some(new_server.forge_version.trim()).filter(|x| !x.is_empty());
+        let build = match new_server.build.trim() {
+            "" => None,
+            build => Some(build.parse().map_err(|_| "invalid build")?),
+        };
+        let server_type = match new_server.server_type {
+            NewServerType::Vanilla => ServerType::Vanilla,
+            NewServerType::Fabric => fabric::resolve(&info.id, loader_version).await?,
+            NewServerType::Paper => paper::resolve(&info.id, build).await?,
+            NewServerType::Purpur => purpur::resolve(&info.id, build).await?,
+            NewServerType::Forge => {
+                forge::resolve(ForgeKind::Forge, &info.id, forge_version).await?
+            }
+            NewServerType::NeoForge => {
+                forge::resolve(ForgeKind::NeoForge, &info.id, forge_version).await?
+            }
+        };
+        ensure_available(&server_type, &info.id).await?;
+        let Some(server) = self
             .config
-            .create_new_server(new_server.servername, info, eula)
-            .await;
-        self.config.start_all(self.runner_handle.clone()).await;
-        server
+            .create_new_server(new_server.servername, info, server_type, eula)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if server.server_type.forge().is_some() {
+            self.install_in_background(server.clone());
+        } else {
+            self.enable_server(server.id).await;
+        }
+        Ok(Some(server))
+    }
+    /// creates a server for a modrinth pack and puts the pack onto it in the background,
+    /// the server is enabled once that's done. none if there is no free port left
+    pub async fn create_pack_server(
+        &self,
+        name: String,
+        eula: bool,
+        archive: PathBuf,
+    ) -> Result<Option<Server>, String> {
+        let index = read_index(&archive).await?;
+        let mc_version = index.mc_version()?;
+        let server_type = index.server_type().await?;
+        let packages = VersionCatalog::packages().await?;
+        let selected = packages
+            .get_version(mc_version)
+            .ok_or(format!("unknown minecraft version {}", mc_version))?;
+        let info = VersionCatalog::version_info(&selected).await?;
+        let Some(server) = self
+            .config
+            .create_new_server(name, info, server_type, eula)
+            .await?
+        else {
+            return Ok(None);
+        };
+        // so it isn't started with half of the pack
+        self.config.set_server_enabled(server.id, false).await;
+        let pack = PathBuf::from(format!("./servers/{}/pack.mrpack", server.id));
+        fs::create_dir_all(format!("./servers/{}/game", server.id))
+            .await
+            .map_err(|err| err.to_string())?;
+        fs::rename(&archive, &pack)
+            .await
+            .map_err(|err| err.to_string())?;
+        let state = self.clone();
+        let created = server.clone();
+        tokio::spawn(async move {
+            let mut result = import(&server, &index, &pack).await;
+            let _ = fs::remove_file(&pack).await;
+            if result.is_ok() && server.server_type.forge().is_some() {
+                result = run_installer(&server).await;
+            }
+            match result {
+                Ok(()) => state.enable_server(server.id).await,
+                Err(err) => println!("importing a pack onto server {} failed: {}", server.id, err),
+            }
+        });
+        Ok(Some(created))
+    }
+    /// runs the forge installer and enables the server once it's done, the install
+    /// page follows along
+    pub fn install_in_background(&self, server: Server) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            match run_installer(&server).await {
+                Ok(()) => state.enable_server(server.id).await,
+                Err(err) => println!("installing server {} failed: {}", server.id, err),
+            }
+        });
     }
     pub async fn disable_server(&self, server_id: usize) {
         self.runner_handle.stop_specific(server_id);
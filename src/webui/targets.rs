@@ -0,0 +1,314 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+
+use crate::{
+    targets::{
+        BackupTarget, TargetKind,
+        local::LocalTarget,
+        s3::S3Target,
+        sftp::{SftpTarget, is_valid_host_key, is_valid_sftp_login, is_valid_sftp_path},
+        test_target,
+    },
+    webui::{
+        auth::Info,
+        state::{Checked, WebState},
+    },
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "targets.html",
+            include_str!("../../webui/templates/targets.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum NewTargetKind {
+    Local,
+    S3,
+    Sftp,
+}
+
+/// one form for every kind, the fields that don't apply to the chosen kind are ignored
+#[derive(Deserialize)]
+pub struct NewTarget {
+    pub name: String,
+    pub kind: NewTargetKind,
+    /// a server id, or empty for every server
+    pub server: String,
+    pub keep_last: usize,
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub endpoint: String,
+    #[serde(default)]
+    pub region: String,
+    #[serde(default)]
+    pub bucket: String,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub access_key: String,
+    #[serde(default)]
+    pub secret_key: String,
+    pub path_style: Option<Checked>,
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub port: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub identity_file: String,
+    #[serde(default)]
+    pub host_key: String,
+}
+
+impl NewTarget {
+    pub fn into_target(self) -> Result<BackupTarget, String> {
+        let server_id = match self.server.trim() {
+            "" => None,
+            id => Some(id.parse().map_err(|_| "invalid server")?),
+        };
+        let required = |value: &str, what: &str| {
+            if value.trim().is_empty() {
+                Err(format!("{} is required", what))
+            } else {
+                Ok(value.trim().to_string())
+            }
+        };
+        let kind = match self.kind {
+            NewTargetKind::Local => TargetKind::Local(LocalTarget {
+                path: required(&self.path, "path")?,
+            }),
+            NewTargetKind::S3 => {
+                let endpoint = required(&self.endpoint, "endpoint")?;
+                if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+                    return Err("endpoint must be http or https".to_string());
+                }
+                TargetKind::S3(S3Target {
+                    endpoint,
+                    region: required(&self.region, "region")?,
+                    bucket: required(&self.bucket, "bucket")?,
+                    prefix: self.prefix.trim().trim_matches('/').to_string(),
+                    access_key: required(&self.access_key, "access key")?,
+                    secret_key: required(&self.secret_key, "secret key")?,
+                    path_style: self.path_style.is_some_and(bool::from),
+                })
+            }
+            NewTargetKind::Sftp => {
+                let path = required(&self.path, "path")?;
+                if !is_valid_sftp_path(&path) {
+                    return Err("path can't contain quotes".to_string());
+                }
+                let host = required(&self.host, "host")?;
+                let username = required(&self.username, "username")?;
+                if !is_valid_sftp_login(&host) || !is_valid_sftp_login(&username) {
+                    return Err(
+                        "host and username can't start with - or contain spaces".to_string()
+                    );
+                }
+                let host_key = Some(self.host_key.trim().to_string()).filter(|x| !x.is_empty());
+                if host_key.as_deref().is_some_and(|x| !is_valid_host_key(x)) {
+                    return Err("host key should look like ssh-ed25519 AAAA...".to_string());
+                }
+                TargetKind::Sftp(SftpTarget {
+                    host,
+                    port: match self.port.trim() {
+                        "" => 22,
+                        port => port.parse().map_err(|_| "invalid port")?,
+                    },
+                    username,
+                    identity_file: Some(self.identity_file.trim().to_string())
+                        .filter(|x| !x.is_empty()),
+                    path,
+                    host_key,
+                })
+            }
+        };
+        Ok(BackupTarget {
+            id: 0,
+            name: self.name,
+            kind,
+            server_id,
+            keep_last: self.keep_last,
+            enabled: true,
+        })
+    }
+}
+
+#[get("/targets")]
+async fn targets_page(req: HttpRequest, state: Data<WebState>) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("targets login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+
+    let targets: Vec<(BackupTarget, String)> = state
+        .config
+        .get_backup_targets()
+        .await
+        .into_iter()
+        .map(|x| {
+            let description = x.kind.describe();
+            (x, description)
+        })
+        .collect();
+
+    let mut context = Context::new();
+    context.insert("targets", &targets);
+    context.insert("servers", &state.config.get_servers().await);
+    let body = TEMPLATES
+        .render("targets.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/targets")]
+async fn create_target(
+    req: HttpRequest,
+    state: Data<WebState>,
+    web::Form(form): web::Form<NewTarget>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("targets login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+
+    let target = match form.into_target() {
+        Ok(target) => target,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    state.config.add_backup_target(target).await;
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", "/targets"))
+        .body("success")
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetChange {
+    Enable,
+    Disable,
+    Delete,
+    /// upload, list and delete a small file right away
+    Test,
+}
+
+#[post("/targets/{id}/{change}")]
+async fn change_target(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, TargetChange)>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("targets login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (target_id, change) = path.into_inner();
+
+    match change {
+        TargetChange::Enable => {
+            state
+                .config
+                .set_backup_target_enabled(target_id, true)
+                .await
+        }
+        TargetChange::Disable => {
+            state
+                .config
+                .set_backup_target_enabled(target_id, false)
+                .await
+        }
+        TargetChange::Delete => state.config.remove_backup_target(target_id).await,
+        TargetChange::Test => {
+            let Some(target) = state.config.get_backup_target(target_id).await else {
+                return HttpResponse::build(StatusCode::NOT_FOUND).body("target not found");
+            };
+            if let Err(err) = test_target(&target).await {
+                return HttpResponse::BadRequest().body(format!("test failed: {}", err));
+            }
+        }
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", "/targets"))
+        .body("success")
+}
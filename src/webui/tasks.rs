@@ -0,0 +1,327 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+
+use crate::{
+    scheduler::{Schedule, ScheduledTask, TaskAction, TaskHistory},
+    webui::{auth::Info, state::WebState},
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "tasks.html",
+            include_str!("../../webui/templates/tasks.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    Restart,
+    Command,
+    Broadcast,
+    Start,
+    Stop,
+    Backup,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleKind {
+    Cron,
+    Interval,
+}
+
+#[derive(Deserialize)]
+pub struct NewTask {
+    pub name: String,
+    pub action: ActionKind,
+    /// the command, message or countdown seconds depending on the action
+    pub argument: String,
+    pub schedule_kind: ScheduleKind,
+    /// the cron expression or interval minutes depending on the schedule kind
+    pub schedule: String,
+}
+
+impl NewTask {
+    pub fn into_task(self, server_id: usize) -> Result<ScheduledTask, String> {
+        let schedule = match self.schedule_kind {
+            ScheduleKind::Cron => Schedule::Cron {
+                expression: self.schedule.trim().to_string(),
+            },
+            ScheduleKind::Interval => Schedule::Interval {
+                minutes: self
+                    .schedule
+                    .trim()
+                    .parse()
+                    .map_err(|_| "interval must be a whole number of minutes")?,
+            },
+        };
+        schedule.validate()?;
+        let argument = self.argument.trim().to_string();
+        if argument.contains('\n') {
+            return Err("argument must be a single line".to_string());
+        }
+        let action = match self.action {
+            ActionKind::Restart => TaskAction::Restart {
+                countdown_secs: if argument.is_empty() {
+                    0
+                } else {
+                    argument
+                        .parse()
+                        .map_err(|_| "countdown must be a whole number of seconds")?
+                },
+            },
+            ActionKind::Command if argument.is_empty() => {
+                return Err("command can't be empty".to_string());
+            }
+            ActionKind::Command => TaskAction::Command { command: argument },
+            ActionKind::Broadcast if argument.is_empty() => {
+                return Err("message can't be empty".to_string());
+            }
+            ActionKind::Broadcast => TaskAction::Broadcast { message: argument },
+            ActionKind::Start => TaskAction::Start,
+            ActionKind::Stop => TaskAction::Stop,
+            ActionKind::Backup => TaskAction::Backup,
+        };
+        Ok(ScheduledTask {
+            id: 0,
+            server_id,
+            name: self.name,
+            schedule,
+            action,
+            enabled: true,
+        })
+    }
+}
+
+#[get("/tasks/{id}")]
+async fn tasks_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("tasks login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let tasks: Vec<(ScheduledTask, String)> = state
+        .config
+        .get_tasks()
+        .await
+        .into_iter()
+        .filter(|x| x.server_id == server.id)
+        .map(|x| {
+            let description = x.action.describe();
+            (x, description)
+        })
+        .collect();
+    let mut context = Context::new();
+    match TaskHistory::load().await {
+        Ok(history) => context.insert("history", &history.for_server(server.id)),
+        Err(err) => {
+            context.insert("history", &Vec::<()>::new());
+            context.insert("history_error", &err);
+        }
+    }
+    context.insert("server", &server);
+    context.insert("tasks", &tasks);
+    let body = TEMPLATES
+        .render("tasks.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/tasks/{id}")]
+async fn create_task(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<NewTask>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("tasks login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let task = match form.into_task(server.id) {
+        Ok(task) => task,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    state.config.add_task(task).await;
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/tasks/{}", server.id)))
+        .body("success")
+}
+
+#[post("/tasks/{id}/{task_id}")]
+async fn edit_task(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, usize)>,
+    web::Form(form): web::Form<NewTask>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("tasks login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, task_id) = path.into_inner();
+    let tasks = state.config.get_tasks().await;
+    let Some(existing) = tasks
+        .iter()
+        .find(|x| x.id == task_id && x.server_id == server_id)
+    else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("task not found");
+    };
+
+    let mut task = match form.into_task(server_id) {
+        Ok(task) => task,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    task.id = existing.id;
+    state.config.update_task(task).await;
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/tasks/{}", server_id)))
+        .body("success")
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskChange {
+    Enable,
+    Disable,
+    Delete,
+}
+
+#[post("/tasks/{id}/{task_id}/{change}")]
+async fn change_task(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, usize, TaskChange)>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("tasks login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, task_id, change) = path.into_inner();
+    let tasks = state.config.get_tasks().await;
+    if !tasks
+        .iter()
+        .any(|x| x.id == task_id && x.server_id == server_id)
+    {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("task not found");
+    }
+
+    match change {
+        TaskChange::Enable => state.config.set_task_enabled(task_id, true).await,
+        TaskChange::Disable => state.config.set_task_enabled(task_id, false).await,
+        TaskChange::Delete => state.config.remove_task(task_id).await,
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/tasks/{}", server_id)))
+        .body("success")
+}
@@ -0,0 +1,196 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    web::{self, Data},
+};
+use chrono::DateTime;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tera::{Context, Tera};
+
+use crate::{
+    loaders::forge::run_installer,
+    mods::ModLock,
+    upgrades::upgrade_server,
+    versions::{ReleaseType, VersionCatalog},
+    webui::{
+        auth::Info,
+        state::{Checked, WebState},
+    },
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "upgrade.html",
+            include_str!("../../webui/templates/upgrade.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+/// a version to offer and whether moving to it would be a downgrade
+#[derive(Serialize)]
+struct VersionChoice {
+    id: String,
+    release_type: ReleaseType,
+    older: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UpgradeForm {
+    version: String,
+    force_upgrade: Option<Checked>,
+    /// has to be checked to move to an older version
+    downgrade: Option<Checked>,
+}
+
+#[get("/upgrade/{id}")]
+async fn upgrade_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("upgrade page login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    let packages = match VersionCatalog::packages().await {
+        Ok(packages) => packages,
+        Err(err) => return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).body(err),
+    };
+    let released = |id: &str| {
+        packages
+            .get_version(id)
+            .and_then(|x| DateTime::parse_from_rfc3339(&x.release_time).ok())
+    };
+    let current = released(&server.mc_version_id);
+    let versions: Vec<VersionChoice> = packages
+        .versions
+        .iter()
+        .filter(|x| x.id != server.mc_version_id)
+        .map(|x| VersionChoice {
+            id: x.id.clone(),
+            release_type: x.release_type.clone(),
+            older: current.is_some_and(|current| released(&x.id).is_none_or(|x| x < current)),
+        })
+        .collect();
+
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("versions", &versions);
+    context.insert("latest", &packages.latest.release);
+    match ModLock::load(server.id).await {
+        Ok(lock) => context.insert("mods_installed", &lock.projects.len()),
+        Err(err) => {
+            context.insert("mods_installed", &0);
+            context.insert("mods_error", &err);
+        }
+    }
+    let body = TEMPLATES
+        .render("upgrade.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/upgrade/{id}")]
+async fn upgrade_version(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<UpgradeForm>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("upgrade login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if state.runner_handle.is_running(server.id).await {
+        return HttpResponse::build(StatusCode::CONFLICT).body("stop the server first");
+    }
+    let force_upgrade = form.force_upgrade.unwrap_or(Checked::Off).into();
+    let allow_downgrade = form.downgrade.unwrap_or(Checked::Off).into();
+    let upgraded = match upgrade_server(
+        &state.config,
+        &state.runner_handle,
+        &server,
+        form.version.trim(),
+        force_upgrade,
+        allow_downgrade,
+    )
+    .await
+    {
+        Ok(upgraded) => upgraded,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    println!(
+        "moved server {} from {} to {}",
+        server.id, server.mc_version_id, upgraded.mc_version_id
+    );
+    // the libraries for the new version come from its installer
+    if upgraded.server_type.forge().is_some() {
+        tokio::spawn(async move {
+            if let Err(err) = run_installer(&upgraded).await {
+                println!("installing server {} failed: {}", upgraded.id, err);
+            }
+        });
+        return HttpResponse::SeeOther()
+            .insert_header(("Location", format!("/install/{}", server.id)))
+            .body("success");
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/server/{}", server.id)))
+        .body("success")
+}
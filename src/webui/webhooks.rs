@@ -0,0 +1,260 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{StatusCode, header::ContentType},
+    post,
+    rt::spawn,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+
+use crate::{
+    configuration::Server,
+    events::{GameEvent, ServerEvent},
+    webhooks::{DeliveryLog, Webhook, WebhookEvent, WebhookFormat, deliver, payload},
+    webui::{
+        auth::Info,
+        state::{Checked, WebState},
+    },
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "webhooks.html",
+            include_str!("../../webui/templates/webhooks.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize)]
+pub struct NewWebhook {
+    pub name: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    /// a server id, or empty for every server
+    pub server: String,
+    pub secret: String,
+    pub started: Option<Checked>,
+    pub stopping: Option<Checked>,
+    pub crashed: Option<Checked>,
+    pub player_joined: Option<Checked>,
+    pub player_left: Option<Checked>,
+    pub chat: Option<Checked>,
+    pub death: Option<Checked>,
+    pub advancement: Option<Checked>,
+    pub backup_finished: Option<Checked>,
+    pub backup_failed: Option<Checked>,
+}
+
+impl NewWebhook {
+    pub fn into_webhook(self) -> Result<Webhook, String> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            return Err("webhook url must be http or https".to_string());
+        }
+        let server_id = match self.server.trim() {
+            "" => None,
+            id => Some(id.parse().map_err(|_| "invalid server")?),
+        };
+        let selected = [
+            self.started,
+            self.stopping,
+            self.crashed,
+            self.player_joined,
+            self.player_left,
+            self.chat,
+            self.death,
+            self.advancement,
+            self.backup_finished,
+            self.backup_failed,
+        ];
+        let events: Vec<WebhookEvent> = WebhookEvent::ALL
+            .into_iter()
+            .zip(selected)
+            .filter_map(|(event, checked)| checked.is_some_and(bool::from).then_some(event))
+            .collect();
+        if events.is_empty() {
+            return Err("pick at least one event".to_string());
+        }
+        Ok(Webhook {
+            id: 0,
+            name: self.name,
+            url: self.url,
+            format: self.format,
+            server_id,
+            events,
+            secret: Some(self.secret).filter(|x| !x.is_empty()),
+            enabled: true,
+        })
+    }
+}
+
+#[get("/webhooks")]
+async fn webhooks_page(req: HttpRequest, state: Data<WebState>) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("webhooks login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+
+    let mut context = Context::new();
+    match DeliveryLog::load().await {
+        Ok(log) => {
+            let mut deliveries = log.deliveries;
+            deliveries.reverse();
+            context.insert("deliveries", &deliveries);
+        }
+        Err(err) => {
+            context.insert("deliveries", &Vec::<()>::new());
+            context.insert("deliveries_error", &err);
+        }
+    }
+    context.insert("webhooks", &state.config.get_webhooks().await);
+    context.insert("servers", &state.config.get_servers().await);
+    context.insert("events", &WebhookEvent::ALL);
+    let body = TEMPLATES
+        .render("webhooks.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[post("/webhooks")]
+async fn create_webhook(
+    req: HttpRequest,
+    state: Data<WebState>,
+    web::Form(form): web::Form<NewWebhook>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("webhooks login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+
+    let webhook = match form.into_webhook() {
+        Ok(webhook) => webhook,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    state.config.add_webhook(webhook).await;
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", "/webhooks"))
+        .body("success")
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookChange {
+    Enable,
+    Disable,
+    Delete,
+    /// send a sample payload right away
+    Test,
+}
+
+#[post("/webhooks/{id}/{change}")]
+async fn change_webhook(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, WebhookChange)>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("webhooks login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (webhook_id, change) = path.into_inner();
+
+    match change {
+        WebhookChange::Enable => state.config.set_webhook_enabled(webhook_id, true).await,
+        WebhookChange::Disable => state.config.set_webhook_enabled(webhook_id, false).await,
+        WebhookChange::Delete => state.config.remove_webhook(webhook_id).await,
+        WebhookChange::Test => {
+            let Some(webhook) = state.config.get_webhook(webhook_id).await else {
+                return HttpResponse::build(StatusCode::NOT_FOUND).body("webhook not found");
+            };
+            let server = match webhook.server_id {
+                Some(id) => state.config.get_server(id).await,
+                None => None,
+            }
+            .unwrap_or_else(|| Server::new("test".to_string(), "".to_string(), false));
+            let event = ServerEvent::new(
+                server.id,
+                GameEvent::Chat {
+                    username: "mcmanage".to_string(),
+                    message: "webhook test".to_string(),
+                },
+            );
+            let body = payload(webhook.format, &server, &event);
+            // retries can take a while, the outcome shows up in the delivery log
+            spawn(deliver(webhook, "test".to_string(), body));
+        }
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", "/webhooks"))
+        .body("success")
+}
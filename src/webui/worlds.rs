@@ -0,0 +1,389 @@
+use std::path::PathBuf;
+
+use actix_files::NamedFile;
+use actix_multipart::Multipart;
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get,
+    http::{
+        StatusCode,
+        header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    },
+    post,
+    web::{self, Data},
+};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Context, Tera};
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_stream::StreamExt;
+
+use crate::{
+    files::MAX_UPLOAD_BYTES,
+    webui::{auth::Info, state::WebState},
+    worlds::{
+        LEVEL_TYPES, current_world, delete_world, export_world, import_world, list_worlds,
+        pause_saving, reset_world, resume_saving, switch_world, temp_archive,
+    },
+};
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "worlds.html",
+            include_str!("../../webui/templates/worlds.html"),
+        )
+        .expect("Failed to add raw template");
+        tera
+    };
+}
+
+#[derive(Deserialize)]
+pub struct WorldName {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetWorld {
+    /// empty keeps the current seed
+    #[serde(default)]
+    pub seed: String,
+    /// empty keeps the current level type
+    #[serde(default)]
+    pub level_type: String,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum WorldChange {
+    Switch,
+    Delete,
+}
+
+#[get("/worlds/{id}")]
+async fn worlds_page(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("worlds login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let mut context = Context::new();
+    context.insert("server", &server);
+    context.insert("worlds", &list_worlds(server.id).await);
+    context.insert("running", &state.runner_handle.is_running(server.id).await);
+    context.insert("level_types", &LEVEL_TYPES);
+    let body = TEMPLATES
+        .render("worlds.html", &context)
+        .expect("failed to render");
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+/// saves the uploaded zip next to the server, returning the world name it was given
+async fn receive_upload(
+    server_id: usize,
+    mut payload: Multipart,
+) -> Result<(String, PathBuf), String> {
+    let archive = temp_archive(server_id, "upload");
+    fs::create_dir_all(format!("./servers/{}/game", server_id))
+        .await
+        .map_err(|err| err.to_string())?;
+    let result = async {
+        let mut name = String::new();
+        let mut received = false;
+        while let Some(field) = payload.next().await {
+            let mut field = field.map_err(|err| err.to_string())?;
+            match field.name() {
+                Some("name") => {
+                    let mut value = vec![];
+                    while let Some(chunk) = field.next().await {
+                        value.extend_from_slice(&chunk.map_err(|err| err.to_string())?);
+                    }
+                    name = String::from_utf8_lossy(&value).trim().to_string();
+                }
+                Some("world") => {
+                    let mut file = fs::File::create(&archive)
+                        .await
+                        .map_err(|err| err.to_string())?;
+                    let mut size: u64 = 0;
+                    while let Some(chunk) = field.next().await {
+                        let chunk = chunk.map_err(|err| err.to_string())?;
+                        size += chunk.len() as u64;
+                        if size > MAX_UPLOAD_BYTES {
+                            return Err("the world is too big to upload".to_string());
+                        }
+                        file.write_all(&chunk)
+                            .await
+                            .map_err(|err| err.to_string())?;
+                    }
+                    file.flush().await.map_err(|err| err.to_string())?;
+                    received = true;
+                }
+                _ => while field.next().await.is_some() {},
+            }
+        }
+        if !received {
+            return Err("no world uploaded".to_string());
+        }
+        Ok(name)
+    }
+    .await;
+    match result {
+        Ok(name) => Ok((name, archive)),
+        Err(err) => {
+            let _ = fs::remove_file(&archive).await;
+            Err(err)
+        }
+    }
+}
+
+#[post("/worlds/{id}/upload")]
+async fn upload_world(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    payload: Multipart,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("worlds login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let result = match receive_upload(server.id, payload).await {
+        Ok((name, archive)) => {
+            let result = import_world(server.id, &name, archive.clone()).await;
+            let _ = fs::remove_file(archive).await;
+            result
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/worlds/{}", server.id)))
+        .body("success")
+}
+
+#[get("/worlds/{id}/download/{name}")]
+async fn download_world(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, String)>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("worlds login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, name) = path.into_inner();
+    let Some(server) = state.config.get_server(server_id).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    // the world in use has to be flushed and left alone while it's copied
+    let live =
+        state.runner_handle.is_running(server.id).await && current_world(server.id).await == name;
+    if live && !pause_saving(&state.runner_handle, server.id).await {
+        resume_saving(&state.runner_handle, server.id);
+        return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+            .body("the server didn't save the world in time, try again");
+    }
+    let exported = export_world(server.id, &name).await;
+    if live {
+        resume_saving(&state.runner_handle, server.id);
+    }
+    let archive = match exported {
+        Ok(archive) => archive,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let file = NamedFile::open_async(&archive).await;
+    // the open file keeps the data around until it has been sent
+    let _ = fs::remove_file(&archive).await;
+    match file {
+        Ok(file) => file
+            .set_content_disposition(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!("{}.zip", name))],
+            })
+            .into_response(&req),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[post("/worlds/{id}/reset")]
+async fn reset_current_world(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<usize>,
+    web::Form(form): web::Form<ResetWorld>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("worlds login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let Some(server) = state.config.get_server(path.into_inner()).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+    if state.runner_handle.is_running(server.id).await {
+        return HttpResponse::build(StatusCode::CONFLICT).body("stop the server first");
+    }
+
+    let seed = Some(form.seed.trim()).filter(|x| !x.is_empty());
+    let level_type = Some(form.level_type.trim()).filter(|x| !x.is_empty());
+    if let Err(err) = reset_world(server.id, seed, level_type).await {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/worlds/{}", server.id)))
+        .body("success")
+}
+
+#[post("/worlds/{id}/{change}")]
+async fn change_world(
+    req: HttpRequest,
+    state: Data<WebState>,
+    path: web::Path<(usize, WorldChange)>,
+    web::Form(form): web::Form<WorldName>,
+) -> impl Responder {
+    let user = if let Some(cookie) = req.cookie("auth") {
+        if let Ok(user) = serde_json::from_str::<Info>(cookie.value()) {
+            match state
+                .config
+                .validate_password(user.username.clone(), user.password.clone())
+                .await
+            {
+                Ok(_) => Some(user),
+                Err(_) => None,
+            }
+        } else {
+            println!("bad cookie");
+            None
+        }
+    } else {
+        println!("no cookie");
+        None
+    };
+    let Some(_user) = user else {
+        println!("worlds login failed");
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", "/login"))
+            .body("");
+    };
+    let (server_id, change) = path.into_inner();
+    let Some(server) = state.config.get_server(server_id).await else {
+        return HttpResponse::build(StatusCode::NOT_FOUND).body("server not found");
+    };
+
+    let name = form.name.trim();
+    let result = match change {
+        WorldChange::Switch => {
+            if state.runner_handle.is_running(server.id).await {
+                return HttpResponse::build(StatusCode::CONFLICT).body("stop the server first");
+            }
+            switch_world(server.id, name).await
+        }
+        WorldChange::Delete => delete_world(server.id, name).await,
+    };
+    if let Err(err) = result {
+        return HttpResponse::BadRequest().body(err);
+    }
+
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/worlds/{}", server.id)))
+        .body("success")
+}
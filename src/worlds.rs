@@ -0,0 +1,319 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::fs;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::spawn_blocking;
+use tokio::time::timeout;
+use zip::{CompressionMethod, ZipArchive};
+
+use crate::archive::{SESSION_LOCK, copy_capped, zip_directory};
+use crate::events::GameEvent;
+use crate::metrics::directory_size;
+use crate::properties::ServerProperties;
+use crate::server_runner::ServerRunnerHandle;
+
+/// what the server calls its world when level-name isn't set
+pub const DEFAULT_LEVEL_NAME: &str = "world";
+pub const LEVEL_TYPES: [&str; 5] = [
+    "minecraft:normal",
+    "minecraft:flat",
+    "minecraft:large_biomes",
+    "minecraft:amplified",
+    "minecraft:single_biome_surface",
+];
+/// uploads that unpack to more than this are refused
+const MAX_WORLD_BYTES: u64 = 32 << 30;
+/// how long a running server gets to flush its world before a download
+const SAVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldInfo {
+    pub name: String,
+    pub current: bool,
+    /// false for a level-name the server hasn't generated yet
+    pub generated: bool,
+    pub size_bytes: u64,
+}
+
+pub fn game_dir(server_id: usize) -> PathBuf {
+    PathBuf::from(format!("./servers/{}/game", server_id))
+}
+
+pub fn is_valid_world_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-' || x == ' ')
+}
+
+pub async fn current_world(server_id: usize) -> String {
+    ServerProperties::load(server_id)
+        .await
+        .get("level-name")
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| DEFAULT_LEVEL_NAME.to_string())
+}
+
+/// every folder in the server's directory that holds a level.dat, plus the current world
+pub async fn list_worlds(server_id: usize) -> Vec<WorldInfo> {
+    let current = current_world(server_id).await;
+    let mut worlds = vec![];
+    if let Ok(mut entries) = fs::read_dir(game_dir(server_id)).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if !entry.path().join("level.dat").exists() {
+                continue;
+            }
+            worlds.push(WorldInfo {
+                current: name == current,
+                generated: true,
+                size_bytes: directory_size(&entry.path()).await,
+                name,
+            });
+        }
+    }
+    // a level-name that isn't a plain folder name isn't offered as a world
+    if !worlds.iter().any(|x| x.current) && is_valid_world_name(&current) {
+        worlds.push(WorldInfo {
+            name: current,
+            current: true,
+            generated: false,
+            size_bytes: 0,
+        });
+    }
+    worlds.sort_by(|a, b| a.name.cmp(&b.name));
+    worlds
+}
+
+/// points level-name at another world, a name that doesn't exist yet is generated on start
+pub async fn switch_world(server_id: usize, name: &str) -> Result<(), String> {
+    if !is_valid_world_name(name) {
+        return Err("invalid world name".to_string());
+    }
+    let mut properties = ServerProperties::load(server_id).await;
+    properties.set("level-name", name);
+    properties
+        .write(server_id)
+        .await
+        .map_err(|err| format!("failed to write server.properties: {}", err))
+}
+
+/// deletes the current world so the next start generates a fresh one
+pub async fn reset_world(
+    server_id: usize,
+    seed: Option<&str>,
+    level_type: Option<&str>,
+) -> Result<(), String> {
+    // level-name comes from a file anyone with access to the server can edit, and it's
+    // about to be deleted
+    let current = current_world(server_id).await;
+    if !is_valid_world_name(&current) {
+        return Err(format!(
+            "level-name {:?} isn't a plain world name, not deleting it",
+            current
+        ));
+    }
+    let mut properties = ServerProperties::load(server_id).await;
+    if let Some(level_type) = level_type {
+        if !LEVEL_TYPES.contains(&level_type) {
+            return Err(format!(
+                "level type must be one of {}",
+                LEVEL_TYPES.join(", ")
+            ));
+        }
+        properties.set("level-type", level_type);
+    }
+    if let Some(seed) = seed {
+        if seed.contains(['\n', '\r']) {
+            return Err("seed must be a single line".to_string());
+        }
+        properties.set("level-seed", seed);
+    }
+    let world = game_dir(server_id).join(&current);
+    if fs::try_exists(&world).await.unwrap_or(false) {
+        fs::remove_dir_all(&world)
+            .await
+            .map_err(|err| format!("failed to delete world: {}", err))?;
+    }
+    properties
+        .write(server_id)
+        .await
+        .map_err(|err| format!("failed to write server.properties: {}", err))
+}
+
+/// removes a world that isn't the current one
+pub async fn delete_world(server_id: usize, name: &str) -> Result<(), String> {
+    if !is_valid_world_name(name) {
+        return Err("invalid world name".to_string());
+    }
+    if current_world(server_id).await == name {
+        return Err("can't delete the current world, reset it instead".to_string());
+    }
+    let world = game_dir(server_id).join(name);
+    if !world.join("level.dat").exists() {
+        return Err("world not found".to_string());
+    }
+    fs::remove_dir_all(world)
+        .await
+        .map_err(|err| format!("failed to delete world: {}", err))
+}
+
+/// the folder inside the archive holding level.dat, closest to the root
+fn find_world_root(archive: &mut ZipArchive<File>) -> Option<PathBuf> {
+    (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok()?.enclosed_name())
+        .filter(|x| x.file_name().is_some_and(|x| x == "level.dat"))
+        .min_by_key(|x| x.components().count())
+        .map(|x| x.parent().unwrap_or(Path::new("")).to_path_buf())
+}
+
+fn unpack(archive: &Path, destination: &Path) -> Result<(), String> {
+    let file = File::open(archive).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|err| format!("not a zip file: {}", err))?;
+    let root = find_world_root(&mut archive).ok_or("the zip doesn't contain a level.dat")?;
+    let mut total: u64 = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+        // anything that would land outside the destination is skipped
+        let Some(path) = entry.enclosed_name() else {
+            continue;
+        };
+        let Ok(relative) = path.strip_prefix(&root) else {
+            continue;
+        };
+        if relative.as_os_str().is_empty() || relative.ends_with(SESSION_LOCK) {
+            continue;
+        }
+        let target = destination.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target).map_err(|err| err.to_string())?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let mut out = File::create(&target).map_err(|err| err.to_string())?;
+        if !copy_capped(&mut entry, &mut out, &mut total, MAX_WORLD_BYTES)
+            .map_err(|err| err.to_string())?
+        {
+            return Err("world is too big".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// unpacks a zipped singleplayer or server world into a new world next to the others
+pub async fn import_world(server_id: usize, name: &str, archive: PathBuf) -> Result<(), String> {
+    if !is_valid_world_name(name) {
+        return Err("invalid world name".to_string());
+    }
+    let destination = game_dir(server_id).join(name);
+    if fs::try_exists(&destination).await.unwrap_or(false) {
+        return Err(format!("a world named {} already exists", name));
+    }
+    // unpack beside it first so a failed upload doesn't leave half a world behind
+    let unpacking = game_dir(server_id).join(format!(".{}.unpacking", name));
+    let _ = fs::remove_dir_all(&unpacking).await;
+    let result = {
+        let unpacking = unpacking.clone();
+        spawn_blocking(move || unpack(&archive, &unpacking))
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|x| x)
+    };
+    if let Err(err) = result {
+        let _ = fs::remove_dir_all(&unpacking).await;
+        return Err(err);
+    }
+    fs::rename(&unpacking, &destination)
+        .await
+        .map_err(|err| format!("failed to move world into place: {}", err))
+}
+
+/// a zip in the server's directory no other request is using, for uploads and downloads
+pub fn temp_archive(server_id: usize, purpose: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    PathBuf::from(format!(
+        "./servers/{}/.{}-{}-{}.zip",
+        server_id,
+        purpose,
+        chrono::Utc::now().timestamp_millis(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// zips a world into the server's directory and returns the archive's path
+pub async fn export_world(server_id: usize, name: &str) -> Result<PathBuf, String> {
+    if !is_valid_world_name(name) {
+        return Err("invalid world name".to_string());
+    }
+    let world = game_dir(server_id).join(name);
+    if !world.join("level.dat").exists() {
+        return Err("world not found".to_string());
+    }
+    let archive = temp_archive(server_id, "export");
+    let name = name.to_string();
+    let destination = archive.clone();
+    spawn_blocking(move || {
+        zip_directory(
+            &world,
+            &destination,
+            Path::new(&name),
+            CompressionMethod::Deflated,
+        )
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| format!("failed to zip world: {}", err))?;
+    Ok(archive)
+}
+
+/// turns off autosave and flushes the world so it can be copied safely, returns false if
+/// the server didn't confirm the save in time. call `resume_saving` afterwards
+pub async fn pause_saving(runner_handle: &ServerRunnerHandle, server_id: usize) -> bool {
+    let mut events = runner_handle.subscribe();
+    runner_handle.issue_command(server_id, "save-off".to_string());
+    runner_handle.issue_command(server_id, "save-all flush".to_string());
+    timeout(SAVE_TIMEOUT, async {
+        loop {
+            match events.recv().await {
+                Ok(event) if event.server_id == server_id && event.event == GameEvent::Saved => {
+                    return true;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    })
+    .await
+    .unwrap_or(false)
+}
+
+pub fn resume_saving(runner_handle: &ServerRunnerHandle, server_id: usize) {
+    runner_handle.issue_command(server_id, "save-on".to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_accepts_plain_world_names() {
+        for name in ["world", "My World", "world_nether-2"] {
+            assert!(is_valid_world_name(name), "{:?}", name);
+        }
+        for name in [
+            "", ".", "..", "../../..", "a/b", "a\\b", ".hidden", "/world",
+        ] {
+            assert!(!is_valid_world_name(name), "{:?}", name);
+        }
+    }
+}